@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use crate::core::{
+	amounts::Amounts,
+	directive::{Directive, DirectiveKind, Posting},
+	error::Diagnostic,
+	inventory::{Inventory, Position},
+	options::{booking_methods, Options},
+	position::{Cost, CostOrSpec, CostSpec},
+	types::{Account, Amount, BookingMethod},
+};
+
+/// Books every transaction, in date order.
+///
+/// Cost specs that add to a position are resolved into a lot, dated on the
+/// transaction unless a date is given. Those that reduce a position are matched
+/// against the lots held in the account, using its booking method, and split
+/// into a posting per lot reduced. Then the posting without an amount, if any,
+/// is filled in with what's needed to balance the transaction, one posting per
+/// commodity.
+///
+/// The directives are returned sorted. Transactions that can't be booked are
+/// left as they are, with a diagnostic.
+pub fn book(
+	mut directives: Vec<Directive>,
+	options: &Options,
+) -> (Vec<Directive>, Vec<Diagnostic>) {
+	directives.sort_by_key(|directive| directive.sort_key());
+	let methods = booking_methods(&directives, options);
+
+	let mut errors = vec![];
+	let mut balances: HashMap<Account, Inventory> = HashMap::new();
+	for directive in &mut directives {
+		let DirectiveKind::Transaction { postings, .. } = &directive.kind else {
+			continue;
+		};
+		let mut booker = Booker {
+			date: directive.date,
+			balances: &balances,
+			changed: HashMap::new(),
+			methods: &methods,
+			options,
+		};
+		match booker.book_postings(postings) {
+			Ok(booked) => {
+				let changed = booker.changed;
+				balances.extend(changed);
+				if let DirectiveKind::Transaction { postings, .. } = &mut directive.kind {
+					*postings = booked;
+				}
+			}
+			Err(message) => errors.push(Diagnostic::at(directive, message)),
+		}
+	}
+	(directives, errors)
+}
+
+/// Books the postings of a single transaction, keeping the inventories it
+/// changes apart until it's fully booked.
+struct Booker<'a> {
+	date: NaiveDate,
+	balances: &'a HashMap<Account, Inventory>,
+	changed: HashMap<Account, Inventory>,
+	methods: &'a HashMap<Account, BookingMethod>,
+	options: &'a Options,
+}
+
+impl Booker<'_> {
+	fn inventory(&mut self, account: &Account) -> &mut Inventory {
+		self.changed
+			.entry(account.clone())
+			.or_insert_with(|| self.balances.get(account).cloned().unwrap_or_default())
+	}
+
+	fn apply(&mut self, posting: &Posting) -> Result<(), String> {
+		if let Some(position) = posting.position() {
+			self.inventory(posting.account())
+				.add_position(&position)
+				.map_err(|e| format!("{} in the balance of {}", e, posting.account()))?;
+		}
+		Ok(())
+	}
+
+	fn book_postings(&mut self, postings: &[Posting]) -> Result<Vec<Posting>, String> {
+		let mut booked = vec![];
+		let mut missing = None;
+		for posting in postings {
+			match (posting.units(), posting.cost()) {
+				(None, Some(_)) => {
+					return Err(format!(
+						"Postings with a cost must have an amount: {}",
+						posting
+					))
+				}
+				(None, None) => {
+					if missing.is_some() {
+						return Err("Only one posting per transaction can be missing its amount"
+							.to_string());
+					}
+					missing = Some(booked.len());
+					booked.push(posting.clone());
+				}
+				(Some(units), Some(CostOrSpec::Spec(spec))) => {
+					for posting in self.book_cost(posting, units, spec)? {
+						self.apply(&posting)?;
+						booked.push(posting);
+					}
+				}
+				(Some(_), _) => {
+					self.apply(posting)?;
+					booked.push(posting.clone());
+				}
+			}
+		}
+
+		if let Some(i) = missing {
+			let weights = booked
+				.iter()
+				.map(Posting::weight)
+				.collect::<Result<Vec<_>, _>>()
+				.and_then(|weights| Amounts::sum(weights.into_iter().flatten()));
+			let residual = weights.map_err(|e| format!("Cannot balance the transaction: {}", e))?;
+			let posting = booked.remove(i);
+			let fills: Vec<Posting> = residual
+				.iter()
+				.map(|amount| {
+					let amount = match written_scale(postings, &amount) {
+						Some(dp) => amount.round_dp(dp),
+						None => amount,
+					};
+					Posting::new(
+						posting.account().clone(),
+						Some(-amount),
+						None,
+						None,
+						posting.flag(),
+						posting.meta.clone(),
+					)
+				})
+				.collect();
+			for fill in &fills {
+				self.apply(fill)?;
+			}
+			booked.splice(i..i, fills);
+		}
+
+		Ok(booked)
+	}
+
+	/// Resolves a cost spec into the lot it creates, or into the lots it
+	/// reduces.
+	fn book_cost(
+		&mut self,
+		posting: &Posting,
+		units: &Amount,
+		spec: &CostSpec,
+	) -> Result<Vec<Posting>, String> {
+		let method = self
+			.methods
+			.get(posting.account())
+			.copied()
+			.unwrap_or(self.options.booking_method);
+		let is_reduction = method != BookingMethod::None
+			&& self
+				.inventory(posting.account())
+				.lots(units.commodity())
+				.any(|lot| {
+					lot.cost().is_some()
+						&& lot.units().number().is_sign_negative()
+							!= units.number().is_sign_negative()
+				});
+
+		let with_cost = |number: Decimal, cost: Cost| {
+			let booked = Posting::new(
+				posting.account().clone(),
+				Some(Amount::new(number, units.commodity().clone())),
+				Some(CostOrSpec::Cost(cost)),
+				posting.price().cloned(),
+				posting.flag(),
+				posting.meta.clone(),
+			);
+			match posting.total_price() {
+				// The total price only holds while the posting isn't split across lots
+				Some(total) if number == units.number() => booked.with_total_price(total.clone()),
+				_ => booked,
+			}
+		};
+
+		if !is_reduction {
+			let number = match (spec.number_per(), spec.number_total()) {
+				(per, Some(total)) if !units.is_zero() => {
+					per.unwrap_or_default() + total / units.number().abs()
+				}
+				(Some(per), _) => per,
+				_ => return Err(format!("Missing the cost of the lot for {}", posting)),
+			};
+			let commodity = spec
+				.commodity()
+				.ok_or_else(|| format!("Missing the cost commodity for {}", posting))?;
+			let cost = Cost::new(
+				number,
+				commodity.clone(),
+				spec.date().unwrap_or(self.date),
+				spec.label().map(Rc::from),
+			);
+			return Ok(vec![with_cost(units.number(), cost)]);
+		}
+
+		let inventory = self.inventory(posting.account()).clone();
+		let mut lots: Vec<&Position> = inventory
+			.lots(units.commodity())
+			.filter(|lot| {
+				lot.units().number().is_sign_negative() != units.number().is_sign_negative()
+					&& lot.cost().is_some_and(|cost| matches_spec(cost, spec))
+			})
+			.collect();
+		if lots.is_empty() {
+			return Err(format!(
+				"No position matches {} against inventory {}",
+				posting, inventory
+			));
+		}
+
+		let wanted = units.number().abs();
+		let held: Decimal = lots.iter().map(|lot| lot.units().number().abs()).sum();
+		match method {
+			BookingMethod::Strict | BookingMethod::StrictWithSize if lots.len() > 1 => {
+				let exact = lots
+					.iter()
+					.position(|lot| lot.units().number().abs() == wanted);
+				match exact {
+					Some(i) if method == BookingMethod::StrictWithSize => lots = vec![lots[i]],
+					// Reducing all the matching lots isn't ambiguous
+					_ if held == wanted => {}
+					_ => {
+						return Err(format!(
+							"Ambiguous matches for {} against inventory {}",
+							posting, inventory
+						))
+					}
+				}
+			}
+			BookingMethod::FirstInFirstout => {
+				lots.sort_by_key(|lot| lot.cost().map(Cost::date));
+			}
+			BookingMethod::LastInFirstOut => {
+				lots.sort_by_key(|lot| std::cmp::Reverse(lot.cost().map(Cost::date)));
+			}
+			BookingMethod::HighestInFirstOut => {
+				lots.sort_by_key(|lot| std::cmp::Reverse(lot.cost().map(Cost::number)));
+			}
+			BookingMethod::Average => {
+				return Err("The AVERAGE booking method is not supported".to_string())
+			}
+			_ => {}
+		}
+
+		let mut remaining = wanted;
+		let mut reductions = vec![];
+		for lot in lots {
+			if remaining.is_zero() {
+				break;
+			}
+			let number = remaining.min(lot.units().number().abs());
+			remaining -= number;
+			let number = if units.is_negative() { -number } else { number };
+			reductions.push(with_cost(number, lot.cost().unwrap().clone()));
+		}
+		if !remaining.is_zero() {
+			return Err(format!(
+				"Not enough lots to reduce {} against inventory {}",
+				posting, inventory
+			));
+		}
+		Ok(reductions)
+	}
+}
+
+/// Checks if a lot matches everything given in a cost spec.
+fn matches_spec(cost: &Cost, spec: &CostSpec) -> bool {
+	spec.number_per()
+		.is_none_or(|number| number == cost.number())
+		&& spec.commodity().is_none_or(|c| c == cost.commodity())
+		&& spec.date().is_none_or(|date| date == cost.date())
+		&& spec.label().is_none_or(|label| Some(label) == cost.label())
+}
+
+/// Returns the largest number of fractional digits written for a commodity in
+/// the units of the postings.
+fn written_scale(postings: &[Posting], amount: &Amount) -> Option<u32> {
+	postings
+		.iter()
+		.filter_map(Posting::units)
+		.filter(|units| units.commodity() == amount.commodity())
+		.map(|units| units.number().scale())
+		.max()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::parser::{parse_str, Statement};
+
+	/// Books the directives of a source with the default options.
+	fn book_str(src: &str) -> (Vec<Directive>, Vec<Diagnostic>) {
+		let (statements, errors) = parse_str(Rc::from("test"), src);
+		assert!(errors.is_empty(), "{:?}", errors);
+		let directives = statements
+			.into_iter()
+			.filter_map(|statement| match statement {
+				Statement::Directive(directive) => Some(directive),
+				_ => None,
+			})
+			.collect();
+		book(directives, &Options::default())
+	}
+
+	#[test]
+	fn test_interpolation() {
+		let (directives, errors) = book_str(
+			r#"
+			2025-01-01 * "Two commodities"
+				Assets:Cash -10.00 USD
+				Assets:Cash -5 CAD
+				Expenses:Food
+			2025-01-02 * "At a price"
+				Assets:Cash 100.00 CAD @ 0.753 USD
+				Assets:Bank -5.00 USD
+				Assets:Checking
+			"#,
+		);
+		assert!(errors.is_empty(), "{:?}", errors);
+
+		let printed: Vec<String> = directives.iter().map(|d| d.to_string()).collect();
+		assert_eq!(
+			printed,
+			vec![
+				"2025-01-01 * \"Two commodities\"\n  Assets:Cash  -10.00 USD\n  Assets:Cash  -5 CAD\n  Expenses:Food  5 CAD\n  Expenses:Food  10.00 USD",
+				"2025-01-02 * \"At a price\"\n  Assets:Cash  100.00 CAD @ 0.753 USD\n  Assets:Bank  -5.00 USD\n  Assets:Checking  -70.30 USD",
+			]
+		);
+	}
+
+	#[test]
+	fn test_lot_booking() {
+		let (directives, errors) = book_str(
+			r#"
+			2025-01-01 open Assets:Fifo "FIFO"
+			2025-01-01 open Assets:Strict
+			2025-01-02 * "Buy"
+				Assets:Fifo 10 HOOL {500.00 USD}
+				Assets:Strict 10 HOOL {{5000.00 USD}}
+				Assets:Cash
+			2025-01-03 * "Buy"
+				Assets:Fifo 10 HOOL {510.00 USD}
+				Assets:Strict 10 HOOL {510.00 USD, "second"}
+				Assets:Cash
+			2025-01-04 * "Sell"
+				Assets:Fifo -15 HOOL {} @ 520.00 USD
+				Assets:Cash 7800.00 USD
+				Income:Gains
+			2025-01-05 * "Sell the labelled lot"
+				Assets:Strict -5 HOOL {"second"}
+				Assets:Cash
+			2025-01-06 * "Ambiguous"
+				Assets:Strict -5 HOOL {}
+				Assets:Cash
+			"#,
+		);
+
+		assert_eq!(errors.len(), 1, "{:?}", errors);
+		assert!(errors[0].message.starts_with("Ambiguous matches"));
+
+		let postings: Vec<String> = directives
+			.iter()
+			.filter(|d| d.date.to_string() == "2025-01-04")
+			.map(|d| d.to_string())
+			.collect();
+		assert_eq!(
+			postings,
+			vec![
+				"2025-01-04 * \"Sell\"\n  Assets:Fifo  -10 HOOL {500.00 USD, 2025-01-02} @ 520.00 USD\n  Assets:Fifo  -5 HOOL {510.00 USD, 2025-01-03} @ 520.00 USD\n  Assets:Cash  7800.00 USD\n  Income:Gains  -250.00 USD"
+			]
+		);
+
+		let strict = directives
+			.iter()
+			.find(|d| d.date.to_string() == "2025-01-05")
+			.unwrap();
+		assert!(strict
+			.to_string()
+			.contains("Assets:Strict  -5 HOOL {510.00 USD, 2025-01-03, \"second\"}"));
+	}
+
+	#[test]
+	fn test_overflowing_balance() {
+		let (directives, errors) = book_str(
+			r#"
+			2025-01-01 * "Deposit"
+				Assets:Cash 50000000000000000000000000000 USD
+				Equity:Opening
+			2025-01-02 * "Deposit again"
+				Assets:Cash 50000000000000000000000000000 USD
+				Equity:Opening
+			"#,
+		);
+
+		let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			errors,
+			vec!["test:5: Arithmetic overflow in the balance of Assets:Cash"]
+		);
+		assert_eq!(directives.len(), 2);
+	}
+}
@@ -0,0 +1,203 @@
+use std::{borrow::Borrow, collections::BTreeMap, fmt::Display, ops::Neg};
+
+use rust_decimal::Decimal;
+
+use super::{
+	error::{BeanError, Result},
+	tolerance::Tolerances,
+	types::{Amount, Commodity},
+};
+
+/// A sum of amounts of any number of commodities. Unlike adding [`Amount`]s,
+/// accumulating amounts of different commodities doesn't fail, each commodity
+/// is summed on its own. Only a sum that overflows does.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Amounts(BTreeMap<Commodity, Decimal>);
+
+impl Amounts {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Returns the sum for a commodity, zero if there is none.
+	pub fn get(&self, commodity: &Commodity) -> Decimal {
+		self.0.get(commodity).copied().unwrap_or_default()
+	}
+
+	/// Sums amounts of any commodities.
+	pub fn sum<A: Borrow<Amount>>(amounts: impl IntoIterator<Item = A>) -> Result<Amounts> {
+		let mut sum = Self::new();
+		for amount in amounts {
+			sum.add_amount(amount.borrow())?;
+		}
+		Ok(sum)
+	}
+
+	/// Adds a number of a commodity. Commodities that sum to zero are removed.
+	/// Fails if the sum overflows, leaving it unchanged.
+	pub fn add_number(&mut self, number: Decimal, commodity: &Commodity) -> Result<()> {
+		let sum = self
+			.get(commodity)
+			.checked_add(number)
+			.ok_or(BeanError::Overflow)?;
+		if sum.is_zero() {
+			self.0.remove(commodity);
+		} else {
+			self.0.insert(commodity.clone(), sum);
+		}
+		Ok(())
+	}
+
+	pub fn add_amount(&mut self, amount: &Amount) -> Result<()> {
+		self.add_number(amount.number(), amount.commodity())
+	}
+
+	pub fn sub_amount(&mut self, amount: &Amount) -> Result<()> {
+		self.add_number(-amount.number(), amount.commodity())
+	}
+
+	/// Adds the sums of other amounts. Fails if any sum overflows, leaving
+	/// them all unchanged.
+	pub fn add_amounts(&mut self, other: &Amounts) -> Result<()> {
+		let mut sum = self.clone();
+		other
+			.iter()
+			.try_for_each(|amount| sum.add_amount(&amount))?;
+		*self = sum;
+		Ok(())
+	}
+
+	pub fn sub_amounts(&mut self, other: &Amounts) -> Result<()> {
+		self.add_amounts(&-other.clone())
+	}
+
+	/// Multiplies every sum by a number.
+	pub fn checked_mul(&self, number: Decimal) -> Result<Amounts> {
+		Self::sum(
+			self.iter()
+				.map(|amount| amount.checked_mul(number))
+				.collect::<Result<Vec<_>>>()?,
+		)
+	}
+
+	/// Checks if there are no non-zero sums.
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	/// Returns the number of commodities with a non-zero sum.
+	pub fn len(&self) -> usize {
+		self.0.len()
+	}
+
+	pub fn commodities(&self) -> impl Iterator<Item = &Commodity> {
+		self.0.keys()
+	}
+
+	/// Returns the sums as amounts, sorted by commodity.
+	pub fn iter(&self) -> impl Iterator<Item = Amount> + '_ {
+		self.0
+			.iter()
+			.map(|(commodity, number)| Amount::new(*number, commodity.clone()))
+	}
+
+	/// Returns the single amount if there is exactly one commodity.
+	pub fn single(&self) -> Option<Amount> {
+		match self.len() {
+			1 => self.iter().next(),
+			_ => None,
+		}
+	}
+
+	/// Checks if all the sums are within the tolerances of their commodity.
+	pub fn is_negligible(&self, tolerances: &Tolerances) -> bool {
+		self.iter().all(|amount| tolerances.is_negligible(&amount))
+	}
+
+	/// Rounds every sum to a number of fractional digits. Sums that round to
+	/// zero are removed.
+	pub fn round_dp(&self, dp: u32) -> Amounts {
+		Self(
+			self.iter()
+				.map(|amount| amount.round_dp(dp))
+				.filter(|amount| !amount.is_zero())
+				.map(|amount| (amount.commodity().clone(), amount.number()))
+				.collect(),
+		)
+	}
+}
+
+impl Neg for Amounts {
+	type Output = Amounts;
+
+	fn neg(self) -> Amounts {
+		Self(self.0.into_iter().map(|(c, n)| (c, -n)).collect())
+	}
+}
+
+impl From<Amount> for Amounts {
+	fn from(amount: Amount) -> Self {
+		let mut amounts = Self::new();
+		if !amount.is_zero() {
+			amounts
+				.0
+				.insert(amount.commodity().clone(), amount.number());
+		}
+		amounts
+	}
+}
+
+impl Display for Amounts {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let amounts: Vec<String> = self.iter().map(|amount| amount.to_string()).collect();
+		write!(f, "({})", amounts.join(", "))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::core::types::amount;
+
+	#[test]
+	fn test_amounts() {
+		let usd: Commodity = "USD".parse().unwrap();
+
+		let mut amounts = Amounts::sum([
+			amount("10.00", "USD"),
+			amount("5", "CAD"),
+			amount("-2.50", "USD"),
+		])
+		.unwrap();
+		assert_eq!(amounts.len(), 2);
+		assert_eq!(amounts.get(&usd), Decimal::from_str("7.50").unwrap());
+		assert_eq!(amounts.to_string(), "(5 CAD, 7.50 USD)");
+
+		amounts.sub_amount(&amount("5", "CAD")).unwrap();
+		assert_eq!(amounts.single(), Some(amount("7.50", "USD")));
+
+		let negated = -amounts.clone();
+		amounts.add_amounts(&negated).unwrap();
+		assert!(amounts.is_empty());
+	}
+
+	#[test]
+	fn test_amounts_overflow() {
+		let mut amounts = Amounts::from(amount("50000000000000000000000000000", "USD"));
+		assert!(matches!(
+			amounts.add_amount(&amount("50000000000000000000000000000", "USD")),
+			Err(BeanError::Overflow)
+		));
+		assert_eq!(
+			amounts.single(),
+			Some(amount("50000000000000000000000000000", "USD"))
+		);
+		assert!(amounts.add_amount(&amount("1", "CAD")).is_ok());
+		assert!(matches!(
+			amounts.checked_mul(Decimal::TWO),
+			Err(BeanError::Overflow)
+		));
+	}
+}
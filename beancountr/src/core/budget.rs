@@ -0,0 +1,190 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+
+use super::{
+	custom::{CustomSchema, ValueType},
+	directive::Directive,
+	error::{BeanError, Diagnostic, Result},
+	types::{Account, Amount},
+};
+
+/// The type of the custom directives budgets are written with.
+pub const BUDGET: &str = "budget";
+
+/// How often a budgeted amount is available, and the periods of a report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BudgetPeriod {
+	Daily,
+	Weekly,
+	#[default]
+	Monthly,
+	Quarterly,
+	Yearly,
+}
+
+impl BudgetPeriod {
+	/// Returns the first day of the period containing a date. Weeks start on
+	/// Mondays.
+	pub fn start(self, date: NaiveDate) -> NaiveDate {
+		let first_month = match self {
+			Self::Daily => return date,
+			Self::Weekly => return date.week(Weekday::Mon).first_day(),
+			Self::Monthly => date.month(),
+			Self::Quarterly => date.month0() / 3 * 3 + 1,
+			Self::Yearly => 1,
+		};
+		NaiveDate::from_ymd_opt(date.year(), first_month, 1).unwrap()
+	}
+
+	/// Returns the first day of the period following the one starting on a
+	/// date.
+	pub fn next(self, start: NaiveDate) -> NaiveDate {
+		match self {
+			Self::Daily => start + Days::new(1),
+			Self::Weekly => start + Days::new(7),
+			Self::Monthly => start + Months::new(1),
+			Self::Quarterly => start + Months::new(3),
+			Self::Yearly => start + Months::new(12),
+		}
+	}
+
+	/// Returns the number of days in the period containing a date.
+	pub(crate) fn days(self, date: NaiveDate) -> i64 {
+		let start = self.start(date);
+		(self.next(start) - start).num_days()
+	}
+}
+
+impl FromStr for BudgetPeriod {
+	type Err = BeanError;
+
+	fn from_str(s: &str) -> Result<Self> {
+		match s.to_lowercase().as_str() {
+			"daily" => Ok(Self::Daily),
+			"weekly" => Ok(Self::Weekly),
+			"monthly" => Ok(Self::Monthly),
+			"quarterly" => Ok(Self::Quarterly),
+			"yearly" => Ok(Self::Yearly),
+			_ => Err(BeanError::InvalidPeriod(s.to_string())),
+		}
+	}
+}
+
+impl Display for BudgetPeriod {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::Daily => "daily",
+			Self::Weekly => "weekly",
+			Self::Monthly => "monthly",
+			Self::Quarterly => "quarterly",
+			Self::Yearly => "yearly",
+		})
+	}
+}
+
+/// An amount budgeted for an account every period, from its date until
+/// another budget of the account in the same commodity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Budget {
+	pub date: NaiveDate,
+	pub account: Account,
+	pub period: BudgetPeriod,
+	pub amount: Amount,
+}
+
+/// The schema of the budgets, which also checks their period.
+pub fn budget_schema() -> CustomSchema {
+	CustomSchema::new(vec![
+		ValueType::Account,
+		ValueType::String,
+		ValueType::Amount,
+	])
+	.with_check(
+		|values| match values.get_str(1).map(BudgetPeriod::from_str) {
+			Some(Err(e)) => Err(e.to_string()),
+			_ => Ok(()),
+		},
+	)
+}
+
+/// Reads the budgets written the way Fava does, like
+/// `custom "budget" Expenses:Food "monthly" 500.00 USD`, reporting the ones
+/// with an invalid period.
+pub fn budgets(directives: &[Directive]) -> (Vec<Budget>, Vec<Diagnostic>) {
+	let mut budgets = vec![];
+	let mut errors = vec![];
+	for directive in directives {
+		let Some(values) = directive.custom(BUDGET) else {
+			continue;
+		};
+		let (Some(account), Some(period), Some(amount)) = (
+			values.get_account(0),
+			values.get_str(1),
+			values.get_amount(2),
+		) else {
+			continue;
+		};
+		match period.parse() {
+			Ok(period) => budgets.push(Budget {
+				date: directive.date,
+				account: account.clone(),
+				period,
+				amount: amount.clone(),
+			}),
+			Err(e) => errors.push(Diagnostic::at(directive, e.to_string())),
+		}
+	}
+	(budgets, errors)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_budgets() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			2025-01-01 open Expenses:Food
+			2025-01-01 custom "budget" Expenses:Food "Monthly" 500.00 USD
+			2025-02-15 custom "budget" Expenses:Food "weekly" 140.00 USD
+			2025-03-01 custom "budget" Expenses:Food "biweekly" 300.00 USD
+			"#,
+		);
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			errors,
+			vec!["test:5: Invalid custom \"budget\" directive: Invalid period: biweekly"]
+		);
+
+		let (budgets, errors) = budgets(&ledger.directives);
+		let budgets: Vec<String> = budgets
+			.iter()
+			.map(|b| format!("{} {} {} {}", b.date, b.account, b.period, b.amount))
+			.collect();
+		assert_eq!(
+			budgets,
+			vec![
+				"2025-01-01 Expenses:Food monthly 500.00 USD",
+				"2025-02-15 Expenses:Food weekly 140.00 USD",
+			]
+		);
+		let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(errors, vec!["test:5: Invalid period: biweekly"]);
+	}
+
+	#[test]
+	fn test_budget_periods() {
+		let date = |m, d| NaiveDate::from_ymd_opt(2025, m, d).unwrap();
+		assert_eq!(BudgetPeriod::Weekly.start(date(2, 20)), date(2, 17));
+		assert_eq!(BudgetPeriod::Quarterly.start(date(5, 20)), date(4, 1));
+		assert_eq!(BudgetPeriod::Monthly.next(date(1, 1)), date(2, 1));
+		assert_eq!(BudgetPeriod::Yearly.days(date(2, 20)), 365);
+	}
+}
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use super::{
+	directive::{Directive, DirectiveKind, Metadata},
+	error::Diagnostic,
+	types::{Account, Amount, Commodity},
+};
+
+/// The type of a value of a custom directive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+	String,
+	Account,
+	Date,
+	Commodity,
+	Tag,
+	Link,
+	Bool,
+	Number,
+	Amount,
+}
+
+impl ValueType {
+	/// Returns the type of a value, or `None` for a null value.
+	pub fn of(value: &Metadata) -> Option<Self> {
+		match value {
+			Metadata::String(_) => Some(Self::String),
+			Metadata::Account(_) => Some(Self::Account),
+			Metadata::Date(_) => Some(Self::Date),
+			Metadata::Commodity(_) => Some(Self::Commodity),
+			Metadata::Tags(_) => Some(Self::Tag),
+			Metadata::Link(_) => Some(Self::Link),
+			Metadata::Bool(_) => Some(Self::Bool),
+			Metadata::None => None,
+			Metadata::Number(_) => Some(Self::Number),
+			Metadata::Amount(_) => Some(Self::Amount),
+		}
+	}
+}
+
+impl Display for ValueType {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::String => "a string",
+			Self::Account => "an account",
+			Self::Date => "a date",
+			Self::Commodity => "a commodity",
+			Self::Tag => "a tag",
+			Self::Link => "a link",
+			Self::Bool => "a boolean",
+			Self::Number => "a number",
+			Self::Amount => "an amount",
+		})
+	}
+}
+
+/// A check of the values of a custom directive beyond their types, run once
+/// they have the types of the schema.
+pub type ValueCheck = fn(CustomValues) -> Result<(), String>;
+
+/// The values a type of custom directives expects, in order. Optional values
+/// can only follow the required ones.
+#[derive(Debug, Clone)]
+pub struct CustomSchema {
+	required: Vec<ValueType>,
+	optional: Vec<ValueType>,
+	check: Option<ValueCheck>,
+}
+
+impl CustomSchema {
+	pub fn new(required: Vec<ValueType>) -> Self {
+		Self {
+			required,
+			optional: vec![],
+			check: None,
+		}
+	}
+
+	/// Sets the values that can follow the required ones.
+	pub fn with_optional(mut self, optional: Vec<ValueType>) -> Self {
+		self.optional = optional;
+		self
+	}
+
+	/// Sets a check of the values themselves, like the range of a number.
+	pub fn with_check(mut self, check: ValueCheck) -> Self {
+		self.check = Some(check);
+		self
+	}
+
+	/// Checks the number and types of the values of a custom directive.
+	pub fn check(&self, values: &[Metadata]) -> Result<(), String> {
+		let max = self.required.len() + self.optional.len();
+		if values.len() < self.required.len() || values.len() > max {
+			let expected = match self.optional.is_empty() {
+				true => self.required.len().to_string(),
+				false => format!("{} to {}", self.required.len(), max),
+			};
+			return Err(format!(
+				"expected {} values, found {}",
+				expected,
+				values.len()
+			));
+		}
+		let expected = self.required.iter().chain(&self.optional);
+		for (i, (value, expected)) in values.iter().zip(expected).enumerate() {
+			if ValueType::of(value) != Some(*expected) {
+				let found = match ValueType::of(value) {
+					Some(found) => found.to_string(),
+					None => "null".to_string(),
+				};
+				return Err(format!(
+					"value {} should be {}, found {}",
+					i + 1,
+					expected,
+					found
+				));
+			}
+		}
+		match self.check {
+			Some(check) => check(CustomValues::new(values)),
+			None => Ok(()),
+		}
+	}
+}
+
+/// The schemas of the types of custom directives, by name. Custom directives
+/// of other types aren't checked.
+#[derive(Debug, Clone, Default)]
+pub struct CustomSchemas {
+	schemas: HashMap<String, CustomSchema>,
+}
+
+impl CustomSchemas {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Registers the schema of a type, replacing any for the same type.
+	pub fn register(&mut self, kind: impl Into<String>, schema: CustomSchema) {
+		self.schemas.insert(kind.into(), schema);
+	}
+
+	pub fn get(&self, kind: &str) -> Option<&CustomSchema> {
+		self.schemas.get(kind)
+	}
+
+	/// Checks the custom directives of the registered types.
+	pub fn validate(&self, directives: &[Directive]) -> Vec<Diagnostic> {
+		directives
+			.iter()
+			.filter_map(|directive| {
+				let DirectiveKind::Custom { kind, values } = &directive.kind else {
+					return None;
+				};
+				let e = self.get(kind)?.check(values).err()?;
+				Some(Diagnostic::at(
+					directive,
+					format!("Invalid custom \"{}\" directive: {}", kind, e),
+				))
+			})
+			.collect()
+	}
+}
+
+/// The values of a custom directive, with accessors by position for each
+/// type. They return `None` if the value is missing or of another type, which
+/// can't happen for the values required by a registered schema.
+#[derive(Debug, Clone, Copy)]
+pub struct CustomValues<'a>(&'a [Metadata]);
+
+impl<'a> CustomValues<'a> {
+	pub fn new(values: &'a [Metadata]) -> Self {
+		Self(values)
+	}
+
+	pub fn len(&self) -> usize {
+		self.0.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	pub fn get(&self, i: usize) -> Option<&'a Metadata> {
+		self.0.get(i)
+	}
+
+	pub fn get_str(&self, i: usize) -> Option<&'a str> {
+		self.get(i).and_then(Metadata::as_str)
+	}
+
+	pub fn get_account(&self, i: usize) -> Option<&'a Account> {
+		self.get(i).and_then(Metadata::as_account)
+	}
+
+	pub fn get_date(&self, i: usize) -> Option<NaiveDate> {
+		self.get(i).and_then(Metadata::as_date)
+	}
+
+	pub fn get_commodity(&self, i: usize) -> Option<&'a Commodity> {
+		self.get(i).and_then(Metadata::as_commodity)
+	}
+
+	pub fn get_tag(&self, i: usize) -> Option<&'a str> {
+		self.get(i).and_then(Metadata::as_tag)
+	}
+
+	pub fn get_link(&self, i: usize) -> Option<&'a str> {
+		self.get(i).and_then(Metadata::as_link)
+	}
+
+	pub fn get_bool(&self, i: usize) -> Option<bool> {
+		self.get(i).and_then(Metadata::as_bool)
+	}
+
+	pub fn get_number(&self, i: usize) -> Option<Decimal> {
+		self.get(i).and_then(Metadata::as_number)
+	}
+
+	pub fn get_amount(&self, i: usize) -> Option<&'a Amount> {
+		self.get(i).and_then(Metadata::as_amount)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::{CustomSchema, ValueType};
+	use crate::loader::load_str_with;
+	use crate::plugins::PluginRegistry;
+
+	#[test]
+	fn test_custom_schemas() {
+		let mut registry = PluginRegistry::new();
+		registry.register_custom(
+			"budget",
+			CustomSchema::new(vec![
+				ValueType::Account,
+				ValueType::String,
+				ValueType::Amount,
+			]),
+		);
+		registry.register_custom(
+			"tracked",
+			CustomSchema::new(vec![ValueType::Commodity]).with_optional(vec![ValueType::Bool]),
+		);
+		let ledger = load_str_with(
+			Rc::from("test"),
+			r#"
+			2025-01-01 custom "budget" Expenses:Food "monthly" 400.00 USD
+			2025-01-01 custom "budget" Expenses:Food "monthly"
+			2025-01-01 custom "budget" Expenses:Food 400.00 USD "monthly"
+			2025-01-01 custom "tracked" HOOL
+			2025-01-01 custom "tracked" HOOL TRUE "daily"
+			2025-01-01 custom "fava-option" "language" "fr"
+			"#,
+			&registry,
+		);
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			errors,
+			vec![
+				"test:3: Invalid custom \"budget\" directive: expected 3 values, found 2",
+				"test:4: Invalid custom \"budget\" directive: value 2 should be a string, found an amount",
+				"test:6: Invalid custom \"tracked\" directive: expected 1 to 2 values, found 3",
+			]
+		);
+
+		let budget = ledger.directives[0].custom("budget").unwrap();
+		assert_eq!(budget.get_account(0).unwrap().as_str(), "Expenses:Food");
+		assert_eq!(budget.get_str(1), Some("monthly"));
+		assert_eq!(budget.get_amount(2).unwrap().to_string(), "400.00 USD");
+		assert!(ledger.directives[0].custom("tracked").is_none());
+	}
+}
@@ -1,9 +1,16 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::ops::Range;
+use std::rc::Rc;
 
 use chrono::NaiveDate;
+use indexmap::IndexMap;
 use rust_decimal::Decimal;
 
 use super::{
+	custom::CustomValues,
+	error::Result,
+	inventory::Position,
 	position::CostOrSpec,
 	types::{Account, Amount, BookingMethod, Commodity},
 };
@@ -15,13 +22,227 @@ pub enum Metadata {
 	Date(NaiveDate),
 	Commodity(Commodity),
 	Tags(String),
+	Link(String),
 	Bool(bool),
 	None,
 	Number(Decimal),
 	Amount(Amount),
 }
 
-pub type MetadataMap = HashMap<String, Metadata>;
+impl Metadata {
+	pub fn as_str(&self) -> Option<&str> {
+		match self {
+			Self::String(s) => Some(s),
+			_ => None,
+		}
+	}
+
+	pub fn as_account(&self) -> Option<&Account> {
+		match self {
+			Self::Account(account) => Some(account),
+			_ => None,
+		}
+	}
+
+	pub fn as_date(&self) -> Option<NaiveDate> {
+		match self {
+			Self::Date(date) => Some(*date),
+			_ => None,
+		}
+	}
+
+	pub fn as_commodity(&self) -> Option<&Commodity> {
+		match self {
+			Self::Commodity(commodity) => Some(commodity),
+			_ => None,
+		}
+	}
+
+	pub fn as_tag(&self) -> Option<&str> {
+		match self {
+			Self::Tags(tag) => Some(tag),
+			_ => None,
+		}
+	}
+
+	pub fn as_link(&self) -> Option<&str> {
+		match self {
+			Self::Link(link) => Some(link),
+			_ => None,
+		}
+	}
+
+	pub fn as_bool(&self) -> Option<bool> {
+		match self {
+			Self::Bool(b) => Some(*b),
+			_ => None,
+		}
+	}
+
+	pub fn as_number(&self) -> Option<Decimal> {
+		match self {
+			Self::Number(number) => Some(*number),
+			_ => None,
+		}
+	}
+
+	pub fn as_amount(&self) -> Option<&Amount> {
+		match self {
+			Self::Amount(amount) => Some(amount),
+			_ => None,
+		}
+	}
+}
+
+/// Renders the value the way it's written in the input.
+impl Display for Metadata {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::String(s) => write!(f, "{:?}", s),
+			Self::Account(account) => write!(f, "{}", account),
+			Self::Date(date) => write!(f, "{}", date),
+			Self::Commodity(commodity) => write!(f, "{}", commodity),
+			Self::Tags(tag) => write!(f, "#{}", tag),
+			Self::Link(link) => write!(f, "^{}", link),
+			Self::Bool(b) => f.write_str(if *b { "TRUE" } else { "FALSE" }),
+			Self::None => f.write_str("NULL"),
+			Self::Number(number) => write!(f, "{}", number),
+			Self::Amount(amount) => write!(f, "{}", amount),
+		}
+	}
+}
+
+/// Metadata key-value pairs, kept in the order they were written.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetadataMap(IndexMap<String, Metadata>);
+
+impl MetadataMap {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Sets the value of a key. A key that is set again keeps its position.
+	pub fn insert(&mut self, key: impl Into<String>, value: Metadata) -> Option<Metadata> {
+		self.0.insert(key.into(), value)
+	}
+
+	pub fn get(&self, key: &str) -> Option<&Metadata> {
+		self.0.get(key)
+	}
+
+	pub fn contains_key(&self, key: &str) -> bool {
+		self.0.contains_key(key)
+	}
+
+	/// Removes a key, keeping the order of the others.
+	pub fn remove(&mut self, key: &str) -> Option<Metadata> {
+		self.0.shift_remove(key)
+	}
+
+	pub fn len(&self) -> usize {
+		self.0.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (&String, &Metadata)> {
+		self.0.iter()
+	}
+
+	pub fn keys(&self) -> impl Iterator<Item = &String> {
+		self.0.keys()
+	}
+
+	pub fn get_str(&self, key: &str) -> Option<&str> {
+		self.get(key).and_then(Metadata::as_str)
+	}
+
+	pub fn get_account(&self, key: &str) -> Option<&Account> {
+		self.get(key).and_then(Metadata::as_account)
+	}
+
+	pub fn get_date(&self, key: &str) -> Option<NaiveDate> {
+		self.get(key).and_then(Metadata::as_date)
+	}
+
+	pub fn get_commodity(&self, key: &str) -> Option<&Commodity> {
+		self.get(key).and_then(Metadata::as_commodity)
+	}
+
+	pub fn get_tag(&self, key: &str) -> Option<&str> {
+		self.get(key).and_then(Metadata::as_tag)
+	}
+
+	pub fn get_link(&self, key: &str) -> Option<&str> {
+		self.get(key).and_then(Metadata::as_link)
+	}
+
+	pub fn get_bool(&self, key: &str) -> Option<bool> {
+		self.get(key).and_then(Metadata::as_bool)
+	}
+
+	pub fn get_number(&self, key: &str) -> Option<Decimal> {
+		self.get(key).and_then(Metadata::as_number)
+	}
+
+	pub fn get_amount(&self, key: &str) -> Option<&Amount> {
+		self.get(key).and_then(Metadata::as_amount)
+	}
+}
+
+impl<K: Into<String>> FromIterator<(K, Metadata)> for MetadataMap {
+	fn from_iter<T: IntoIterator<Item = (K, Metadata)>>(iter: T) -> Self {
+		Self(iter.into_iter().map(|(k, v)| (k.into(), v)).collect())
+	}
+}
+
+impl<K: Into<String>, const N: usize> From<[(K, Metadata); N]> for MetadataMap {
+	fn from(pairs: [(K, Metadata); N]) -> Self {
+		pairs.into_iter().collect()
+	}
+}
+
+impl<K: Into<String>> Extend<(K, Metadata)> for MetadataMap {
+	fn extend<T: IntoIterator<Item = (K, Metadata)>>(&mut self, iter: T) {
+		self.0.extend(iter.into_iter().map(|(k, v)| (k.into(), v)));
+	}
+}
+
+impl IntoIterator for MetadataMap {
+	type Item = (String, Metadata);
+	type IntoIter = indexmap::map::IntoIter<String, Metadata>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.0.into_iter()
+	}
+}
+
+impl<'a> IntoIterator for &'a MetadataMap {
+	type Item = (&'a String, &'a Metadata);
+	type IntoIter = indexmap::map::Iter<'a, String, Metadata>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.0.iter()
+	}
+}
+
+/// Where a directive was written in the input.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceLocation {
+	pub filename: Rc<str>,
+	/// The line the directive starts on, starting at 1.
+	pub lineno: usize,
+	/// The span of the directive in characters, not bytes.
+	pub span: Range<usize>,
+}
+
+impl Display for SourceLocation {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}:{}", self.filename, self.lineno)
+	}
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Posting {
@@ -29,6 +250,9 @@ pub struct Posting {
 	units: Option<Amount>,
 	cost: Option<CostOrSpec>,
 	price: Option<Amount>,
+	/// The total price as written with `@@`, `price` then holds the per-unit
+	/// price derived from it.
+	total_price: Option<Amount>,
 	flag: Option<char>,
 	pub meta: MetadataMap,
 }
@@ -47,17 +271,127 @@ impl Posting {
 			units,
 			cost,
 			price,
+			total_price: None,
 			flag,
 			meta,
 		}
 	}
+
+	/// Sets the price from a total price written with `@@`. The per-unit price
+	/// is derived from it, and the total is kept so the weight is exact.
+	pub fn with_total_price(mut self, total: Amount) -> Self {
+		self.price = Some(match &self.units {
+			Some(units) if !units.is_zero() => Amount::new(
+				total.number() / units.number().abs(),
+				total.commodity().clone(),
+			),
+			_ => total.clone(),
+		});
+		self.total_price = Some(total);
+		self
+	}
+
+	pub fn account(&self) -> &Account {
+		&self.account
+	}
+
+	pub fn units(&self) -> Option<&Amount> {
+		self.units.as_ref()
+	}
+
+	pub fn cost(&self) -> Option<&CostOrSpec> {
+		self.cost.as_ref()
+	}
+
+	pub fn price(&self) -> Option<&Amount> {
+		self.price.as_ref()
+	}
+
+	/// Returns the total price if it was written with `@@`.
+	pub fn total_price(&self) -> Option<&Amount> {
+		self.total_price.as_ref()
+	}
+
+	pub fn flag(&self) -> Option<char> {
+		self.flag
+	}
+
+	/// Returns the units along with the lot they are held at, once booked.
+	/// Postings that still have a cost spec are returned without a cost.
+	pub fn position(&self) -> Option<Position> {
+		let units = self.units.clone()?;
+		let cost = match &self.cost {
+			Some(CostOrSpec::Cost(cost)) => Some(cost.clone()),
+			_ => None,
+		};
+		Some(Position::new(units, cost))
+	}
+
+	/// Returns the amount this posting contributes to the balance of its
+	/// transaction, that is the units at cost, or at price if there is no cost.
+	/// Returns `None` if the units or the cost aren't known, and fails if the
+	/// weight overflows.
+	pub fn weight(&self) -> Result<Option<Amount>> {
+		let Some(units) = &self.units else {
+			return Ok(None);
+		};
+		match (&self.cost, &self.price, &self.total_price) {
+			(Some(cost), _, _) => cost.total_cost(units.number()),
+			(None, _, Some(total)) => Ok(Some(if units.is_negative() {
+				-total
+			} else {
+				total.clone()
+			})),
+			(None, Some(price), None) => price.checked_mul(units.number()).map(Some),
+			(None, None, None) => Ok(Some(units.clone())),
+		}
+	}
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Renders the posting as it's written in a transaction, without indentation.
+impl Display for Posting {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if let Some(flag) = self.flag {
+			write!(f, "{} ", flag)?;
+		}
+		write!(f, "{}", self.account)?;
+		if let Some(units) = &self.units {
+			write!(f, "  {}", units)?;
+		}
+		match &self.cost {
+			Some(CostOrSpec::Cost(cost)) => write!(f, " {{{}}}", cost)?,
+			Some(CostOrSpec::Spec(spec))
+				if spec.number_per().is_none() && spec.number_total().is_some() =>
+			{
+				write!(f, " {{{{{}}}}}", spec)?
+			}
+			Some(CostOrSpec::Spec(spec)) => write!(f, " {{{}}}", spec)?,
+			None => {}
+		}
+		match (&self.total_price, &self.price) {
+			(Some(total), _) => write!(f, " @@ {}", total)?,
+			(None, Some(price)) => write!(f, " @ {}", price)?,
+			(None, None) => {}
+		}
+		Ok(())
+	}
+}
+
+#[derive(Debug, Clone)]
 pub struct Directive {
 	pub date: NaiveDate,
 	pub kind: DirectiveKind,
 	pub meta: MetadataMap,
+	/// Where the directive was written, `None` if it was generated.
+	pub location: Option<SourceLocation>,
+}
+
+/// Directives are equal if they have the same content, regardless of where
+/// they were written.
+impl PartialEq for Directive {
+	fn eq(&self, other: &Self) -> bool {
+		self.date == other.date && self.kind == other.kind && self.meta == other.meta
+	}
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -126,6 +460,227 @@ pub enum DirectiveKind {
 
 impl Directive {
 	pub fn new(date: NaiveDate, kind: DirectiveKind, meta: MetadataMap) -> Self {
-		Self { date, kind, meta }
+		Self {
+			date,
+			kind,
+			meta,
+			location: None,
+		}
+	}
+
+	/// Sets where the directive was written.
+	pub fn with_location(mut self, location: SourceLocation) -> Self {
+		self.location = Some(location);
+		self
+	}
+
+	/// Returns the values of a custom directive of the given type.
+	pub fn custom(&self, custom_kind: &str) -> Option<CustomValues<'_>> {
+		match &self.kind {
+			DirectiveKind::Custom { kind, values } if kind == custom_kind => {
+				Some(CustomValues::new(values))
+			}
+			_ => None,
+		}
+	}
+
+	/// Returns the accounts the directive is about.
+	pub fn accounts(&self) -> Vec<&Account> {
+		match &self.kind {
+			DirectiveKind::Open(account, ..)
+			| DirectiveKind::Close(account)
+			| DirectiveKind::Balance { account, .. }
+			| DirectiveKind::Note { account, .. }
+			| DirectiveKind::Document { account, .. } => vec![account],
+			DirectiveKind::Pad {
+				account,
+				source_account,
+			} => vec![account, source_account],
+			DirectiveKind::Transaction { postings, .. } => {
+				postings.iter().map(Posting::account).collect()
+			}
+			_ => vec![],
+		}
+	}
+
+	/// Returns the key to sort directives by. On the same day, accounts are
+	/// opened first and balances are asserted at the start of the day, while
+	/// documents and account closings come last.
+	pub fn sort_key(&self) -> (NaiveDate, i8) {
+		let order = match self.kind {
+			DirectiveKind::Open(..) => -2,
+			DirectiveKind::Balance { .. } => -1,
+			DirectiveKind::Document { .. } => 1,
+			DirectiveKind::Close(..) => 2,
+			_ => 0,
+		};
+		(self.date, order)
+	}
+}
+
+/// Writes tags and links sorted, so the output doesn't depend on hashing.
+fn write_tags_links(
+	f: &mut std::fmt::Formatter<'_>,
+	tags: &HashSet<String>,
+	links: &HashSet<String>,
+) -> std::fmt::Result {
+	let mut tags: Vec<&String> = tags.iter().collect();
+	tags.sort();
+	let mut links: Vec<&String> = links.iter().collect();
+	links.sort();
+	for tag in tags {
+		write!(f, " #{}", tag)?;
+	}
+	for link in links {
+		write!(f, " ^{}", link)?;
+	}
+	Ok(())
+}
+
+fn write_meta(
+	f: &mut std::fmt::Formatter<'_>,
+	meta: &MetadataMap,
+	indent: &str,
+) -> std::fmt::Result {
+	for (key, value) in meta {
+		write!(f, "\n{}{}: {}", indent, key, value)?;
+	}
+	Ok(())
+}
+
+/// Renders the directive in Beancount syntax, without a trailing newline.
+impl Display for Directive {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} ", self.date)?;
+		match &self.kind {
+			DirectiveKind::Open(account, commodities, booking_method) => {
+				write!(f, "open {}", account)?;
+				if !commodities.is_empty() {
+					let commodities: Vec<String> =
+						commodities.iter().map(|c| c.to_string()).collect();
+					write!(f, " {}", commodities.join(","))?;
+				}
+				if let Some(booking_method) = booking_method {
+					write!(f, " \"{}\"", booking_method)?;
+				}
+			}
+			DirectiveKind::Close(account) => write!(f, "close {}", account)?,
+			DirectiveKind::Commodity(commodity) => write!(f, "commodity {}", commodity)?,
+			DirectiveKind::Pad {
+				account,
+				source_account,
+			} => write!(f, "pad {} {}", account, source_account)?,
+			DirectiveKind::Balance {
+				account,
+				amount,
+				tolerance,
+				..
+			} => match tolerance {
+				Some(tolerance) => write!(
+					f,
+					"balance {} {} ~ {} {}",
+					account,
+					amount.number(),
+					tolerance,
+					amount.commodity()
+				)?,
+				None => write!(f, "balance {} {}", account, amount)?,
+			},
+			DirectiveKind::Transaction {
+				flag,
+				payee,
+				narration,
+				tags,
+				links,
+				postings,
+			} => {
+				write!(f, "{}", flag.unwrap_or('*'))?;
+				if let Some(payee) = payee {
+					write!(f, " {:?}", payee)?;
+				}
+				write!(f, " {:?}", narration.as_deref().unwrap_or_default())?;
+				write_tags_links(f, tags, links)?;
+				write_meta(f, &self.meta, "  ")?;
+				for posting in postings {
+					write!(f, "\n  {}", posting)?;
+					write_meta(f, &posting.meta, "    ")?;
+				}
+				return Ok(());
+			}
+			DirectiveKind::Note {
+				account,
+				comment,
+				tags,
+				links,
+			} => {
+				write!(f, "note {} {:?}", account, comment)?;
+				write_tags_links(f, tags, links)?;
+			}
+			DirectiveKind::Event { kind, description } => {
+				write!(f, "event {:?} {:?}", kind, description)?
+			}
+			DirectiveKind::Query { name, query } => write!(f, "query {:?} {:?}", name, query)?,
+			DirectiveKind::Price { commodity, amount } => {
+				write!(f, "price {} {}", commodity, amount)?
+			}
+			DirectiveKind::Document {
+				account,
+				filename,
+				tags,
+				links,
+			} => {
+				write!(f, "document {} {:?}", account, filename)?;
+				write_tags_links(f, tags, links)?;
+			}
+			DirectiveKind::Custom { kind, values } => {
+				write!(f, "custom {:?}", kind)?;
+				for value in values {
+					write!(f, " {}", value)?;
+				}
+			}
+		}
+		write_meta(f, &self.meta, "  ")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use crate::loader::load_str;
+
+	const INPUT: &str = r#"2025-01-01 open Assets:Brokerage HOOL,USD "FIFO"
+2025-01-01 open Assets:Cash
+  owner: "Alice"
+2025-01-01 commodity HOOL
+2025-01-01 custom "goal" Expenses:Food "monthly" 500.00 USD TRUE
+2025-01-02 * "Broker" "Buy" #invest ^trade-1
+  checked: 2025-01-03
+  Assets:Brokerage  3 HOOL {30.00 USD, 2025-01-02}
+    lot: Assets:Brokerage
+  Assets:Cash  -90.00 USD
+2025-01-03 ! "Exchange"
+  Assets:Cash  10.00 EUR @@ 11.00 USD
+  Assets:Cash  -11.00 USD
+2025-01-04 price HOOL 40.00 USD
+2025-01-05 note Assets:Cash "Counted" #cash
+2025-01-05 event "location" "Paris"
+2025-01-05 query "cash" "SELECT account WHERE account ~ 'Cash'"
+2025-01-06 balance Assets:Cash -101.00 ~ 0.01 USD
+2025-01-06 close Assets:Brokerage"#;
+
+	/// Booked directives render as they're written, and load back the same.
+	#[test]
+	fn test_display_round_trip() {
+		let ledger = load_str(Rc::from("test"), INPUT);
+		assert!(ledger.errors.is_empty(), "{:?}", ledger.errors);
+		let rendered: Vec<String> = ledger
+			.directives
+			.iter()
+			.map(|directive| directive.to_string())
+			.collect();
+		let reloaded = load_str(Rc::from("test"), &rendered.join("\n"));
+		assert_eq!(reloaded.directives, ledger.directives);
+		assert_eq!(rendered.join("\n"), INPUT);
 	}
 }
@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap, HashMap};
+
+use rust_decimal::{Decimal, RoundingStrategy};
+
+use super::{
+	directive::{Directive, DirectiveKind},
+	inventory::{Inventory, Position},
+	number::format_number,
+	position::Cost,
+	types::{Amount, Commodity},
+};
+
+/// Which of the precisions seen in the input to use when rendering numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+	/// The most common number of fractional digits, best for display.
+	MostCommon,
+	/// The largest number of fractional digits, so nothing is hidden.
+	Maximum,
+}
+
+/// Statistics about the numbers seen for a single commodity.
+#[derive(Debug, Clone, Default)]
+struct CommodityContext {
+	/// Number of occurrences of each number of fractional digits.
+	fractional_dist: BTreeMap<u32, usize>,
+	/// The largest number of integer digits seen.
+	integer_max: usize,
+	/// Whether any negative number was seen.
+	has_sign: bool,
+	/// Numbers large enough to be grouped that were written with separators.
+	grouped: usize,
+	/// Numbers large enough to be grouped that were written without them.
+	ungrouped: usize,
+}
+
+impl CommodityContext {
+	fn update(&mut self, number: Decimal, grouped: bool) {
+		*self.fractional_dist.entry(number.scale()).or_default() += 1;
+
+		let integer_digits = number.trunc().abs().to_string().len();
+		self.integer_max = self.integer_max.max(integer_digits);
+		self.has_sign |= number.is_sign_negative();
+
+		// Small numbers don't tell us whether the user groups digits
+		if integer_digits > 3 {
+			if grouped {
+				self.grouped += 1;
+			} else {
+				self.ungrouped += 1;
+			}
+		}
+	}
+
+	fn precision(&self, precision: Precision) -> Option<u32> {
+		match precision {
+			// On ties the larger precision wins, so nothing is rounded away
+			Precision::MostCommon => self
+				.fractional_dist
+				.iter()
+				.max_by_key(|(_, count)| **count)
+				.map(|(dp, _)| *dp),
+			Precision::Maximum => self.fractional_dist.keys().next_back().copied(),
+		}
+	}
+
+	fn is_grouped(&self) -> bool {
+		self.grouped > self.ungrouped
+	}
+}
+
+/// Infers how numbers of each commodity should be rendered from the way they
+/// were written in the input, similar to Beancount's display context.
+#[derive(Debug, Clone, Default)]
+pub struct DisplayContext {
+	commodities: HashMap<Commodity, CommodityContext>,
+}
+
+impl DisplayContext {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Builds a display context from all the amounts in the directives.
+	pub fn from_directives<'a>(directives: impl IntoIterator<Item = &'a Directive>) -> Self {
+		let mut context = Self::new();
+		for directive in directives {
+			context.update_directive(directive);
+		}
+		context
+	}
+
+	/// Records a number written in the input for a commodity.
+	pub fn update(&mut self, amount: &Amount) {
+		self.commodities
+			.entry(amount.commodity().clone())
+			.or_default()
+			.update(amount.number(), amount.is_grouped());
+	}
+
+	/// Records all the amounts written in a directive.
+	pub fn update_directive(&mut self, directive: &Directive) {
+		match &directive.kind {
+			DirectiveKind::Transaction { postings, .. } => {
+				for posting in postings {
+					posting.units().into_iter().for_each(|a| self.update(a));
+					// A price derived from a total has no precision of its own
+					posting
+						.total_price()
+						.or(posting.price())
+						.into_iter()
+						.for_each(|a| self.update(a));
+				}
+			}
+			DirectiveKind::Balance { amount, .. } | DirectiveKind::Price { amount, .. } => {
+				self.update(amount)
+			}
+			_ => {}
+		}
+	}
+
+	/// Returns the number of fractional digits to render for a commodity, or
+	/// `None` if it was never seen.
+	pub fn precision(&self, commodity: &Commodity, precision: Precision) -> Option<u32> {
+		self.commodities
+			.get(commodity)
+			.and_then(|context| context.precision(precision))
+	}
+
+	/// Returns the largest number of integer digits seen for a commodity,
+	/// including room for a sign if negative numbers were seen. This is useful
+	/// to align numbers in columns.
+	pub fn integer_width(&self, commodity: &Commodity) -> usize {
+		self.commodities
+			.get(commodity)
+			.map(|context| {
+				let digits = context.integer_max;
+				let separators = if context.is_grouped() {
+					(digits.max(1) - 1) / 3
+				} else {
+					0
+				};
+				digits + separators + usize::from(context.has_sign)
+			})
+			.unwrap_or_default()
+	}
+
+	/// Formats a number using the style inferred for its commodity. Numbers of
+	/// commodities that were never seen are rendered as they are.
+	pub fn format_number(&self, number: Decimal, commodity: &Commodity) -> String {
+		match self.commodities.get(commodity) {
+			Some(context) => format_number(
+				number,
+				context.precision(Precision::MostCommon),
+				context.is_grouped(),
+			),
+			None => format_number(number, None, false),
+		}
+	}
+
+	/// Rounds a number to the precision inferred for its commodity, keeping
+	/// its trailing zeros. Numbers of commodities that were never seen are
+	/// returned as they are.
+	pub fn round(&self, number: Decimal, commodity: &Commodity) -> Decimal {
+		match self.precision(commodity, Precision::MostCommon) {
+			Some(dp) => {
+				let mut rounded =
+					number.round_dp_with_strategy(dp, RoundingStrategy::MidpointNearestEven);
+				rounded.rescale(dp);
+				rounded
+			}
+			None => number,
+		}
+	}
+
+	/// Formats an amount using the style inferred for its commodity.
+	pub fn format_amount(&self, amount: &Amount) -> String {
+		format!(
+			"{} {}",
+			self.format_number(amount.number(), amount.commodity()),
+			amount.commodity()
+		)
+	}
+
+	/// Formats a cost like its `Display`, with the number in the style of its
+	/// commodity.
+	pub fn format_cost(&self, cost: &Cost) -> String {
+		let mut s = format!(
+			"{} {}, {}",
+			self.format_number(cost.number(), cost.commodity()),
+			cost.commodity(),
+			cost.date()
+		);
+		if let Some(label) = cost.label() {
+			s.push_str(&format!(", \"{}\"", label));
+		}
+		s
+	}
+
+	/// Formats a position like its `Display`, with the numbers in the style of
+	/// their commodities.
+	pub fn format_position(&self, position: &Position) -> String {
+		match position.cost() {
+			Some(cost) => format!(
+				"{} {{{}}}",
+				self.format_amount(position.units()),
+				self.format_cost(cost)
+			),
+			None => self.format_amount(position.units()),
+		}
+	}
+
+	/// Formats the positions of an inventory, separated by commas.
+	pub fn format_inventory(&self, inventory: &Inventory) -> String {
+		let positions: Vec<String> = inventory
+			.iter()
+			.map(|position| self.format_position(position))
+			.collect();
+		positions.join(", ")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashSet;
+	use std::str::FromStr;
+
+	use chrono::NaiveDate;
+
+	use super::*;
+	use crate::core::directive::{MetadataMap, Posting};
+	use crate::core::types::amount;
+
+	#[test]
+	fn test_format_number() {
+		let d = |s| Decimal::from_str(s).unwrap();
+		assert_eq!(format_number(d("1234567.891"), None, false), "1234567.891");
+		assert_eq!(format_number(d("1234567.891"), None, true), "1,234,567.891");
+		assert_eq!(format_number(d("-1234.5"), Some(2), true), "-1,234.50");
+		assert_eq!(format_number(d("123.455"), Some(2), true), "123.46");
+		assert_eq!(format_number(d("-0.001"), Some(2), false), "0.00");
+		assert_eq!(format_number(d("100"), Some(0), true), "100");
+	}
+
+	#[test]
+	fn test_inferred_precision() {
+		let usd: Commodity = "USD".parse().unwrap();
+		let hool: Commodity = "HOOL".parse().unwrap();
+
+		let mut context = DisplayContext::new();
+		context.update(&amount("1.00", "USD"));
+		context.update(&amount("-12.50", "USD"));
+		context.update(&amount("7.125", "USD"));
+		context.update(&amount("1000.00", "USD").with_grouping(true));
+		context.update(&amount("10", "HOOL"));
+
+		assert_eq!(context.precision(&usd, Precision::MostCommon), Some(2));
+		assert_eq!(context.precision(&usd, Precision::Maximum), Some(3));
+		assert_eq!(context.precision(&hool, Precision::MostCommon), Some(0));
+		assert_eq!(context.integer_width(&usd), 6);
+
+		assert_eq!(
+			context.format_amount(&amount("1234.5", "USD")),
+			"1,234.50 USD"
+		);
+		assert_eq!(context.format_amount(&amount("3.5", "HOOL")), "4 HOOL");
+		assert_eq!(context.round(Decimal::from(5), &usd).to_string(), "5.00");
+		assert_eq!(
+			context.format_amount(&amount("0.1234", "EUR")),
+			"0.1234 EUR"
+		);
+	}
+
+	#[test]
+	fn test_total_price_precision() {
+		let usd: Commodity = "USD".parse().unwrap();
+		let posting = |units, price| {
+			Posting::new(
+				"Assets:Brokerage".parse().unwrap(),
+				Some(units),
+				None,
+				price,
+				None,
+				MetadataMap::default(),
+			)
+		};
+		let bought = posting(amount("3", "HOOL"), None).with_total_price(amount("100.00", "USD"));
+		let directive = Directive::new(
+			NaiveDate::from_ymd_opt(2025, 1, 1).unwrap(),
+			DirectiveKind::Transaction {
+				flag: Some('*'),
+				payee: None,
+				narration: None,
+				tags: HashSet::new(),
+				links: HashSet::new(),
+				postings: vec![bought.clone(), posting(amount("-100.00", "USD"), None)],
+			},
+			MetadataMap::default(),
+		);
+
+		// The per-unit price has a long expansion but isn't used for precision
+		assert!(bought.price().unwrap().number().scale() > 2);
+		assert_eq!(bought.weight().unwrap().unwrap(), amount("100.00", "USD"));
+		let context = DisplayContext::from_directives([&directive]);
+		assert_eq!(context.precision(&usd, Precision::Maximum), Some(2));
+	}
+}
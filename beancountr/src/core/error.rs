@@ -1,11 +1,24 @@
 use std::fmt;
 
-use super::types::Commodity;
+use super::{
+	directive::{Directive, SourceLocation},
+	types::Commodity,
+};
 
 #[derive(Debug)]
 pub enum BeanError {
 	DecimalError(rust_decimal::Error),
 	CommodityMismatch(Commodity, Commodity),
+	DivisionByZero,
+	/// An arithmetic result too large to be represented.
+	Overflow,
+	InvalidBookingMethod(String),
+	/// A budget period other than daily, weekly, monthly, quarterly or yearly.
+	InvalidPeriod(String),
+	/// An option that is unknown or has an invalid value, with the reason.
+	InvalidOption(String, String),
+	/// A query that can't be run, with the reason.
+	InvalidQuery(String),
 }
 
 impl std::error::Error for BeanError {}
@@ -19,6 +32,12 @@ impl fmt::Display for BeanError {
 				"Unmatching currencies for operation on {} and {}",
 				lhs, rhs
 			),
+			Self::DivisionByZero => write!(f, "Division by zero"),
+			Self::Overflow => write!(f, "Arithmetic overflow"),
+			Self::InvalidBookingMethod(s) => write!(f, "Invalid booking method: {}", s),
+			Self::InvalidPeriod(s) => write!(f, "Invalid period: {}", s),
+			Self::InvalidOption(key, reason) => write!(f, "Invalid option \"{}\": {}", key, reason),
+			Self::InvalidQuery(reason) => write!(f, "Invalid query: {}", reason),
 		}
 	}
 }
@@ -30,3 +49,36 @@ impl From<rust_decimal::Error> for BeanError {
 }
 
 pub type Result<T> = std::result::Result<T, BeanError>;
+
+/// A problem found in the input, along with where it was found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+	pub message: String,
+	pub location: Option<SourceLocation>,
+}
+
+impl Diagnostic {
+	pub fn new(message: impl Into<String>) -> Self {
+		Self {
+			message: message.into(),
+			location: None,
+		}
+	}
+
+	/// Creates a diagnostic located at the directive it's about.
+	pub fn at(directive: &Directive, message: impl Into<String>) -> Self {
+		Self {
+			message: message.into(),
+			location: directive.location.clone(),
+		}
+	}
+}
+
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match &self.location {
+			Some(location) => write!(f, "{}: {}", location, self.message),
+			None => f.write_str(&self.message),
+		}
+	}
+}
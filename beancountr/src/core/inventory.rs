@@ -0,0 +1,283 @@
+use std::{borrow::Borrow, fmt::Display, ops::Neg};
+
+use rust_decimal::Decimal;
+
+use super::{
+	amounts::Amounts,
+	error::{BeanError, Result},
+	position::Cost,
+	types::{Amount, Commodity},
+};
+
+/// A number of units of a commodity, optionally held at a cost.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Position {
+	units: Amount,
+	cost: Option<Cost>,
+}
+
+impl Position {
+	pub fn new(units: Amount, cost: Option<Cost>) -> Self {
+		Self { units, cost }
+	}
+
+	pub fn units(&self) -> &Amount {
+		&self.units
+	}
+
+	pub fn cost(&self) -> Option<&Cost> {
+		self.cost.as_ref()
+	}
+
+	/// Returns the total cost of the units, or the units if they aren't held at
+	/// cost.
+	pub fn at_cost(&self) -> Amount {
+		match &self.cost {
+			Some(cost) => Amount::new(
+				self.units.number() * cost.number(),
+				cost.commodity().clone(),
+			),
+			None => self.units.clone(),
+		}
+	}
+
+	/// Returns the same lot with another number of units.
+	pub fn with_number(&self, number: Decimal) -> Position {
+		Position::new(
+			Amount::new(number, self.units.commodity().clone()),
+			self.cost.clone(),
+		)
+	}
+
+	/// Checks if both positions are of the same commodity held at the same cost.
+	pub fn same_lot(&self, other: &Position) -> bool {
+		self.units.commodity() == other.units.commodity() && self.cost == other.cost
+	}
+}
+
+impl Neg for &Position {
+	type Output = Position;
+
+	fn neg(self) -> Position {
+		self.with_number(-self.units.number())
+	}
+}
+
+impl From<Amount> for Position {
+	fn from(units: Amount) -> Self {
+		Self::new(units, None)
+	}
+}
+
+/// Renders the position as it would be written in a posting, as in
+/// `10 HOOL {5.00 USD, 2025-01-01}`.
+impl Display for Position {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.cost {
+			Some(cost) => write!(f, "{} {{{}}}", self.units, cost),
+			None => write!(f, "{}", self.units),
+		}
+	}
+}
+
+/// The positions held in an account. Positions of the same commodity and cost
+/// are merged into a single lot, and lots are kept in the order they were
+/// first added.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Inventory {
+	positions: Vec<Position>,
+}
+
+impl Inventory {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.positions.is_empty()
+	}
+
+	/// Returns the number of lots.
+	pub fn len(&self) -> usize {
+		self.positions.len()
+	}
+
+	pub fn iter(&self) -> std::slice::Iter<'_, Position> {
+		self.positions.iter()
+	}
+
+	/// Sums positions into an inventory.
+	pub fn sum<P: Borrow<Position>>(positions: impl IntoIterator<Item = P>) -> Result<Inventory> {
+		let mut inventory = Self::new();
+		for position in positions {
+			inventory.add_position(position.borrow())?;
+		}
+		Ok(inventory)
+	}
+
+	/// Adds a position to the lot of the same commodity and cost. Lots that
+	/// are reduced to zero are removed. Fails if the units of the lot
+	/// overflow, leaving it unchanged.
+	pub fn add_position(&mut self, position: &Position) -> Result<()> {
+		if position.units.is_zero() {
+			return Ok(());
+		}
+		match self.positions.iter().position(|p| p.same_lot(position)) {
+			Some(i) => {
+				let number = self.positions[i]
+					.units
+					.number()
+					.checked_add(position.units.number())
+					.ok_or(BeanError::Overflow)?;
+				if number.is_zero() {
+					self.positions.remove(i);
+				} else {
+					self.positions[i] = self.positions[i].with_number(number);
+				}
+			}
+			None => self.positions.push(position.clone()),
+		}
+		Ok(())
+	}
+
+	/// Adds units that aren't held at cost.
+	pub fn add_amount(&mut self, amount: &Amount) -> Result<()> {
+		self.add_position(&Position::new(amount.clone(), None))
+	}
+
+	/// Adds the positions of another inventory. Fails if any lot overflows,
+	/// leaving them all unchanged.
+	pub fn add_inventory(&mut self, other: &Inventory) -> Result<()> {
+		let mut sum = self.clone();
+		for position in &other.positions {
+			sum.add_position(position)?;
+		}
+		*self = sum;
+		Ok(())
+	}
+
+	/// Returns the lots of a commodity.
+	pub fn lots<'a>(&'a self, commodity: &'a Commodity) -> impl Iterator<Item = &'a Position> {
+		self.positions
+			.iter()
+			.filter(move |p| p.units.commodity() == commodity)
+	}
+
+	/// Returns the units of every commodity, ignoring costs. Fails if they
+	/// overflow.
+	pub fn units(&self) -> Result<Amounts> {
+		Amounts::sum(self.positions.iter().map(|p| &p.units))
+	}
+
+	/// Returns the total cost of the positions, with units that aren't held at
+	/// cost counted as they are. Fails if it overflows.
+	pub fn at_cost(&self) -> Result<Amounts> {
+		Amounts::sum(self.positions.iter().map(Position::at_cost))
+	}
+
+	/// Returns an inventory with every position transformed. Fails if the
+	/// positions that end up in the same lot overflow.
+	pub fn map(&self, f: impl Fn(&Position) -> Position) -> Result<Inventory> {
+		Self::sum(self.positions.iter().map(f))
+	}
+}
+
+impl Neg for &Inventory {
+	type Output = Inventory;
+
+	fn neg(self) -> Inventory {
+		let positions = self.positions.iter().map(|position| -position).collect();
+		Inventory { positions }
+	}
+}
+
+impl From<Position> for Inventory {
+	fn from(position: Position) -> Self {
+		let mut inventory = Self::new();
+		if !position.units.is_zero() {
+			inventory.positions.push(position);
+		}
+		inventory
+	}
+}
+
+impl<'a> IntoIterator for &'a Inventory {
+	type Item = &'a Position;
+	type IntoIter = std::slice::Iter<'a, Position>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.positions.iter()
+	}
+}
+
+impl Display for Inventory {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let positions: Vec<String> = self.positions.iter().map(|p| p.to_string()).collect();
+		write!(f, "({})", positions.join(", "))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use chrono::NaiveDate;
+
+	use super::*;
+	use crate::core::types::amount;
+
+	fn lot(number: &str, cost: &str, day: u32) -> Position {
+		Position::new(
+			amount(number, "HOOL"),
+			Some(Cost::new(
+				Decimal::from_str(cost).unwrap(),
+				"USD".parse().unwrap(),
+				NaiveDate::from_ymd_opt(2025, 1, day).unwrap(),
+				None,
+			)),
+		)
+	}
+
+	#[test]
+	fn test_inventory() {
+		let mut inventory = Inventory::new();
+		inventory.add_position(&lot("10", "5.00", 1)).unwrap();
+		inventory.add_position(&lot("5", "6.00", 2)).unwrap();
+		inventory.add_amount(&amount("100.00", "USD")).unwrap();
+		inventory.add_position(&lot("2", "5.00", 1)).unwrap();
+		assert_eq!(inventory.len(), 3);
+		assert_eq!(
+			inventory.to_string(),
+			"(12 HOOL {5.00 USD, 2025-01-01}, 5 HOOL {6.00 USD, 2025-01-02}, 100.00 USD)"
+		);
+		assert_eq!(
+			inventory.units().unwrap().to_string(),
+			"(17 HOOL, 100.00 USD)"
+		);
+		assert_eq!(inventory.at_cost().unwrap().to_string(), "(190.00 USD)");
+
+		inventory.add_position(&lot("-12", "5.00", 1)).unwrap();
+		inventory.add_amount(&amount("-100.00", "USD")).unwrap();
+		assert_eq!(inventory.len(), 1);
+		assert_eq!(
+			inventory.lots(&"HOOL".parse().unwrap()).next(),
+			Some(&lot("5", "6.00", 2))
+		);
+		assert!((-&inventory).iter().all(|p| p.units().is_negative()));
+	}
+
+	#[test]
+	fn test_inventory_overflow() {
+		let mut inventory = Inventory::from(lot("50000000000000000000000000000", "1", 1));
+		assert!(matches!(
+			inventory.add_position(&lot("50000000000000000000000000000", "1", 1)),
+			Err(BeanError::Overflow)
+		));
+		assert_eq!(inventory.len(), 1);
+		assert!(inventory.add_position(&lot("1", "1", 2)).is_ok());
+		// Both lots end up in the same one
+		assert!(inventory
+			.map(|_| lot("50000000000000000000000000000", "1", 1))
+			.is_err());
+	}
+}
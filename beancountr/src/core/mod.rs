@@ -3,3 +3,11 @@ pub mod number;
 pub mod position;
 pub mod types;
 pub mod directive;
+pub mod display_context;
+pub mod options;
+pub mod tolerance;
+pub mod amounts;
+pub mod inventory;
+pub mod prices;
+pub mod custom;
+pub mod budget;
@@ -14,5 +14,43 @@ pub fn bean_d(s: &str) -> Result<Decimal> {
 			let s_no_commas = s.replace(",", "");
 			Decimal::from_str(&s_no_commas)
 		})
-		.map_err(|e| BeanError::from(e))
+		.map_err(BeanError::from)
+}
+
+/// Formats a number with a fixed number of fractional digits, or with its own
+/// scale if no precision is given, optionally grouping the integer digits in
+/// thousands with commas.
+pub fn format_number(number: Decimal, precision: Option<u32>, grouped: bool) -> String {
+	let number = match precision {
+		Some(dp) => number.round_dp_with_strategy(dp, RoundingStrategy::MidpointNearestEven),
+		None => number,
+	};
+	let s = match precision {
+		Some(dp) => format!("{:.*}", dp as usize, number.abs()),
+		None => number.abs().to_string(),
+	};
+	let (integer, fraction) = match s.split_once('.') {
+		Some((integer, fraction)) => (integer, Some(fraction)),
+		None => (s.as_str(), None),
+	};
+
+	let mut out = String::new();
+	if number.is_sign_negative() && !number.is_zero() {
+		out.push('-');
+	}
+	if grouped {
+		for (i, c) in integer.chars().enumerate() {
+			if i > 0 && (integer.len() - i) % 3 == 0 {
+				out.push(',');
+			}
+			out.push(c);
+		}
+	} else {
+		out.push_str(integer);
+	}
+	if let Some(fraction) = fraction {
+		out.push('.');
+		out.push_str(fraction);
+	}
+	out
 }
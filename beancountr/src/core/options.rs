@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use rust_decimal::Decimal;
+
+use super::{
+	directive::{Directive, DirectiveKind},
+	error::{BeanError, Result},
+	number::{bean_d, HALF},
+	tolerance::Tolerances,
+	types::{Account, BookingMethod, Commodity},
+};
+
+/// The options of a ledger, set with `option` statements in the input. Any
+/// option that isn't set keeps the same default as in Beancount.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+	/// The title of the ledger.
+	pub title: String,
+
+	/// Names of the root accounts of each account type.
+	pub name_assets: String,
+	pub name_liabilities: String,
+	pub name_equity: String,
+	pub name_income: String,
+	pub name_expenses: String,
+
+	/// Equity sub-accounts used when summarizing and closing periods.
+	pub account_previous_balances: String,
+	pub account_previous_earnings: String,
+	pub account_previous_conversions: String,
+	pub account_current_earnings: String,
+	pub account_current_conversions: String,
+	pub account_unrealized_gains: String,
+	pub account_rounding: Option<String>,
+
+	/// The commodity used to record conversions at cost.
+	pub conversion_currency: String,
+
+	/// The commodities the ledger is mainly kept in, for reporting.
+	pub operating_currency: Vec<Commodity>,
+
+	/// Whether numbers are rendered with thousands separators.
+	pub render_commas: bool,
+
+	/// Directories to search for documents.
+	pub documents: Vec<String>,
+
+	/// Tolerances to use for commodities that have none inferred from the
+	/// postings of a transaction. A wildcard is given with `*:0.005`.
+	pub inferred_tolerance_default: Tolerances,
+
+	/// Multiplier applied to the last digit of a number to infer a tolerance.
+	pub inferred_tolerance_multiplier: Decimal,
+
+	/// Whether to also infer tolerances for cost and price commodities.
+	pub infer_tolerance_from_cost: bool,
+
+	/// The booking method of accounts that don't specify one when opened.
+	pub booking_method: BookingMethod,
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Self {
+			title: String::new(),
+			name_assets: "Assets".to_string(),
+			name_liabilities: "Liabilities".to_string(),
+			name_equity: "Equity".to_string(),
+			name_income: "Income".to_string(),
+			name_expenses: "Expenses".to_string(),
+			account_previous_balances: "Opening-Balances".to_string(),
+			account_previous_earnings: "Earnings:Previous".to_string(),
+			account_previous_conversions: "Conversions:Previous".to_string(),
+			account_current_earnings: "Earnings:Current".to_string(),
+			account_current_conversions: "Conversions:Current".to_string(),
+			account_unrealized_gains: "Earnings:Unrealized".to_string(),
+			account_rounding: None,
+			conversion_currency: "NOTHING".to_string(),
+			operating_currency: vec![],
+			render_commas: false,
+			documents: vec![],
+			inferred_tolerance_default: Tolerances::default(),
+			inferred_tolerance_multiplier: HALF,
+			infer_tolerance_from_cost: false,
+			booking_method: BookingMethod::default(),
+		}
+	}
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool> {
+	match value.to_lowercase().as_str() {
+		"true" | "1" => Ok(true),
+		"false" | "0" => Ok(false),
+		_ => Err(BeanError::InvalidOption(
+			key.to_string(),
+			format!("expected a boolean, found \"{}\"", value),
+		)),
+	}
+}
+
+fn parse_decimal(key: &str, value: &str) -> Result<Decimal> {
+	bean_d(value).map_err(|_| {
+		BeanError::InvalidOption(
+			key.to_string(),
+			format!("expected a number, found \"{}\"", value),
+		)
+	})
+}
+
+impl Options {
+	/// Sets an option from the key and value of an `option` statement. Options
+	/// that can be given several times, like `operating_currency`, accumulate.
+	pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+		match key {
+			"title" => self.title = value.to_string(),
+			"name_assets" => self.name_assets = value.to_string(),
+			"name_liabilities" => self.name_liabilities = value.to_string(),
+			"name_equity" => self.name_equity = value.to_string(),
+			"name_income" => self.name_income = value.to_string(),
+			"name_expenses" => self.name_expenses = value.to_string(),
+			"account_previous_balances" => self.account_previous_balances = value.to_string(),
+			"account_previous_earnings" => self.account_previous_earnings = value.to_string(),
+			"account_previous_conversions" => self.account_previous_conversions = value.to_string(),
+			"account_current_earnings" => self.account_current_earnings = value.to_string(),
+			"account_current_conversions" => self.account_current_conversions = value.to_string(),
+			"account_unrealized_gains" => self.account_unrealized_gains = value.to_string(),
+			"account_rounding" => self.account_rounding = Some(value.to_string()),
+			"conversion_currency" => self.conversion_currency = value.to_string(),
+			"operating_currency" => self.operating_currency.push(value.parse()?),
+			"render_commas" => self.render_commas = parse_bool(key, value)?,
+			"documents" => self.documents.push(value.to_string()),
+			"inferred_tolerance_default" => {
+				let (commodity, tolerance) = value.split_once(':').ok_or_else(|| {
+					BeanError::InvalidOption(
+						key.to_string(),
+						format!("expected \"<commodity>:<tolerance>\", found \"{}\"", value),
+					)
+				})?;
+				let tolerance = parse_decimal(key, tolerance)?;
+				match commodity {
+					"*" => self.inferred_tolerance_default.set_default(tolerance),
+					_ => self
+						.inferred_tolerance_default
+						.set(commodity.parse()?, tolerance),
+				}
+			}
+			"inferred_tolerance_multiplier" => {
+				self.inferred_tolerance_multiplier = parse_decimal(key, value)?
+			}
+			"infer_tolerance_from_cost" => self.infer_tolerance_from_cost = parse_bool(key, value)?,
+			"booking_method" => self.booking_method = value.parse()?,
+			_ => {
+				return Err(BeanError::InvalidOption(
+					key.to_string(),
+					"unknown option".to_string(),
+				))
+			}
+		}
+		Ok(())
+	}
+
+	/// Returns the full name of an account under the equity root, like the
+	/// `account_current_earnings` option which is relative to it.
+	pub fn equity_account(&self, name: &str) -> Account {
+		Account::from(vec![self.name_equity.as_str(), name])
+	}
+}
+
+/// Renders the options as `option` statements, one per line, that set them
+/// back when parsed.
+impl Display for Options {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let mut options: Vec<(&str, String)> = vec![
+			("title", self.title.clone()),
+			("name_assets", self.name_assets.clone()),
+			("name_liabilities", self.name_liabilities.clone()),
+			("name_equity", self.name_equity.clone()),
+			("name_income", self.name_income.clone()),
+			("name_expenses", self.name_expenses.clone()),
+			(
+				"account_previous_balances",
+				self.account_previous_balances.clone(),
+			),
+			(
+				"account_previous_earnings",
+				self.account_previous_earnings.clone(),
+			),
+			(
+				"account_previous_conversions",
+				self.account_previous_conversions.clone(),
+			),
+			(
+				"account_current_earnings",
+				self.account_current_earnings.clone(),
+			),
+			(
+				"account_current_conversions",
+				self.account_current_conversions.clone(),
+			),
+			(
+				"account_unrealized_gains",
+				self.account_unrealized_gains.clone(),
+			),
+		];
+		if let Some(account_rounding) = &self.account_rounding {
+			options.push(("account_rounding", account_rounding.clone()));
+		}
+		options.push(("conversion_currency", self.conversion_currency.clone()));
+		for commodity in &self.operating_currency {
+			options.push(("operating_currency", commodity.to_string()));
+		}
+		options.push(("render_commas", self.render_commas.to_string()));
+		for document in &self.documents {
+			options.push(("documents", document.clone()));
+		}
+		let default_tolerance = self.inferred_tolerance_default.default_tolerance();
+		if !default_tolerance.is_zero() {
+			options.push((
+				"inferred_tolerance_default",
+				format!("*:{}", default_tolerance),
+			));
+		}
+		let mut tolerances: Vec<_> = self.inferred_tolerance_default.iter().collect();
+		tolerances.sort();
+		for (commodity, tolerance) in tolerances {
+			options.push((
+				"inferred_tolerance_default",
+				format!("{}:{}", commodity, tolerance),
+			));
+		}
+		options.push((
+			"inferred_tolerance_multiplier",
+			self.inferred_tolerance_multiplier.to_string(),
+		));
+		options.push((
+			"infer_tolerance_from_cost",
+			self.infer_tolerance_from_cost.to_string(),
+		));
+		options.push(("booking_method", self.booking_method.to_string()));
+
+		for (key, value) in options {
+			writeln!(f, "option {:?} {:?}", key, value)?;
+		}
+		Ok(())
+	}
+}
+
+/// Returns the booking method of every opened account, using the
+/// `booking_method` option for accounts that don't specify one.
+pub fn booking_methods<'a>(
+	directives: impl IntoIterator<Item = &'a Directive>,
+	options: &Options,
+) -> HashMap<Account, BookingMethod> {
+	directives
+		.into_iter()
+		.filter_map(|directive| match &directive.kind {
+			DirectiveKind::Open(account, _, booking_method) => Some((
+				account.clone(),
+				booking_method.unwrap_or(options.booking_method),
+			)),
+			_ => None,
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_display_options() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			option "title" "My \"ledger\""
+			option "name_assets" "Actifs"
+			option "operating_currency" "USD"
+			option "operating_currency" "EUR"
+			option "inferred_tolerance_default" "*:0.005"
+			option "inferred_tolerance_default" "JPY:1"
+			option "booking_method" "FIFO"
+			"#,
+		);
+		assert!(ledger.errors.is_empty());
+		let rendered = ledger.options.to_string();
+		assert!(rendered.contains("option \"title\" \"My \\\"ledger\\\"\"\n"));
+		assert_eq!(
+			load_str(Rc::from("test"), &rendered).options,
+			ledger.options
+		);
+	}
+}
@@ -1,10 +1,14 @@
 use chrono::NaiveDate;
 use rust_decimal::Decimal;
+use std::fmt::Display;
 use std::rc::Rc;
 
-use super::types::Commodity;
+use super::{
+	error::{BeanError, Result},
+	types::{Amount, Commodity},
+};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Cost {
 	/// Per-unit cost.
 	number: Decimal,
@@ -40,3 +44,154 @@ pub enum CostOrSpec {
 	Cost(Cost),
 	Spec(CostSpec),
 }
+
+impl Cost {
+	pub fn new(
+		number: Decimal,
+		commodity: Commodity,
+		date: NaiveDate,
+		label: Option<Rc<str>>,
+	) -> Self {
+		Self {
+			number,
+			commodity,
+			date,
+			label,
+		}
+	}
+
+	pub fn number(&self) -> Decimal {
+		self.number
+	}
+
+	pub fn commodity(&self) -> &Commodity {
+		&self.commodity
+	}
+
+	pub fn date(&self) -> NaiveDate {
+		self.date
+	}
+
+	pub fn label(&self) -> Option<&str> {
+		self.label.as_deref()
+	}
+}
+
+impl CostSpec {
+	pub fn new(
+		number_per: Option<Decimal>,
+		number_total: Option<Decimal>,
+		commodity: Option<Commodity>,
+		date: Option<NaiveDate>,
+		label: Option<Rc<str>>,
+		merge: Option<bool>,
+	) -> Self {
+		Self {
+			number_per,
+			number_total,
+			commodity,
+			date,
+			label,
+			merge,
+		}
+	}
+
+	pub fn number_per(&self) -> Option<Decimal> {
+		self.number_per
+	}
+
+	pub fn number_total(&self) -> Option<Decimal> {
+		self.number_total
+	}
+
+	pub fn commodity(&self) -> Option<&Commodity> {
+		self.commodity.as_ref()
+	}
+
+	pub fn date(&self) -> Option<NaiveDate> {
+		self.date
+	}
+
+	pub fn label(&self) -> Option<&str> {
+		self.label.as_deref()
+	}
+
+	pub fn merge(&self) -> Option<bool> {
+		self.merge
+	}
+}
+
+impl CostOrSpec {
+	/// Returns the total cost of a number of units, if enough of the cost is
+	/// known to compute it. Fails if it overflows.
+	pub fn total_cost(&self, units: Decimal) -> Result<Option<Amount>> {
+		let number = match self {
+			Self::Cost(cost) => units.checked_mul(cost.number),
+			Self::Spec(spec) => {
+				let per = spec.number_per.map(|per| units.checked_mul(per));
+				// The total cost takes the sign of the units
+				let total = spec.number_total.map(|total| {
+					if units.is_sign_negative() {
+						-total
+					} else {
+						total
+					}
+				});
+				match (per, total) {
+					(Some(per), Some(total)) => per.and_then(|per| per.checked_add(total)),
+					(Some(number), None) => number,
+					(None, Some(number)) => Some(number),
+					(None, None) => return Ok(None),
+				}
+			}
+		};
+		let commodity = match self {
+			Self::Cost(cost) => cost.commodity.clone(),
+			Self::Spec(spec) => match &spec.commodity {
+				Some(commodity) => commodity.clone(),
+				None => return Ok(None),
+			},
+		};
+		let number = number.ok_or(BeanError::Overflow)?;
+		Ok(Some(Amount::new(number, commodity)))
+	}
+}
+
+/// Renders the cost without braces, as in `5.00 USD, 2025-01-01, "lot"`.
+impl Display for Cost {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} {}, {}", self.number, self.commodity, self.date)?;
+		if let Some(label) = &self.label {
+			write!(f, ", \"{}\"", label)?;
+		}
+		Ok(())
+	}
+}
+
+/// Renders the cost spec without braces, as it would be written in the input.
+impl Display for CostSpec {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let mut parts = vec![];
+		let number = match (self.number_per, self.number_total) {
+			(Some(per), Some(total)) => Some(format!("{} # {}", per, total)),
+			(Some(number), None) | (None, Some(number)) => Some(number.to_string()),
+			(None, None) => None,
+		};
+		match (number, &self.commodity) {
+			(Some(number), Some(commodity)) => parts.push(format!("{} {}", number, commodity)),
+			(Some(number), None) => parts.push(number),
+			(None, Some(commodity)) => parts.push(commodity.to_string()),
+			(None, None) => {}
+		}
+		if let Some(date) = self.date {
+			parts.push(date.to_string());
+		}
+		if let Some(label) = &self.label {
+			parts.push(format!("\"{}\"", label));
+		}
+		if self.merge == Some(true) {
+			parts.push("*".to_string());
+		}
+		write!(f, "{}", parts.join(", "))
+	}
+}
@@ -0,0 +1,161 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use super::{
+	directive::{Directive, DirectiveKind},
+	error::Result,
+	inventory::{Inventory, Position},
+	types::{Amount, Commodity},
+};
+
+/// The rates of commodities over time, from price directives. A rate from a
+/// commodity to another can also be used the other way around, inverted.
+#[derive(Debug, Clone, Default)]
+pub struct PriceMap {
+	/// Rates of each (base, quote) pair by date, the last one of a day wins.
+	rates: HashMap<(Commodity, Commodity), BTreeMap<NaiveDate, Decimal>>,
+}
+
+impl PriceMap {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Builds a price map from all the price directives.
+	pub fn from_directives<'a>(directives: impl IntoIterator<Item = &'a Directive>) -> Self {
+		let mut prices = Self::new();
+		for directive in directives {
+			if let DirectiveKind::Price { commodity, amount } = &directive.kind {
+				prices.insert(directive.date, commodity, amount);
+			}
+		}
+		prices
+	}
+
+	/// Records the price of one unit of a commodity on a date.
+	pub fn insert(&mut self, date: NaiveDate, commodity: &Commodity, price: &Amount) {
+		self.rates
+			.entry((commodity.clone(), price.commodity().clone()))
+			.or_default()
+			.insert(date, price.number());
+	}
+
+	/// Returns the last rate from a commodity to another on or before a date,
+	/// or the last one known if no date is given, along with its date.
+	pub fn get_rate(
+		&self,
+		base: &Commodity,
+		quote: &Commodity,
+		date: Option<NaiveDate>,
+	) -> Option<(NaiveDate, Decimal)> {
+		if base == quote {
+			return Some((date.unwrap_or(NaiveDate::MIN), Decimal::ONE));
+		}
+		let last = |rates: &BTreeMap<NaiveDate, Decimal>| match date {
+			Some(date) => rates.range(..=date).next_back().map(|(d, r)| (*d, *r)),
+			None => rates.iter().next_back().map(|(d, r)| (*d, *r)),
+		};
+		let direct = self
+			.rates
+			.get(&(base.clone(), quote.clone()))
+			.and_then(last);
+		let inverse = self
+			.rates
+			.get(&(quote.clone(), base.clone()))
+			.and_then(last)
+			.filter(|(_, rate)| !rate.is_zero())
+			.map(|(d, rate)| (d, Decimal::ONE / rate));
+		// The most recent of both directions wins, the direct one on a tie
+		match (direct, inverse) {
+			(Some(direct), Some(inverse)) if inverse.0 > direct.0 => Some(inverse),
+			(Some(direct), _) => Some(direct),
+			(None, inverse) => inverse,
+		}
+	}
+
+	/// Converts an amount to another commodity, if a rate is known.
+	pub fn convert(
+		&self,
+		amount: &Amount,
+		commodity: &Commodity,
+		date: Option<NaiveDate>,
+	) -> Option<Amount> {
+		self.get_rate(amount.commodity(), commodity, date)
+			.map(|(_, rate)| Amount::new(amount.number() * rate, commodity.clone()))
+	}
+
+	/// Returns the market value of a position in the commodity of its cost, or
+	/// its units if it isn't held at cost or its commodity has no price.
+	pub fn value(&self, position: &Position, date: Option<NaiveDate>) -> Amount {
+		position
+			.cost()
+			.and_then(|cost| self.convert(position.units(), cost.commodity(), date))
+			.unwrap_or_else(|| position.units().clone())
+	}
+
+	/// Returns the market value of all the positions of an inventory. Fails if
+	/// the values of a commodity overflow.
+	pub fn value_inventory(
+		&self,
+		inventory: &Inventory,
+		date: Option<NaiveDate>,
+	) -> Result<Inventory> {
+		inventory.map(|position| self.value(position, date).into())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::core::directive::MetadataMap;
+	use crate::core::types::amount;
+
+	#[test]
+	fn test_price_map() {
+		let date = |day| NaiveDate::from_ymd_opt(2025, 1, day).unwrap();
+		let price = |day, commodity: &str, number, quote| {
+			Directive::new(
+				date(day),
+				DirectiveKind::Price {
+					commodity: commodity.parse().unwrap(),
+					amount: amount(number, quote),
+				},
+				MetadataMap::new(),
+			)
+		};
+		let prices = PriceMap::from_directives(&[
+			price(1, "HOOL", "500", "USD"),
+			price(10, "HOOL", "520", "USD"),
+			price(5, "USD", "1.25", "CAD"),
+			price(7, "CAD", "0.75", "USD"),
+		]);
+		let usd = "USD".parse().unwrap();
+		let cad = "CAD".parse().unwrap();
+
+		assert_eq!(
+			prices.convert(&amount("2", "HOOL"), &usd, Some(date(9))),
+			Some(amount("1000", "USD"))
+		);
+		assert_eq!(
+			prices.convert(&amount("2", "HOOL"), &usd, None),
+			Some(amount("1040", "USD"))
+		);
+		assert_eq!(prices.convert(&amount("2", "HOOL"), &cad, None), None);
+		assert_eq!(
+			prices.convert(&amount("10", "USD"), &cad, Some(date(6))),
+			Some(amount("12.50", "CAD"))
+		);
+		assert_eq!(
+			prices.get_rate(&cad, &usd, Some(date(6))),
+			Some((date(5), Decimal::from_str("0.8").unwrap()))
+		);
+		assert_eq!(
+			prices.get_rate(&cad, &usd, Some(date(8))),
+			Some((date(7), Decimal::from_str("0.75").unwrap()))
+		);
+	}
+}
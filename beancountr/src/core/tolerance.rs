@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+
+use super::{
+	directive::{Metadata, MetadataMap, Posting},
+	number::{HALF, ZERO},
+	options::Options,
+	position::CostOrSpec,
+	types::{Amount, Commodity},
+};
+
+/// The largest tolerance that can be inferred from a cost or a price.
+pub const MAXIMUM_TOLERANCE: Decimal = HALF;
+
+/// The metadata key used to override inferred tolerances.
+pub const TOLERANCE_KEY: &str = "tolerance";
+
+/// Tolerances per commodity, with a default for all other commodities.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tolerances {
+	tolerances: HashMap<Commodity, Decimal>,
+	default: Decimal,
+}
+
+impl Tolerances {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Returns the tolerance for a commodity.
+	pub fn get(&self, commodity: &Commodity) -> Decimal {
+		self.tolerances
+			.get(commodity)
+			.copied()
+			.unwrap_or(self.default)
+	}
+
+	/// Sets the tolerance for a commodity.
+	pub fn set(&mut self, commodity: Commodity, tolerance: Decimal) {
+		self.tolerances.insert(commodity, tolerance);
+	}
+
+	/// Sets the tolerance for commodities without their own.
+	pub fn set_default(&mut self, tolerance: Decimal) {
+		self.default = tolerance;
+	}
+
+	/// Returns the tolerance for commodities without their own.
+	pub fn default_tolerance(&self) -> Decimal {
+		self.default
+	}
+
+	/// Returns the commodities with their own tolerance, in no particular order.
+	pub fn iter(&self) -> impl Iterator<Item = (&Commodity, Decimal)> {
+		self.tolerances
+			.iter()
+			.map(|(commodity, tolerance)| (commodity, *tolerance))
+	}
+
+	/// Raises the tolerance of a commodity to at least the given one. The
+	/// default isn't considered, so an inferred tolerance always replaces it.
+	fn raise(&mut self, commodity: &Commodity, tolerance: Decimal) {
+		self.tolerances
+			.entry(commodity.clone())
+			.and_modify(|t| *t = (*t).max(tolerance))
+			.or_insert(tolerance);
+	}
+
+	/// Checks if an amount is small enough to be considered zero.
+	pub fn is_negligible(&self, amount: &Amount) -> bool {
+		amount.number().abs() <= self.get(amount.commodity())
+	}
+}
+
+/// Returns the value of the last digit of a number, or zero if it has no
+/// fractional digits.
+fn last_digit(number: Decimal) -> Decimal {
+	match number.scale() {
+		0 => ZERO,
+		scale => Decimal::new(1, scale),
+	}
+}
+
+/// Infers the tolerances to use to balance a transaction from the number of
+/// fractional digits used in its postings, the way Beancount does.
+///
+/// Each commodity gets half (the `inferred_tolerance_multiplier`) of its
+/// smallest written digit, falling back on `inferred_tolerance_default`. With
+/// `infer_tolerance_from_cost`, cost and price commodities also get the
+/// tolerance implied by converting those digits. A `tolerance` metadata on the
+/// transaction replaces the inferred tolerances, for all commodities if it's a
+/// number or for a single one if it's an amount.
+pub fn infer_tolerances(postings: &[Posting], meta: &MetadataMap, options: &Options) -> Tolerances {
+	let mut tolerances = options.inferred_tolerance_default.clone();
+	let mut cost_tolerances: HashMap<Commodity, Decimal> = HashMap::new();
+
+	for posting in postings {
+		let Some(units) = posting.units() else {
+			continue;
+		};
+		let digit = last_digit(units.number());
+		if digit.is_zero() {
+			continue;
+		}
+		let tolerance = digit * options.inferred_tolerance_multiplier;
+		tolerances.raise(units.commodity(), tolerance);
+
+		if !options.infer_tolerance_from_cost {
+			continue;
+		}
+
+		let cost = posting.cost().and_then(|cost| match cost {
+			CostOrSpec::Cost(cost) => Some((
+				(tolerance * cost.number()).min(MAXIMUM_TOLERANCE),
+				cost.commodity(),
+			)),
+			CostOrSpec::Spec(spec) => {
+				let tolerance = [spec.number_per(), spec.number_total()]
+					.into_iter()
+					.flatten()
+					.map(|number| tolerance * number)
+					.fold(MAXIMUM_TOLERANCE, Decimal::min);
+				spec.commodity().map(|commodity| (tolerance, commodity))
+			}
+		});
+		let price = posting.price().map(|price| {
+			(
+				(tolerance * price.number()).min(MAXIMUM_TOLERANCE),
+				price.commodity(),
+			)
+		});
+		for (tolerance, commodity) in cost.into_iter().chain(price) {
+			*cost_tolerances.entry(commodity.clone()).or_default() += tolerance;
+		}
+	}
+
+	for (commodity, tolerance) in cost_tolerances {
+		tolerances.raise(&commodity, tolerance);
+	}
+
+	match meta.get(TOLERANCE_KEY) {
+		Some(Metadata::Number(tolerance)) => {
+			tolerances = Tolerances::new();
+			tolerances.set_default(*tolerance);
+		}
+		Some(Metadata::Amount(amount)) => {
+			tolerances.set(amount.commodity().clone(), amount.number());
+		}
+		_ => {}
+	}
+
+	tolerances
+}
+
+/// Returns the tolerance to check a balance assertion with.
+///
+/// A tolerance given explicitly with `~` wins, then a `tolerance` metadata.
+/// Otherwise it's inferred from the last digit of the asserted amount, twice
+/// as loose as for a transaction since a balance accumulates many postings.
+/// Amounts without fractional digits must match exactly, as in Beancount.
+pub fn balance_tolerance(
+	amount: &Amount,
+	tolerance: Option<Decimal>,
+	meta: &MetadataMap,
+	options: &Options,
+) -> Decimal {
+	if let Some(tolerance) = tolerance {
+		return tolerance;
+	}
+	match meta.get(TOLERANCE_KEY) {
+		Some(Metadata::Number(tolerance)) => return *tolerance,
+		Some(Metadata::Amount(tolerance)) if tolerance.commodity() == amount.commodity() => {
+			return tolerance.number()
+		}
+		_ => {}
+	}
+	last_digit(amount.number()) * options.inferred_tolerance_multiplier * Decimal::TWO
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::core::types::amount;
+
+	fn posting(units: &str, commodity: &str, price: Option<Amount>) -> Posting {
+		Posting::new(
+			"Assets:Cash".parse().unwrap(),
+			Some(amount(units, commodity)),
+			None,
+			price,
+			None,
+			MetadataMap::default(),
+		)
+	}
+
+	#[test]
+	fn test_infer_tolerances() {
+		let d = |s| Decimal::from_str(s).unwrap();
+		let usd = "USD".parse().unwrap();
+		let cad = "CAD".parse().unwrap();
+		let eur = "EUR".parse().unwrap();
+
+		let mut options = Options::default();
+		options
+			.set("inferred_tolerance_default", "*:0.001")
+			.unwrap();
+		options
+			.set("inferred_tolerance_default", "USD:0.01")
+			.unwrap();
+
+		let postings = vec![
+			posting("10.1", "CAD", Some(amount("0.75", "USD"))),
+			posting("-7.575", "USD", None),
+		];
+		let tolerances = infer_tolerances(&postings, &MetadataMap::default(), &options);
+		assert_eq!(tolerances.get(&cad), d("0.05"));
+		// The inferred 0.0005 is smaller than the default for the commodity
+		assert_eq!(tolerances.get(&usd), d("0.01"));
+		assert_eq!(tolerances.get(&eur), d("0.001"));
+
+		options.set("infer_tolerance_from_cost", "TRUE").unwrap();
+		options.set("inferred_tolerance_multiplier", "1.2").unwrap();
+		let tolerances = infer_tolerances(&postings, &MetadataMap::default(), &options);
+		assert_eq!(tolerances.get(&cad), d("0.12"));
+		assert_eq!(tolerances.get(&usd), d("0.09"));
+
+		let meta = MetadataMap::from([(
+			TOLERANCE_KEY.to_string(),
+			Metadata::Amount(amount("0.2", "CAD")),
+		)]);
+		let tolerances = infer_tolerances(&postings, &meta, &options);
+		assert_eq!(tolerances.get(&cad), d("0.2"));
+		assert_eq!(tolerances.get(&usd), d("0.09"));
+	}
+
+	#[test]
+	fn test_balance_tolerance() {
+		let d = |s| Decimal::from_str(s).unwrap();
+		let mut options = Options::default();
+		options.set("inferred_tolerance_default", "*:0.5").unwrap();
+		let meta = MetadataMap::default();
+
+		let tolerance = |number, tolerance| {
+			balance_tolerance(&amount(number, "USD"), tolerance, &meta, &options)
+		};
+		assert_eq!(tolerance("100.00", None), d("0.01"));
+		assert_eq!(tolerance("100.5", None), d("0.1"));
+		assert_eq!(tolerance("100", None), Decimal::ZERO);
+		assert_eq!(tolerance("100", Some(d("2"))), d("2"));
+	}
+}
@@ -1,34 +1,64 @@
 use super::error::{BeanError, Result};
-use rust_decimal::Decimal;
-use std::{fmt::Display, rc::Rc, str::FromStr};
+use super::number::format_number;
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::{
+	cmp::Ordering,
+	fmt::Display,
+	hash::{Hash, Hasher},
+	ops::{Add, Div, Mul, Neg, Sub},
+	rc::Rc,
+	str::FromStr,
+};
 
-#[derive(Debug, Clone, PartialEq)]
+/// How lots are matched when reducing a position held at cost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum BookingMethod {
+	/// The lot must match a single one unambiguously.
+	#[default]
 	Strict,
+	/// Like strict, but an exact size match is preferred when ambiguous.
 	StrictWithSize,
+	/// No lot matching, mixed signs are allowed in the inventory.
 	None,
+	/// All the lots are merged at their average cost.
 	Average,
 	FirstInFirstout,
 	LastInFirstOut,
 	HighestInFirstOut,
 }
 
-impl From<String> for BookingMethod {
-	fn from(s: String) -> Self {
-		match s.as_str() {
-			"STRICT" => Self::Strict,
-			"STRICT_WITH_SIZE" => Self::StrictWithSize,
-			"NONE" => Self::None,
-			"AVERAGE" => Self::Average,
-			"FIFO" => Self::FirstInFirstout,
-			"LIFO" => Self::LastInFirstOut,
-			"HIFO" => Self::HighestInFirstOut,
-			_ => panic!("Invalid booking method: {}", s),
+impl FromStr for BookingMethod {
+	type Err = BeanError;
+
+	fn from_str(s: &str) -> Result<Self> {
+		match s {
+			"STRICT" => Ok(Self::Strict),
+			"STRICT_WITH_SIZE" => Ok(Self::StrictWithSize),
+			"NONE" => Ok(Self::None),
+			"AVERAGE" => Ok(Self::Average),
+			"FIFO" => Ok(Self::FirstInFirstout),
+			"LIFO" => Ok(Self::LastInFirstOut),
+			"HIFO" => Ok(Self::HighestInFirstOut),
+			_ => Err(BeanError::InvalidBookingMethod(s.to_string())),
 		}
 	}
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl Display for BookingMethod {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::Strict => "STRICT",
+			Self::StrictWithSize => "STRICT_WITH_SIZE",
+			Self::None => "NONE",
+			Self::Average => "AVERAGE",
+			Self::FirstInFirstout => "FIFO",
+			Self::LastInFirstOut => "LIFO",
+			Self::HighestInFirstOut => "HIFO",
+		})
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Commodity(Rc<str>);
 
 impl FromStr for Commodity {
@@ -39,13 +69,19 @@ impl FromStr for Commodity {
 	}
 }
 
+impl Commodity {
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+}
+
 impl Display for Commodity {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		f.write_str(&self.0)
 	}
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Account(Rc<str>);
 
 impl FromStr for Account {
@@ -56,6 +92,26 @@ impl FromStr for Account {
 	}
 }
 
+impl Display for Account {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(&self.0)
+	}
+}
+
+impl Account {
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+
+	/// Checks if the other account is this account or one of its sub-accounts.
+	pub fn includes(&self, other: &Account) -> bool {
+		other
+			.0
+			.strip_prefix(&*self.0)
+			.is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+	}
+}
+
 impl From<Vec<String>> for Account {
 	fn from(v: Vec<String>) -> Self {
 		Self(Rc::from(v.join(":")))
@@ -68,46 +124,317 @@ impl From<Vec<&str>> for Account {
 	}
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Amount {
+	/// The number, keeping the scale it was written with.
 	number: Decimal,
 	commodity: Commodity,
+	/// Whether the number was written with thousands separators.
+	grouped: bool,
 }
 
 impl Amount {
 	pub fn new(number: Decimal, commodity: Commodity) -> Self {
-		Self { number, commodity }
+		Self {
+			number,
+			commodity,
+			grouped: false,
+		}
+	}
+
+	/// Sets whether the number should be rendered with thousands separators.
+	pub fn with_grouping(mut self, grouped: bool) -> Self {
+		self.grouped = grouped;
+		self
+	}
+
+	pub fn number(&self) -> Decimal {
+		self.number
+	}
+
+	pub fn commodity(&self) -> &Commodity {
+		&self.commodity
+	}
+
+	pub fn is_grouped(&self) -> bool {
+		self.grouped
+	}
+
+	pub fn zero(commodity: Commodity) -> Self {
+		Self::new(Decimal::ZERO, commodity)
+	}
+
+	pub fn is_zero(&self) -> bool {
+		self.number.is_zero()
+	}
+
+	pub fn is_positive(&self) -> bool {
+		self.number.is_sign_positive() && !self.number.is_zero()
+	}
+
+	pub fn is_negative(&self) -> bool {
+		self.number.is_sign_negative() && !self.number.is_zero()
+	}
+
+	pub fn abs(&self) -> Amount {
+		self.with_number(self.number.abs())
+	}
+
+	/// Rounds the number to a number of fractional digits, rounding half to
+	/// even like Beancount.
+	pub fn round_dp(&self, dp: u32) -> Amount {
+		self.with_number(
+			self.number
+				.round_dp_with_strategy(dp, RoundingStrategy::MidpointNearestEven),
+		)
+	}
+
+	/// Returns an amount of the same commodity and style with another number.
+	fn with_number(&self, number: Decimal) -> Amount {
+		Self {
+			number,
+			commodity: self.commodity.clone(),
+			grouped: self.grouped,
+		}
 	}
 
-	pub fn add(&self, rhs: &Amount) -> Result<Amount> {
+	fn check_commodity(&self, rhs: &Amount) -> Result<()> {
 		if self.commodity != rhs.commodity {
 			Err(BeanError::CommodityMismatch(
 				self.commodity.clone(),
 				rhs.commodity.clone(),
 			))
 		} else {
-			Ok(Amount::new(
-				self.number + rhs.number,
-				self.commodity.clone(),
-			))
+			Ok(())
 		}
 	}
 
-	pub fn sub(&self, rhs: &Amount) -> Result<Amount> {
-		if self.commodity != rhs.commodity {
-			Err(BeanError::CommodityMismatch(
-				self.commodity.clone(),
-				rhs.commodity.clone(),
-			))
+	/// Returns an amount with another number, or an error if it overflowed.
+	fn checked_number(&self, number: Option<Decimal>) -> Result<Amount> {
+		number
+			.map(|number| self.with_number(number))
+			.ok_or(BeanError::Overflow)
+	}
+
+	pub fn checked_add(&self, rhs: &Amount) -> Result<Amount> {
+		self.check_commodity(rhs)?;
+		self.checked_number(self.number.checked_add(rhs.number))
+	}
+
+	pub fn checked_sub(&self, rhs: &Amount) -> Result<Amount> {
+		self.check_commodity(rhs)?;
+		self.checked_number(self.number.checked_sub(rhs.number))
+	}
+
+	pub fn checked_mul(&self, number: Decimal) -> Result<Amount> {
+		self.checked_number(self.number.checked_mul(number))
+	}
+
+	pub fn checked_div(&self, number: Decimal) -> Result<Amount> {
+		if number.is_zero() {
+			Err(BeanError::DivisionByZero)
 		} else {
-			Ok(Amount::new(
-				self.number - rhs.number,
-				self.commodity.clone(),
-			))
+			self.checked_number(self.number.checked_div(number))
 		}
 	}
+}
+
+/// Adding amounts fails if their commodities don't match or the sum overflows.
+impl Add for &Amount {
+	type Output = Result<Amount>;
+
+	fn add(self, rhs: &Amount) -> Result<Amount> {
+		self.checked_add(rhs)
+	}
+}
+
+impl Add for Amount {
+	type Output = Result<Amount>;
+
+	fn add(self, rhs: Amount) -> Result<Amount> {
+		self.checked_add(&rhs)
+	}
+}
+
+/// Subtracting amounts fails if their commodities don't match or the
+/// difference overflows.
+impl Sub for &Amount {
+	type Output = Result<Amount>;
+
+	fn sub(self, rhs: &Amount) -> Result<Amount> {
+		self.checked_sub(rhs)
+	}
+}
+
+impl Sub for Amount {
+	type Output = Result<Amount>;
+
+	fn sub(self, rhs: Amount) -> Result<Amount> {
+		self.checked_sub(&rhs)
+	}
+}
+
+impl Neg for &Amount {
+	type Output = Amount;
+
+	fn neg(self) -> Amount {
+		self.with_number(-self.number)
+	}
+}
+
+impl Neg for Amount {
+	type Output = Amount;
+
+	fn neg(self) -> Amount {
+		-&self
+	}
+}
+
+/// Panics when the product overflows, use [`Amount::checked_mul`] otherwise.
+impl Mul<Decimal> for &Amount {
+	type Output = Amount;
+
+	fn mul(self, rhs: Decimal) -> Amount {
+		self.with_number(self.number * rhs)
+	}
+}
+
+impl Mul<Decimal> for Amount {
+	type Output = Amount;
+
+	fn mul(self, rhs: Decimal) -> Amount {
+		&self * rhs
+	}
+}
+
+/// Panics when dividing by zero, use [`Amount::checked_div`] otherwise.
+impl Div<Decimal> for &Amount {
+	type Output = Amount;
+
+	fn div(self, rhs: Decimal) -> Amount {
+		self.with_number(self.number / rhs)
+	}
+}
+
+impl Div<Decimal> for Amount {
+	type Output = Amount;
+
+	fn div(self, rhs: Decimal) -> Amount {
+		&self / rhs
+	}
+}
+
+/// Amounts can only be compared if they have the same commodity.
+impl PartialOrd for Amount {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		if self.commodity == other.commodity {
+			self.number.partial_cmp(&other.number)
+		} else {
+			None
+		}
+	}
+}
+
+/// Amounts are equal if they have the same value, regardless of how they were
+/// written.
+impl PartialEq for Amount {
+	fn eq(&self, other: &Self) -> bool {
+		self.number == other.number && self.commodity == other.commodity
+	}
+}
+
+impl Eq for Amount {}
+
+impl Hash for Amount {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.number.hash(state);
+		self.commodity.hash(state);
+	}
+}
+
+/// Renders the amount the way it was written in the source.
+impl Display for Amount {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"{} {}",
+			format_number(self.number, None, self.grouped),
+			self.commodity
+		)
+	}
+}
+
+/// Builds an amount from the text of its number and its commodity, for tests.
+#[cfg(test)]
+pub(crate) fn amount(number: &str, commodity: &str) -> Amount {
+	Amount::new(
+		Decimal::from_str(number).unwrap(),
+		commodity.parse().unwrap(),
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_booking_method() {
+		for s in [
+			"STRICT",
+			"STRICT_WITH_SIZE",
+			"NONE",
+			"AVERAGE",
+			"FIFO",
+			"LIFO",
+			"HIFO",
+		] {
+			assert_eq!(s.parse::<BookingMethod>().unwrap().to_string(), s);
+		}
+		assert!(matches!(
+			"FIFOO".parse::<BookingMethod>(),
+			Err(BeanError::InvalidBookingMethod(s)) if s == "FIFOO"
+		));
+	}
+
+	#[test]
+	fn test_amount_arithmetic() {
+		let d = |s| Decimal::from_str(s).unwrap();
+
+		assert_eq!(
+			(amount("1.50", "USD") + amount("2", "USD")).unwrap(),
+			amount("3.50", "USD")
+		);
+		assert_eq!(
+			(&amount("1.50", "USD") - &amount("2", "USD")).unwrap(),
+			amount("-0.50", "USD")
+		);
+		assert!(matches!(
+			amount("1", "USD") + amount("1", "CAD"),
+			Err(BeanError::CommodityMismatch(..))
+		));
+		assert_eq!(-amount("1.50", "USD"), amount("-1.50", "USD"));
+		assert_eq!(amount("1.50", "USD") * d("3"), amount("4.50", "USD"));
+		assert_eq!(amount("4.50", "USD") / d("3"), amount("1.50", "USD"));
+		assert!(matches!(
+			amount("1", "USD").checked_div(d("0")),
+			Err(BeanError::DivisionByZero)
+		));
+		assert!(matches!(
+			amount("1", "USD").checked_mul(Decimal::MAX),
+			Ok(product) if product.number() == Decimal::MAX
+		));
+		assert!(matches!(
+			amount("2", "USD").checked_mul(Decimal::MAX),
+			Err(BeanError::Overflow)
+		));
+
+		assert!(amount("1", "USD") < amount("2", "USD"));
+		assert_eq!(amount("1", "USD").partial_cmp(&amount("2", "CAD")), None);
 
-	pub fn mul(&self, number: Decimal) -> Amount {
-		Amount::new(self.number * number, self.commodity.clone())
+		assert_eq!(amount("2.345", "USD").round_dp(2), amount("2.34", "USD"));
+		assert_eq!(amount("-2.5", "USD").abs(), amount("2.5", "USD"));
+		assert!(amount("0.00", "USD").is_zero());
+		assert!(amount("-1", "USD").is_negative());
+		assert!(!amount("0", "USD").is_positive());
 	}
 }
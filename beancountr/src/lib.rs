@@ -1,8 +1,15 @@
 use rust_decimal::prelude::*;
 
+pub mod booking;
 pub mod core;
 pub mod loader;
 pub mod parser; // TODO: Change back to private
+pub mod plugins;
+pub mod query;
+pub mod realization;
+pub mod reports;
+pub mod summarize;
+pub mod validation;
 
 pub fn test() {
 	let a = Decimal::from_str("9000.00").unwrap();
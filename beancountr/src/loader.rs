@@ -0,0 +1,231 @@
+use std::collections::HashSet;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use chumsky::error::{Simple, SimpleReason};
+
+use crate::booking::book;
+use crate::core::{
+	directive::{Directive, SourceLocation},
+	display_context::DisplayContext,
+	error::Diagnostic,
+	options::Options,
+};
+use crate::parser::{parse_str, Statement};
+use crate::plugins::{PluginRegistry, FILE_PLUGINS};
+use crate::validation::{
+	validate_balance_assertions, validate_open_close, validate_transaction_balances,
+};
+
+/// A loaded ledger: its directives sorted and with their transactions booked,
+/// its options, and every problem found along the way.
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+	pub directives: Vec<Directive>,
+	pub options: Options,
+	pub errors: Vec<Diagnostic>,
+	/// How numbers are rendered, inferred from the way they were written.
+	pub display_context: DisplayContext,
+}
+
+/// Collects the statements of the input as they are loaded.
+#[derive(Default)]
+struct Loader {
+	directives: Vec<Directive>,
+	options: Options,
+	errors: Vec<Diagnostic>,
+	/// The plugins declared, with their config, in order.
+	plugins: Vec<(String, Option<String>)>,
+	/// Files already loaded, so include cycles are only loaded once.
+	loaded: HashSet<PathBuf>,
+}
+
+impl Loader {
+	/// Parses a source, loading its includes relative to `dir` if given.
+	/// Options are only taken from the top level file, like Beancount does.
+	fn load_source(&mut self, filename: Rc<str>, src: &str, dir: Option<&Path>, top_level: bool) {
+		let (statements, errors) = parse_str(filename.clone(), src);
+		self.errors.extend(
+			errors
+				.iter()
+				.map(|e| parse_diagnostic(filename.clone(), src, e)),
+		);
+
+		for statement in statements {
+			match statement {
+				Statement::Directive(directive) => self.directives.push(directive),
+				Statement::Option(key, value) if top_level => {
+					if let Err(e) = self.options.set(&key, &value) {
+						self.errors.push(Diagnostic::new(e.to_string()));
+					}
+				}
+				Statement::Option(key, _) => self.errors.push(Diagnostic::new(format!(
+					"Option \"{}\" is ignored in included file {}",
+					key, filename
+				))),
+				Statement::Plugin(name, config) => {
+					// Plugin files are found relative to the file declaring them
+					let name = match (name.split_once(':'), dir) {
+						(Some((kind, path)), Some(dir))
+							if FILE_PLUGINS.contains(&kind) && Path::new(path).is_relative() =>
+						{
+							format!("{}:{}", kind, dir.join(path).display())
+						}
+						_ => name,
+					};
+					self.plugins.push((name, config))
+				}
+				Statement::Include(path) => match dir {
+					Some(dir) => self.load_file(&dir.join(path), false),
+					None => self.errors.push(Diagnostic::new(format!(
+						"Cannot include \"{}\" from {}, it isn't a file",
+						path, filename
+					))),
+				},
+				_ => {}
+			}
+		}
+	}
+
+	fn load_file(&mut self, path: &Path, top_level: bool) {
+		let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+		if !self.loaded.insert(canonical) {
+			return;
+		}
+		match fs::read_to_string(path) {
+			Ok(src) => self.load_source(
+				Rc::from(path.to_string_lossy()),
+				&src,
+				path.parent(),
+				top_level,
+			),
+			Err(e) => self.errors.push(Diagnostic::new(format!(
+				"Could not read {}: {}",
+				path.display(),
+				e
+			))),
+		}
+	}
+
+	/// Books the directives, runs the plugins on them and validates them, along
+	/// with the custom directives of the types registered.
+	fn finish(mut self, registry: &PluginRegistry) -> Ledger {
+		// Only the numbers of the input are used, not the ones computed later
+		let display_context = DisplayContext::from_directives(&self.directives);
+		let (directives, errors) = book(self.directives, &self.options);
+		self.errors.extend(errors);
+		let (directives, errors) = registry.run(directives, &self.options, &self.plugins);
+		self.errors.extend(errors);
+		self.errors.extend(validate_open_close(&directives));
+		self.errors
+			.extend(validate_transaction_balances(&directives, &self.options));
+		self.errors
+			.extend(validate_balance_assertions(&directives, &self.options));
+		self.errors
+			.extend(registry.custom_schemas().validate(&directives));
+		Ledger {
+			directives,
+			options: self.options,
+			errors: self.errors,
+			display_context,
+		}
+	}
+}
+
+/// Loads a ledger from a string. Includes can't be resolved without a file,
+/// and are reported as errors.
+pub fn load_str(filename: Rc<str>, src: &str) -> Ledger {
+	load_str_with(filename, src, &PluginRegistry::builtins())
+}
+
+/// Loads a ledger from a string, with the plugins of a registry.
+pub fn load_str_with(filename: Rc<str>, src: &str, registry: &PluginRegistry) -> Ledger {
+	let mut loader = Loader::default();
+	loader.load_source(filename, src, None, true);
+	loader.finish(registry)
+}
+
+/// Loads a ledger from a file, along with the files it includes.
+pub fn load_file(path: impl AsRef<Path>) -> Ledger {
+	load_file_with(path, &PluginRegistry::builtins())
+}
+
+/// Loads a ledger from a file, with the plugins of a registry.
+pub fn load_file_with(path: impl AsRef<Path>, registry: &PluginRegistry) -> Ledger {
+	let mut loader = Loader::default();
+	loader.load_file(path.as_ref(), true);
+	loader.finish(registry)
+}
+
+/// Turns a parse error into a diagnostic at the line it was found on.
+fn parse_diagnostic(filename: Rc<str>, src: &str, error: &Simple<String>) -> Diagnostic {
+	let span: Range<usize> = error.span();
+	let lineno = src.chars().take(span.start).filter(|c| *c == '\n').count() + 1;
+
+	let message = match error.reason() {
+		SimpleReason::Custom(message) => message.clone(),
+		SimpleReason::Unclosed { delimiter, .. } => format!("Unclosed delimiter {}", delimiter),
+		SimpleReason::Unexpected => {
+			let mut expected: Vec<String> = error
+				.expected()
+				.map(|e| e.clone().unwrap_or_else(|| "end of input".to_string()))
+				.collect();
+			expected.sort();
+			let found = error
+				.found()
+				.cloned()
+				.unwrap_or_else(|| "end of input".to_string());
+			match expected.is_empty() {
+				true => format!("Unexpected {}", found),
+				false => format!("Unexpected {}, expected {}", found, expected.join(", ")),
+			}
+		}
+	};
+
+	Diagnostic {
+		message,
+		location: Some(SourceLocation {
+			filename,
+			lineno,
+			span,
+		}),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_load_str() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			option "title" "Test"
+			option "booking_methd" "FIFO"
+			include "other.beancount"
+			2025-01-01 open Assets:Cash
+			2025-01-02 open Assets:Bank )
+			2025-01-03 * "Unbalanced"
+				Assets:Cash 10.00 USD
+				Expenses:Food -9.00 USD
+			"#,
+		);
+
+		assert_eq!(ledger.options.title, "Test");
+		assert_eq!(ledger.directives.len(), 2);
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			errors,
+			vec![
+				"test:6: Unexpected ), expected \\n, end of input",
+				"Invalid option \"booking_methd\": unknown option",
+				"Cannot include \"other.beancount\" from test, it isn't a file",
+				"test:7: Invalid reference to unknown account 'Expenses:Food'",
+				"test:7: Transaction does not balance: (1.00 USD)",
+			]
+		);
+	}
+}
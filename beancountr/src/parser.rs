@@ -1,5 +1,11 @@
-use crate::core::directive::{Directive, DirectiveKind, Metadata, MetadataMap, Posting};
-use crate::core::types::{Account, Amount, Commodity};
+// chumsky's `select!` closures return its large `Simple` error type
+#![allow(clippy::result_large_err)]
+
+use crate::core::directive::{
+	Directive, DirectiveKind, Metadata, MetadataMap, Posting, SourceLocation,
+};
+use crate::core::position::{CostOrSpec, CostSpec};
+use crate::core::types::{Account, Amount, BookingMethod, Commodity};
 use ariadne::{sources, Color, Fmt, Label, Report, ReportKind};
 use chrono::{Datelike, NaiveDate};
 use chumsky::{error::Simple, text, Parser};
@@ -36,7 +42,7 @@ pub enum Token {
 
 	// Literals
 	Date(NaiveDate),
-	Decimal(Decimal), // ([0-9]+|[0-9][0-9,]+[0-9])(\.[0-9]*)?
+	Decimal(Decimal, bool), // ([0-9]+|[0-9][0-9,]+[0-9])(\.[0-9]*)?, and if it used commas
 	String(String),
 	Bool(bool), // true, false
 	Null,       // None
@@ -96,7 +102,7 @@ impl fmt::Display for Token {
 			Token::PushTag => write!(f, "pushtag"),
 			Token::PopTag => write!(f, "poptag"),
 			Token::Date(d) => write!(f, "{}-{:02}-{:02}", d.year(), d.month0() + 1, d.day0() + 1),
-			Token::Decimal(d) => write!(f, "{}", d),
+			Token::Decimal(d, _) => write!(f, "{}", d),
 			Token::String(s) => write!(f, "\"{}\"", s),
 			Token::Bool(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
 			Token::Null => write!(f, "NULL"),
@@ -165,12 +171,12 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Range<usize>)>, Error = Simple<c
 	))
 	.boxed();
 
-	let four_digits = filter(move |c: &char| c.is_digit(10))
+	let four_digits = filter(move |c: &char| c.is_ascii_digit())
 		.repeated()
 		.exactly(4)
 		.collect::<String>()
 		.map(|s| s.parse::<i32>().unwrap());
-	let two_digits = filter(move |c: &char| c.is_digit(10))
+	let two_digits = filter(move |c: &char| c.is_ascii_digit())
 		.repeated()
 		.exactly(2)
 		.collect::<String>()
@@ -221,20 +227,20 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Range<usize>)>, Error = Simple<c
 
 	let null = text::keyword("NULL").to(Token::Null);
 
-	let number = filter(|c: &char| c.is_digit(10))
-		.chain(filter(|c: &char| c.is_digit(10) || *c == ',').repeated())
+	let number = filter(|c: &char| c.is_ascii_digit())
+		.chain(filter(|c: &char| c.is_ascii_digit() || *c == ',').repeated())
 		.chain::<char, _, _>(
 			just('.')
-				.chain(filter(|c: &char| c.is_digit(10)).repeated())
+				.chain(filter(|c: &char| c.is_ascii_digit()).repeated())
 				.or_not()
 				.flatten(),
 		)
 		.collect::<String>()
 		.try_map(|s, span| {
 			Decimal::from_str(&s.replace(",", ""))
+				.map(|d| Token::Decimal(d, s.contains(',')))
 				.map_err(|e| Simple::custom(span, format!("Error parsing decimal: {}", e)))
 		})
-		.map(Token::Decimal)
 		.boxed();
 
 	// ACCOUNTTYPE = ([A-Z]|{UTF-8-ONLY})([A-Za-z0-9\-]|{UTF-8-ONLY})*
@@ -242,7 +248,7 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Range<usize>)>, Error = Simple<c
 		.chain(filter(|c: &char| c.is_alphanumeric() || *c == '-').repeated())
 		.collect::<String>();
 	// ACCOUNTNAME = ([A-Z0-9]|{UTF-8-ONLY})([A-Za-z0-9\-]|{UTF-8-ONLY})*
-	let account_name = filter(|c: &char| is_uppercase_or_caseless(*c) || c.is_digit(10))
+	let account_name = filter(|c: &char| is_uppercase_or_caseless(*c) || c.is_ascii_digit())
 		.chain(filter(|c: &char| c.is_alphanumeric() || *c == '-').repeated())
 		.collect::<String>();
 	// {ACCOUNTTYPE}(:{ACCOUNTNAME})+
@@ -262,7 +268,7 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Range<usize>)>, Error = Simple<c
 		.chain(
 			filter(|c: &char| {
 				is_uppercase_or_caseless(*c)
-					|| c.is_digit(10)
+					|| c.is_ascii_digit()
 					|| *c == '.' || *c == '_'
 					|| *c == '-' || *c == '\''
 			})
@@ -274,7 +280,7 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Range<usize>)>, Error = Simple<c
 		.chain(
 			filter(|c: &char| {
 				is_uppercase_or_caseless(*c)
-					|| c.is_digit(10)
+					|| c.is_ascii_digit()
 					|| *c == '.' || *c == '_'
 					|| *c == '-' || *c == '\''
 			})
@@ -296,7 +302,7 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Range<usize>)>, Error = Simple<c
 	let commodity = commodity_no_slash
 		.or(commodity_slash)
 		.try_map(|s, span| {
-			if s.ends_with(|c| is_uppercase_or_caseless(c) || c.is_digit(10)) {
+			if s.ends_with(|c| is_uppercase_or_caseless(c) || c.is_ascii_digit()) {
 				Ok(s)
 			} else {
 				Err(Simple::custom(
@@ -360,12 +366,18 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Range<usize>)>, Error = Simple<c
 		just('+').to(Token::Plus),
 		just('|').to(Token::Pipe),
 		just('~').to(Token::Tilde),
+		just('&').to(Token::Ampersand),
+		just('#').to(Token::Hash),
+		just('?').to(Token::Question),
+		just('%').to(Token::Percent),
 	))
 	.boxed();
 
 	let newline = text::newline().map(|_| Token::Newline).boxed();
 
+	// Keys are tried first so a key such as "price:" isn't lexed as a keyword
 	let token = choice((
+		key,
 		directive,
 		command,
 		date,
@@ -378,7 +390,6 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Range<usize>)>, Error = Simple<c
 		capital,
 		tag,
 		link,
-		key,
 		punctuation,
 		newline,
 	))
@@ -395,9 +406,15 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Range<usize>)>, Error = Simple<c
 }
 
 pub fn expr_parser() -> impl Parser<Token, Decimal, Error = Simple<Token>> {
+	expr_with_grouping_parser().map(|(number, _)| number)
+}
+
+/// Parses an arithmetic expression, also returning whether any of its numbers
+/// were written with thousands separators.
+pub fn expr_with_grouping_parser() -> impl Parser<Token, (Decimal, bool), Error = Simple<Token>> {
 	recursive(|expr| {
 		let number = select! {
-			Token::Decimal(d) => d,
+			Token::Decimal(d, grouped) => (d, grouped),
 		};
 
 		let atom = number.or(expr.delimited_by(just(Token::LeftParen), just(Token::RightParen)));
@@ -408,9 +425,9 @@ pub fn expr_parser() -> impl Parser<Token, Decimal, Error = Simple<Token>> {
 			.or(just(Token::Plus))
 			.repeated()
 			.then(atom)
-			.foldr(|op, n| match op {
-				Token::Minus => -n,
-				Token::Plus => n,
+			.foldr(|op, (n, grouped)| match op {
+				Token::Minus => (-n, grouped),
+				Token::Plus => (n, grouped),
 				_ => unreachable!(),
 			})
 			.boxed();
@@ -423,14 +440,17 @@ pub fn expr_parser() -> impl Parser<Token, Decimal, Error = Simple<Token>> {
 					.then(unary)
 					.repeated(),
 			)
-			.foldl(|lhs, (op, rhs)| match op {
-				Token::Asterisk => lhs * rhs,
-				Token::Slash => lhs / rhs,
-				_ => unreachable!(),
+			.foldl(|(lhs, lhs_grouped), (op, (rhs, rhs_grouped))| {
+				let number = match op {
+					Token::Asterisk => lhs * rhs,
+					Token::Slash => lhs / rhs,
+					_ => unreachable!(),
+				};
+				(number, lhs_grouped || rhs_grouped)
 			})
 			.boxed();
 
-		let sum = product
+		product
 			.clone()
 			.then(
 				just(Token::Plus)
@@ -438,16 +458,19 @@ pub fn expr_parser() -> impl Parser<Token, Decimal, Error = Simple<Token>> {
 					.then(product)
 					.repeated(),
 			)
-			.foldl(|lhs, (op, rhs)| match op {
-				Token::Plus => lhs + rhs,
-				Token::Minus => lhs - rhs,
-				_ => unreachable!(),
-			});
-
-		sum
+			.foldl(|(lhs, lhs_grouped), (op, (rhs, rhs_grouped))| {
+				let number = match op {
+					Token::Plus => lhs + rhs,
+					Token::Minus => lhs - rhs,
+					_ => unreachable!(),
+				};
+				(number, lhs_grouped || rhs_grouped)
+			})
 	})
 }
 
+// Nearly every statement is a directive, so boxing them wouldn't save anything
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
 	Option(String, String),
@@ -489,22 +512,35 @@ pub fn parser<F: Fn(usize) -> usize>(
 
 	let commodity_list = commodity.separated_by(just(Token::Comma));
 
-	let amount = expr_parser()
+	let amount = expr_with_grouping_parser()
 		.then(commodity)
-		.map(|(number, commodity)| Amount::new(number, commodity))
+		.map(|((number, grouped), commodity)| Amount::new(number, commodity).with_grouping(grouped))
 		.boxed();
 
-	let amount_tolerance = amount.clone().map(|amount| (amount, None)).or(expr_parser()
-		.then_ignore(just(Token::Tilde))
-		.then(expr_parser())
-		.then(commodity)
-		.map(|((number, tolerance), commodity)| (Amount::new(number, commodity), Some(tolerance)))
-		.boxed());
+	let amount_tolerance =
+		amount
+			.clone()
+			.map(|amount| (amount, None))
+			.or(expr_with_grouping_parser()
+				.then_ignore(just(Token::Tilde))
+				.then(expr_parser())
+				.then(commodity)
+				.map(|(((number, grouped), tolerance), commodity)| {
+					(
+						Amount::new(number, commodity).with_grouping(grouped),
+						Some(tolerance),
+					)
+				})
+				.boxed());
 
 	let tag = select! {
 		Token::Tag(s) => s,
 	};
 
+	let link = select! {
+		Token::Link(s) => s,
+	};
+
 	let bool_ = select! {
 		Token::Bool(b) => b,
 	};
@@ -515,6 +551,7 @@ pub fn parser<F: Fn(usize) -> usize>(
 		.or(date.map(Metadata::Date))
 		.or(commodity.map(Metadata::Commodity))
 		.or(tag.map(Metadata::Tags))
+		.or(link.map(Metadata::Link))
 		.or(bool_.map(Metadata::Bool))
 		.or(just(Token::Null).to(Metadata::None))
 		.or(amount.clone().map(Metadata::Amount))
@@ -587,13 +624,21 @@ pub fn parser<F: Fn(usize) -> usize>(
 		.then(string.or_not())
 		.then_ignore(end_of_line.clone())
 		.then(metadata.clone().or_not())
-		.map(|((((date, account), commodities), booking_method), meta)| {
-			Statement::Directive(Directive::new(
-				date,
-				DirectiveKind::Open(account, commodities, booking_method.map(|s| s.into())),
-				meta.unwrap_or_default(),
-			))
-		})
+		.validate(
+			|((((date, account), commodities), booking_method), meta), span, emit| {
+				// An invalid booking method is reported, but the account is still opened
+				let booking_method = booking_method.and_then(|s| {
+					s.parse::<BookingMethod>()
+						.map_err(|e| emit(Simple::custom(span, e)))
+						.ok()
+				});
+				Statement::Directive(Directive::new(
+					date,
+					DirectiveKind::Open(account, commodities, booking_method),
+					meta.unwrap_or_default(),
+				))
+			},
+		)
 		.boxed();
 
 	let close_directive = date
@@ -679,21 +724,124 @@ pub fn parser<F: Fn(usize) -> usize>(
 		.boxed();
 
 	enum PostingOrMetadata {
-		Posting(Posting),
+		Posting(Box<Posting>),
 		Metadata(String, Metadata),
 	}
 
+	#[derive(Clone)]
+	enum CostComp {
+		Amount(Option<Decimal>, Option<Decimal>, Option<Commodity>),
+		Date(NaiveDate),
+		Label(String),
+		Merge,
+	}
+
+	// A compound amount has the form "<per> # <total> <commodity>", where every
+	// part is optional
+	let compound_amount = expr_parser()
+		.or_not()
+		.then(
+			just(Token::Hash)
+				.ignore_then(expr_parser().or_not())
+				.or_not(),
+		)
+		.then(commodity.or_not())
+		.map(|((per, total), commodity)| CostComp::Amount(per, total.flatten(), commodity));
+
+	let cost_comp_list = choice((
+		date.map(CostComp::Date),
+		string.map(CostComp::Label),
+		just(Token::Asterisk).to(CostComp::Merge),
+		compound_amount,
+	))
+	.separated_by(just(Token::Comma))
+	.boxed();
+
+	let cost_spec = cost_comp_list
+		.clone()
+		.delimited_by(just(Token::LeftCurl), just(Token::RightCurl))
+		.map(|comps| (comps, false))
+		.or(cost_comp_list
+			.delimited_by(just(Token::LeftCurlCurl), just(Token::RightCurlCurl))
+			.map(|comps| (comps, true)))
+		.map(|(comps, is_total)| {
+			let mut number_per = None;
+			let mut number_total = None;
+			let mut commodity = None;
+			let mut date = None;
+			let mut label = None;
+			let mut merge = None;
+			for comp in comps {
+				match comp {
+					// Inside double braces, the single number is the total cost
+					CostComp::Amount(per, total, c) if is_total => {
+						number_total = per.or(total).or(number_total);
+						commodity = c.or(commodity);
+					}
+					CostComp::Amount(per, total, c) => {
+						number_per = per.or(number_per);
+						number_total = total.or(number_total);
+						commodity = c.or(commodity);
+					}
+					CostComp::Date(d) => date = Some(d),
+					CostComp::Label(l) => label = Some(Rc::from(l)),
+					CostComp::Merge => merge = Some(true),
+				}
+			}
+			CostOrSpec::Spec(CostSpec::new(
+				number_per,
+				number_total,
+				commodity,
+				date,
+				label,
+				merge,
+			))
+		})
+		.labelled("cost")
+		.boxed();
+
+	let price = just(Token::AtAt)
+		.to(true)
+		.or(just(Token::At).to(false))
+		.then(expr_with_grouping_parser())
+		.then(commodity)
+		.labelled("price")
+		.boxed();
+
 	let posting = flag
 		.clone()
 		.or_not()
 		.then(account)
+		.then(amount.clone().or_not())
+		.then(cost_spec.or_not())
+		.then(price.or_not())
 		.then_ignore(end_of_line.clone())
-		.map(|(flag, account)| {
-			Posting::new(account, None, None, None, flag, MetadataMap::default())
+		.map(|((((flag, account), units), cost), price)| {
+			let price = price.map(|((is_total, (number, grouped)), commodity)| {
+				(
+					is_total,
+					Amount::new(number, commodity).with_grouping(grouped),
+				)
+			});
+			match price {
+				// The total is kept as written, the per-unit price is derived from it
+				Some((true, total)) => {
+					Posting::new(account, units, cost, None, flag, MetadataMap::default())
+						.with_total_price(total)
+				}
+				price => Posting::new(
+					account,
+					units,
+					cost,
+					price.map(|(_, price)| price),
+					flag,
+					MetadataMap::default(),
+				),
+			}
 		});
 
 	let posting_or_metadata = posting
-		.map(PostingOrMetadata::Posting)
+		.map(|posting| PostingOrMetadata::Posting(Box::new(posting)))
 		.or(metadata_line
 			.clone()
 			.map(|(key, value)| PostingOrMetadata::Metadata(key, value)))
@@ -703,9 +851,10 @@ pub fn parser<F: Fn(usize) -> usize>(
 		.then(flag)
 		.then(string.or_not())
 		.then(string.or_not())
+		.then(tags_links.clone())
 		.then_ignore(end_of_line.clone())
 		.then(posting_or_metadata.repeated())
-		.map(|((((date, flag), str_a), str_b), other)| {
+		.map(|(((((date, flag), str_a), str_b), (tags, links)), other)| {
 			// If both are present, the first is the payee and the second is the narration
 			// If only the first is present, it is the narration
 			let (payee, narration) = match (str_a, str_b) {
@@ -724,7 +873,7 @@ pub fn parser<F: Fn(usize) -> usize>(
 						if let Some(current) = current_posting.take() {
 							postings.push(current);
 						}
-						current_posting = Some(p);
+						current_posting = Some(*p);
 					}
 					// If the item is a metadata, we insert it into the current posting
 					PostingOrMetadata::Metadata(k, v) => {
@@ -750,8 +899,8 @@ pub fn parser<F: Fn(usize) -> usize>(
 					flag: Some(flag),
 					payee,
 					narration,
-					tags: HashSet::default(),
-					links: HashSet::default(),
+					tags,
+					links,
 					postings,
 				},
 				tx_meta,
@@ -874,18 +1023,11 @@ pub fn parser<F: Fn(usize) -> usize>(
 		custom_directive,
 	))
 	.map_with_span(move |stmt, span: Range<usize>| match stmt {
-		Statement::Directive(d) => {
-			let mut meta = d.meta;
-			meta.insert(
-				"filename".to_string(),
-				Metadata::String(filename.to_string()),
-			);
-			meta.insert(
-				"lineno".to_string(),
-				Metadata::Number(Decimal::from(line_lookup(span.start))),
-			);
-			Statement::Directive(Directive::new(d.date, d.kind, meta))
-		}
+		Statement::Directive(d) => Statement::Directive(d.with_location(SourceLocation {
+			filename: filename.clone(),
+			lineno: line_lookup(span.start),
+			span,
+		})),
 		_ => stmt,
 	});
 
@@ -894,54 +1036,127 @@ pub fn parser<F: Fn(usize) -> usize>(
 	statement
 		.padded_by(just(Token::Newline).repeated())
 		.repeated()
+		.padded_by(just(Token::Newline).repeated())
 		.then_ignore(end())
 }
 
-/// Parses a string and returns a vector of statements and a vector of errors.
-pub fn parse_str(filename: Rc<str>, src: &str) -> (Option<Vec<Statement>>, Vec<Simple<String>>) {
-	// Create a line number lookup table
-	let mut line_map = BTreeMap::new();
-	let mut line = 1;
-	for (i, _) in src.match_indices('\n') {
-		line_map.insert(i, line);
-		line += 1;
+/// Checks if a token can begin a new entry, that is a dated directive or a
+/// command, when it is the first token on a line.
+fn starts_entry(token: &Token) -> bool {
+	matches!(
+		token,
+		Token::Date(_)
+			| Token::Option
+			| Token::Plugin
+			| Token::Include
+			| Token::PushTag
+			| Token::PopTag
+	)
+}
+
+type SpannedToken = (Token, Range<usize>);
+
+/// Lexes a source into tokens. When the lexer can't recover from an error,
+/// each line is lexed on its own instead so only the tokens of the broken lines
+/// are lost.
+fn lex(src: &str) -> (Vec<SpannedToken>, Vec<Simple<char>>) {
+	let lexer = lexer();
+	let (tokens, errs) = lexer.parse_recovery(src);
+	if let Some(tokens) = tokens {
+		return (tokens, errs);
+	}
+
+	let mut tokens = vec![];
+	let mut errors = vec![];
+	let mut offset = 0;
+	for line in src.split_inclusive('\n') {
+		let len = line.chars().count();
+		let end = offset + len;
+		// Spans are kept relative to the whole source
+		let chars = line
+			.chars()
+			.enumerate()
+			.map(|(i, c)| (c, offset + i..offset + i + 1));
+		let (line_tokens, errs) = lexer.parse_recovery(Stream::from_iter(end..end + 1, chars));
+		match line_tokens {
+			Some(mut line_tokens) => tokens.append(&mut line_tokens),
+			// Keep the line break so the next entry is still split correctly
+			None if line.ends_with('\n') => tokens.push((Token::Newline, end - 1..end)),
+			None => {}
+		}
+		errors.extend(errs);
+		offset = end;
 	}
+	(tokens, errors)
+}
+
+/// Splits a token stream into chunks that each hold a single entry. A new chunk
+/// is started at every line that begins with a date or a command keyword, so a
+/// malformed entry can be skipped without affecting the ones around it.
+fn split_entries(tokens: Vec<(Token, Range<usize>)>) -> Vec<Vec<(Token, Range<usize>)>> {
+	let mut entries = vec![];
+	let mut current = vec![];
+	let mut at_line_start = true;
+	for (token, span) in tokens {
+		if at_line_start && starts_entry(&token) && !current.is_empty() {
+			entries.push(std::mem::take(&mut current));
+		}
+		at_line_start = token == Token::Newline;
+		current.push((token, span));
+	}
+	if !current.is_empty() {
+		entries.push(current);
+	}
+	entries
+}
+
+/// Parses a string and returns a vector of statements and a vector of errors.
+///
+/// Each entry is parsed on its own, so an entry that fails to parse produces a
+/// single error and is skipped while all the valid entries are still returned.
+pub fn parse_str(filename: Rc<str>, src: &str) -> (Vec<Statement>, Vec<Simple<String>>) {
+	// Map the position of each newline to the number of newlines up to it,
+	// spans are in characters not bytes
+	let line_map: BTreeMap<usize, usize> = src
+		.chars()
+		.enumerate()
+		.filter(|(_, c)| *c == '\n')
+		.enumerate()
+		.map(|(line, (i, _))| (i, line + 1))
+		.collect();
 
 	let line_lookup = |pos: usize| -> usize {
-		// Get the line number for the given position
+		// The line of a position is one past the number of newlines before it
 		line_map
-			.range(..=pos)
+			.range(..pos)
 			.next_back()
-			.map(|(_, &line)| line)
+			.map(|(_, &newlines)| newlines + 1)
 			.unwrap_or(1)
 	};
 
-	let (tokens, errs) = lexer().parse_recovery(src);
-
-	if let Some(tokens) = tokens.clone() {
-		println!("Tokens:");
-		for (token, _) in tokens {
-			println!("- {:?}", token);
+	let (tokens, errs) = lex(src);
+	let mut errors: Vec<Simple<String>> =
+		errs.into_iter().map(|e| e.map(|c| c.to_string())).collect();
+
+	let parser = parser(filename, line_lookup);
+	let mut statements = vec![];
+	for entry in split_entries(tokens) {
+		let end = entry.last().map(|(_, span)| span.end).unwrap_or_default();
+		let (parsed, errs) =
+			parser.parse_recovery(Stream::from_iter(end..end + 1, entry.into_iter()));
+		let errs = errs.into_iter().map(|e| e.map(|tok| tok.to_string()));
+		match parsed {
+			// The entry was parsed but some of its values are invalid
+			Some(mut parsed) => {
+				statements.append(&mut parsed);
+				errors.extend(errs);
+			}
+			// Only the first error is reported, the rest are usually caused by it
+			None => errors.extend(errs.take(1)),
 		}
 	}
 
-	let (statements, parse_errs) = if let Some(tokens) = tokens {
-		let len = src.chars().count();
-		let (statements, parse_errs) = parser(filename, line_lookup)
-			.parse_recovery(Stream::from_iter(len..len + 1, tokens.into_iter()));
-
-		(statements, parse_errs)
-	} else {
-		(None, Vec::new())
-	};
-
-	(
-		statements,
-		errs.into_iter()
-			.map(|e| e.map(|c| c.to_string()))
-			.chain(parse_errs.into_iter().map(|e| e.map(|tok| tok.to_string())))
-			.collect(),
-	)
+	(statements, errors)
 }
 
 /// Prints the errors to the console
@@ -993,7 +1208,7 @@ pub fn print_errors(filename: Rc<str>, src: &str, errors: Vec<Simple<String>>) {
 							.collect::<Vec<_>>()
 							.join(", ")
 					},
-					e.label().unwrap_or(&"input".to_string()),
+					e.label().unwrap_or("input"),
 				))
 				.with_label(
 					Label::new((filename.clone(), e.span()))
@@ -1021,12 +1236,22 @@ pub fn print_errors(filename: Rc<str>, src: &str, errors: Vec<Simple<String>>) {
 
 #[cfg(test)]
 mod tests {
-	use std::collections::{HashMap, HashSet};
+	use std::collections::HashSet;
 
 	use crate::core::types::Amount;
 
 	use super::*;
 
+	fn linenos(statements: &[Statement]) -> Vec<usize> {
+		statements
+			.iter()
+			.filter_map(|statement| match statement {
+				Statement::Directive(d) => d.location.as_ref().map(|l| l.lineno),
+				_ => None,
+			})
+			.collect()
+	}
+
 	#[test]
 	fn test_uppercase() {
 		// Basic ascii
@@ -1135,14 +1360,14 @@ mod tests {
 				Token::String("Hello\tWorld!".to_string()),
 				Token::String("Unicode: κόσμε".to_string()),
 				Token::String("Emojis: 😁".to_string()),
-				Token::Decimal(Decimal::from_str("2.0200").unwrap()),
+				Token::Decimal(Decimal::from_str("2.0200").unwrap(), false),
 				Token::Newline,
-				Token::Decimal(Decimal::from_str("58979323846264338.32").unwrap()),
-				Token::Decimal(Decimal::from_str("65535").unwrap()),
-				Token::Decimal(Decimal::from_str("0.00000097").unwrap()),
+				Token::Decimal(Decimal::from_str("58979323846264338.32").unwrap(), false),
+				Token::Decimal(Decimal::from_str("65535").unwrap(), false),
+				Token::Decimal(Decimal::from_str("0.00000097").unwrap(), false),
 				Token::Minus,
-				Token::Decimal(Decimal::from_str("3.14").unwrap()),
-				Token::Decimal(Decimal::from_str("1234567.89").unwrap()),
+				Token::Decimal(Decimal::from_str("3.14").unwrap(), false),
+				Token::Decimal(Decimal::from_str("1234567.89").unwrap(), true),
 				Token::Newline,
 				Token::Bool(true),
 				Token::Bool(false),
@@ -1205,7 +1430,7 @@ mod tests {
 
 	#[test]
 	fn test_lexer_punctuation() {
-		let src = ", @@ @ ! * ( ) { } / - + | ~";
+		let src = ", @@ @ ! * ( ) { } / - + | ~ & # ? %";
 
 		let tokens: Vec<Token> = lexer()
 			.parse(src)
@@ -1230,6 +1455,10 @@ mod tests {
 				Token::Plus,
 				Token::Pipe,
 				Token::Tilde,
+				Token::Ampersand,
+				Token::Hash,
+				Token::Question,
+				Token::Percent,
 			]
 		);
 	}
@@ -1291,7 +1520,7 @@ mod tests {
 		let (statements, _errors) = parse_str(filename.clone(), src);
 
 		assert_eq!(
-			statements.unwrap(),
+			statements,
 			vec![
 				Statement::Option("title".to_string(), "My Beancount File".to_string()),
 				Statement::Plugin("beancount.plugins.example".to_string(), None),
@@ -1305,32 +1534,19 @@ mod tests {
 					DirectiveKind::Open(
 						"Assets:US:B-of-A:Checking".parse().unwrap(),
 						vec!["USD".parse().unwrap(), "CAD".parse().unwrap()],
-						Some("NONE".to_string().into())
+						Some(BookingMethod::None)
 					),
-					HashMap::from([
-						("filename".to_string(), Metadata::String("test".to_string())),
-						("lineno".to_string(), Metadata::Number(Decimal::from(5))),
-						(
-							"value".to_string(),
-							Metadata::Number(Decimal::from_str("123.456").unwrap())
-						),
-					]),
+					MetadataMap::from([("value", Metadata::Number(Decimal::from_str("123.456").unwrap()))]),
 				)),
 				Statement::Directive(Directive::new(
 					date,
 					DirectiveKind::Close("Assets:US:B-of-A:Checking".parse().unwrap()),
-					HashMap::from([
-						("filename".to_string(), Metadata::String("test".to_string())),
-						("lineno".to_string(), Metadata::Number(Decimal::from(7))),
-					]),
+					MetadataMap::new(),
 				)),
 				Statement::Directive(Directive::new(
 					date,
 					DirectiveKind::Commodity("AAPL".parse().unwrap()),
-					HashMap::from([
-						("filename".to_string(), Metadata::String("test".to_string())),
-						("lineno".to_string(), Metadata::Number(Decimal::from(8))),
-					]),
+					MetadataMap::new(),
 				)),
 				Statement::Directive(Directive::new(
 					date,
@@ -1338,10 +1554,7 @@ mod tests {
 						account: "Assets:US".parse().unwrap(),
 						source_account: "Equity:Opening-Balances".parse().unwrap(),
 					},
-					HashMap::from([
-						("filename".to_string(), Metadata::String("test".to_string())),
-						("lineno".to_string(), Metadata::Number(Decimal::from(9))),
-					]),
+					MetadataMap::new(),
 				)),
 				Statement::Directive(Directive::new(
 					date,
@@ -1354,10 +1567,7 @@ mod tests {
 						tolerance: None,
 						diff_amount: None,
 					},
-					HashMap::from([
-						("filename".to_string(), Metadata::String("test".to_string())),
-						("lineno".to_string(), Metadata::Number(Decimal::from(10))),
-					]),
+					MetadataMap::new(),
 				)),
 				Statement::Directive(Directive::new(
 					date,
@@ -1370,10 +1580,7 @@ mod tests {
 						tolerance: Some(Decimal::from_str("0.002").unwrap()),
 						diff_amount: None,
 					},
-					HashMap::from([
-						("filename".to_string(), Metadata::String("test".to_string())),
-						("lineno".to_string(), Metadata::Number(Decimal::from(11))),
-					]),
+					MetadataMap::new(),
 				)),
 				Statement::Directive(Directive::new(
 					date,
@@ -1383,10 +1590,7 @@ mod tests {
 						tags: HashSet::new(),
 						links: HashSet::new(),
 					},
-					HashMap::from([
-						("filename".to_string(), Metadata::String("test".to_string())),
-						("lineno".to_string(), Metadata::Number(Decimal::from(12))),
-					]),
+					MetadataMap::new(),
 				)),
 				Statement::Directive(Directive::new(
 					date,
@@ -1396,10 +1600,7 @@ mod tests {
 						tags: HashSet::from(["test-tag".to_string()]),
 						links: HashSet::from(["test-link".to_string()]),
 					},
-					HashMap::from([
-						("filename".to_string(), Metadata::String("test".to_string())),
-						("lineno".to_string(), Metadata::Number(Decimal::from(13))),
-					]),
+					MetadataMap::new(),
 				)),
 				Statement::Directive(Directive::new(
 					date,
@@ -1407,10 +1608,7 @@ mod tests {
 						kind: "location".to_string(),
 						description: "Paris, France".to_string(),
 					},
-					HashMap::from([
-						("filename".to_string(), Metadata::String("test".to_string())),
-						("lineno".to_string(), Metadata::Number(Decimal::from(14))),
-					]),
+					MetadataMap::new(),
 				)),
 				Statement::Directive(Directive::new(
 					date,
@@ -1419,10 +1617,7 @@ mod tests {
 						query: "\n\t\t\t\tSELECT account, sum(position) WHERE ‘trip-france-2014’ in tags"
 							.to_string(),
 					},
-					HashMap::from([
-						("filename".to_string(), Metadata::String("test".to_string())),
-						("lineno".to_string(), Metadata::Number(Decimal::from(15))),
-					]),
+					MetadataMap::new(),
 				)),
 				Statement::Directive(Directive::new(
 					date,
@@ -1433,10 +1628,7 @@ mod tests {
 							"USD".parse().unwrap()
 						),
 					},
-					HashMap::from([
-						("filename".to_string(), Metadata::String("test".to_string())),
-						("lineno".to_string(), Metadata::Number(Decimal::from(17))),
-					]),
+					MetadataMap::new(),
 				)),
 				Statement::Directive(Directive::new(
 					date,
@@ -1446,10 +1638,7 @@ mod tests {
 						tags: HashSet::from(["test-tag".to_string()]),
 						links: HashSet::from(["test-link".to_string()]),
 					},
-					HashMap::from([
-						("filename".to_string(), Metadata::String("test".to_string())),
-						("lineno".to_string(), Metadata::Number(Decimal::from(18))),
-					]),
+					MetadataMap::new(),
 				)),
 				Statement::Directive(Directive::new(
 					date,
@@ -1464,48 +1653,260 @@ mod tests {
 							)),
 						],
 					},
-					HashMap::from([
-						("filename".to_string(), Metadata::String("test".to_string())),
-						("lineno".to_string(), Metadata::Number(Decimal::from(19))),
-					]),
+					MetadataMap::new(),
 				)),
 			],
 		);
+		assert_eq!(
+			linenos(&statements),
+			vec![6, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 19, 20]
+		);
 	}
 
 	#[test]
 	fn test_parser_tx() {
 		let filename: Rc<str> = Rc::from("test");
 		let src = r#"
-			2025-01-01 txn "Cafe Mogador" "Lamb tagine with wine"
+			2025-01-01 txn "Cafe Mogador" "Lamb tagine with wine" #food ^receipt-42
 				Liabilities:CreditCard -37.45 USD
 				Expenses:Restaurants
+			2025-01-02 * "Buy shares"
+				Assets:Brokerage 10 HOOL {518.73 USD, 2025-01-02, "first-lot"}
+				Assets:Brokerage 4 HOOL {{2000.00 USD}} @@ 2100.00 USD
+				Assets:Cash
 		"#;
 
 		let date = NaiveDate::from_str("2025-01-01").unwrap();
+		let usd: Commodity = "USD".parse().unwrap();
+		let hool: Commodity = "HOOL".parse().unwrap();
 
-		let (statements, _errors) = parse_str(filename.clone(), src);
+		let (statements, errors) = parse_str(filename.clone(), src);
 
+		assert!(errors.is_empty(), "{:?}", errors);
 		assert_eq!(
-			statements.unwrap(),
-			vec![Statement::Directive(Directive::new(
-				date,
-				DirectiveKind::Custom {
-					kind: "budget".to_string(),
-					values: vec![
-						Metadata::String("...".to_string()),
-						Metadata::Bool(true),
-						Metadata::Amount(Amount::new(
-							Decimal::from_str("4.30").unwrap(),
-							"USD".parse().unwrap()
-						)),
-					],
-				},
-				HashMap::from([
-					("filename".to_string(), Metadata::String("test".to_string())),
-					("lineno".to_string(), Metadata::Number(Decimal::from(1))),
-				]),
-			)),],
+			statements,
+			vec![
+				Statement::Directive(Directive::new(
+					date,
+					DirectiveKind::Transaction {
+						flag: Some('*'),
+						payee: Some("Cafe Mogador".to_string()),
+						narration: Some("Lamb tagine with wine".to_string()),
+						tags: HashSet::from(["food".to_string()]),
+						links: HashSet::from(["receipt-42".to_string()]),
+						postings: vec![
+							Posting::new(
+								"Liabilities:CreditCard".parse().unwrap(),
+								Some(Amount::new(
+									Decimal::from_str("-37.45").unwrap(),
+									usd.clone()
+								)),
+								None,
+								None,
+								None,
+								MetadataMap::default(),
+							),
+							Posting::new(
+								"Expenses:Restaurants".parse().unwrap(),
+								None,
+								None,
+								None,
+								None,
+								MetadataMap::default(),
+							),
+						],
+					},
+					MetadataMap::new(),
+				)),
+				Statement::Directive(Directive::new(
+					NaiveDate::from_str("2025-01-02").unwrap(),
+					DirectiveKind::Transaction {
+						flag: Some('*'),
+						payee: None,
+						narration: Some("Buy shares".to_string()),
+						tags: HashSet::new(),
+						links: HashSet::new(),
+						postings: vec![
+							Posting::new(
+								"Assets:Brokerage".parse().unwrap(),
+								Some(Amount::new(Decimal::from(10), hool.clone())),
+								Some(CostOrSpec::Spec(CostSpec::new(
+									Some(Decimal::from_str("518.73").unwrap()),
+									None,
+									Some(usd.clone()),
+									NaiveDate::from_str("2025-01-02").ok(),
+									Some(Rc::from("first-lot")),
+									None,
+								))),
+								None,
+								None,
+								MetadataMap::default(),
+							),
+							Posting::new(
+								"Assets:Brokerage".parse().unwrap(),
+								Some(Amount::new(Decimal::from(4), hool.clone())),
+								Some(CostOrSpec::Spec(CostSpec::new(
+									None,
+									Some(Decimal::from_str("2000.00").unwrap()),
+									Some(usd.clone()),
+									None,
+									None,
+									None,
+								))),
+								None,
+								None,
+								MetadataMap::default(),
+							)
+							.with_total_price(Amount::new(
+								Decimal::from_str("2100.00").unwrap(),
+								usd
+							)),
+							Posting::new(
+								"Assets:Cash".parse().unwrap(),
+								None,
+								None,
+								None,
+								None,
+								MetadataMap::default(),
+							),
+						],
+					},
+					MetadataMap::new(),
+				)),
+			],
+		);
+		assert_eq!(linenos(&statements), vec![2, 5]);
+	}
+
+	#[test]
+	fn test_parser_metadata() {
+		let filename: Rc<str> = Rc::from("test.beancount");
+		let src = r#"
+			2025-01-01 note Assets:Cash "Typed metadata"
+				zebra: "last alphabetically"
+				account: Assets:Bank
+				date: 2025-02-03
+				amount: 10.50 USD
+				number: 2
+				flag: TRUE
+				tag: #trip
+				link: ^invoice
+		"#;
+
+		let (statements, errors) = parse_str(filename, src);
+		assert!(errors.is_empty(), "{:?}", errors);
+		let Statement::Directive(directive) = &statements[0] else {
+			panic!("Expected a directive, found {:?}", statements[0]);
+		};
+
+		let meta = &directive.meta;
+		assert_eq!(
+			meta.keys().collect::<Vec<_>>(),
+			vec!["zebra", "account", "date", "amount", "number", "flag", "tag", "link"]
+		);
+		assert_eq!(meta.get_str("zebra"), Some("last alphabetically"));
+		assert_eq!(
+			meta.get_account("account").map(|a| a.as_str()),
+			Some("Assets:Bank")
+		);
+		assert_eq!(meta.get_date("date"), NaiveDate::from_ymd_opt(2025, 2, 3));
+		assert_eq!(
+			meta.get_amount("amount").map(|a| a.to_string()),
+			Some("10.50 USD".to_string())
+		);
+		assert_eq!(meta.get_number("number"), Some(Decimal::TWO));
+		assert_eq!(meta.get_bool("flag"), Some(true));
+		assert_eq!(meta.get_tag("tag"), Some("trip"));
+		assert_eq!(meta.get_link("link"), Some("invoice"));
+		assert_eq!(meta.get_tag("link"), None);
+		assert_eq!(meta.get_str("account"), None);
+
+		let location = directive.location.as_ref().unwrap();
+		assert_eq!(location.to_string(), "test.beancount:2");
+	}
+
+	#[test]
+	fn test_parser_recovery() {
+		let filename: Rc<str> = Rc::from("test");
+		let src = r#"
+			2025-01-01 open Assets:Cash
+			2025-01-02 balance Assets:Cash USD 10.00
+				note: "The amount is backwards"
+			2025-01-03 txn "Lunch"
+				Expenses:Food 12.00 USD
+				Assets:Cash }
+			option "title" "Still parsed"
+			2025-01-04 close Assets:Cash
+		"#;
+
+		let (statements, errors) = parse_str(filename.clone(), src);
+
+		// Both broken entries produce a single error each
+		assert_eq!(errors.len(), 2, "{:?}", errors);
+		assert_eq!(statements.len(), 3);
+		assert!(matches!(
+			&statements[0],
+			Statement::Directive(Directive {
+				kind: DirectiveKind::Open(..),
+				..
+			})
+		));
+		assert_eq!(
+			statements[1],
+			Statement::Option("title".to_string(), "Still parsed".to_string())
+		);
+		assert!(matches!(
+			&statements[2],
+			Statement::Directive(Directive {
+				kind: DirectiveKind::Close(..),
+				..
+			})
+		));
+	}
+
+	#[test]
+	fn test_parser_lexer_recovery() {
+		let filename: Rc<str> = Rc::from("test");
+		// The lexer can't recover from the bad token at the end of the file
+		let src = "2025-01-01 open Assets:Cash\n2025-01-02 close Assets:Cash $";
+
+		let (statements, errors) = parse_str(filename.clone(), src);
+
+		assert_eq!(errors.len(), 1, "{:?}", errors);
+		assert_eq!(errors[0].found(), Some(&"$".to_string()));
+		assert_eq!(errors[0].span(), 57..58);
+		assert_eq!(statements.len(), 1);
+		assert!(matches!(
+			&statements[0],
+			Statement::Directive(Directive {
+				kind: DirectiveKind::Open(..),
+				..
+			})
+		));
+	}
+
+	#[test]
+	fn test_parser_invalid_booking_method() {
+		let filename: Rc<str> = Rc::from("test");
+		let src =
+			"2025-01-01 open Assets:Brokerage HOOL \"FIFOO\"\n2025-01-02 close Assets:Brokerage\n";
+
+		let (statements, errors) = parse_str(filename.clone(), src);
+
+		// The account is still opened, without a booking method
+		assert_eq!(statements.len(), 2);
+		assert!(matches!(
+			&statements[0],
+			Statement::Directive(Directive {
+				kind: DirectiveKind::Open(_, _, None),
+				..
+			})
+		));
+		assert_eq!(errors.len(), 1);
+		assert_eq!(
+			errors[0].reason(),
+			&chumsky::error::SimpleReason::Custom("Invalid booking method: FIFOO".to_string())
 		);
+		assert_eq!(errors[0].span(), 0..46);
 	}
 }
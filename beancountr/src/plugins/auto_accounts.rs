@@ -0,0 +1,103 @@
+use std::collections::{HashMap, HashSet};
+
+use super::Plugin;
+use crate::core::{
+	directive::{Directive, DirectiveKind, MetadataMap},
+	error::Diagnostic,
+	options::Options,
+	types::Account,
+};
+
+/// Opens the accounts used without being opened, on the date of the first
+/// directive using them.
+pub struct AutoAccounts;
+
+impl Plugin for AutoAccounts {
+	fn run(
+		&self,
+		mut entries: Vec<Directive>,
+		_options: &Options,
+		_config: Option<&str>,
+	) -> (Vec<Directive>, Vec<Diagnostic>) {
+		let mut first_use: HashMap<&Account, &Directive> = HashMap::new();
+		let mut opened: HashSet<&Account> = HashSet::new();
+		for entry in &entries {
+			if let DirectiveKind::Open(account, ..) = &entry.kind {
+				opened.insert(account);
+			}
+			for account in entry.accounts() {
+				first_use.entry(account).or_insert(entry);
+			}
+		}
+
+		let mut opens: Vec<Directive> = first_use
+			.into_iter()
+			.filter(|(account, _)| !opened.contains(account))
+			.map(|(account, entry)| {
+				let open = DirectiveKind::Open(account.clone(), vec![], None);
+				let directive = Directive::new(entry.date, open, MetadataMap::new());
+				match &entry.location {
+					Some(location) => directive.with_location(location.clone()),
+					None => directive,
+				}
+			})
+			.collect();
+		// The plugins sort the directives afterwards, but keep the output stable
+		opens.sort_by(|a, b| a.accounts().cmp(&b.accounts()));
+		entries.extend(opens);
+		(entries, vec![])
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_auto_accounts() {
+		let input = r#"
+			2025-01-01 open Assets:Cash
+			2025-01-05 * "Lunch"
+				Expenses:Food 10.00 USD
+				Assets:Cash
+			2025-01-10 balance Assets:Bank 0 USD
+			2025-01-20 * "Dinner"
+				Expenses:Food 20.00 USD
+				Assets:Bank
+		"#;
+		let ledger = load_str(Rc::from("test"), input);
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			errors,
+			vec![
+				"test:3: Invalid reference to unknown account 'Expenses:Food'",
+				"test:6: Invalid reference to unknown account 'Assets:Bank'",
+				"test:7: Invalid reference to unknown account 'Assets:Bank'",
+				"test:7: Invalid reference to unknown account 'Expenses:Food'",
+			]
+		);
+
+		let ledger = load_str(
+			Rc::from("test"),
+			&format!("plugin \"beancount.plugins.auto_accounts\"{}", input),
+		);
+		assert!(ledger.errors.is_empty(), "{:?}", ledger.errors);
+		let opens: Vec<String> = ledger
+			.directives
+			.iter()
+			.filter(|directive| matches!(directive.kind, DirectiveKind::Open(..)))
+			.map(|directive| format!("{} {}", directive.date, directive.accounts()[0]))
+			.collect();
+		assert_eq!(
+			opens,
+			vec![
+				"2025-01-01 Assets:Cash",
+				"2025-01-05 Expenses:Food",
+				"2025-01-10 Assets:Bank",
+			]
+		);
+	}
+}
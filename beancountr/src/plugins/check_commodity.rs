@@ -0,0 +1,121 @@
+use std::collections::HashSet;
+
+use regex::Regex;
+
+use super::Plugin;
+use crate::core::{
+	directive::{Directive, DirectiveKind},
+	error::Diagnostic,
+	options::Options,
+	position::CostOrSpec,
+	types::Commodity,
+};
+
+/// Reports the commodities used without being declared by a `commodity`
+/// directive, once each, where they are first used. Commodities matching the
+/// regular expression of the config are skipped.
+pub struct CheckCommodity;
+
+/// Returns the commodities a directive uses.
+fn used_commodities(entry: &Directive) -> Vec<&Commodity> {
+	match &entry.kind {
+		DirectiveKind::Open(_, commodities, _) => commodities.iter().collect(),
+		DirectiveKind::Balance { amount, .. } => vec![amount.commodity()],
+		DirectiveKind::Price { commodity, amount } => vec![commodity, amount.commodity()],
+		DirectiveKind::Transaction { postings, .. } => postings
+			.iter()
+			.flat_map(|posting| {
+				let cost = match posting.cost() {
+					Some(CostOrSpec::Cost(cost)) => Some(cost.commodity()),
+					Some(CostOrSpec::Spec(spec)) => spec.commodity(),
+					None => None,
+				};
+				[
+					posting.units().map(|units| units.commodity()),
+					cost,
+					posting.price().map(|price| price.commodity()),
+				]
+			})
+			.flatten()
+			.collect(),
+		_ => vec![],
+	}
+}
+
+impl Plugin for CheckCommodity {
+	fn run(
+		&self,
+		entries: Vec<Directive>,
+		_options: &Options,
+		config: Option<&str>,
+	) -> (Vec<Directive>, Vec<Diagnostic>) {
+		let mut errors = vec![];
+		let skipped = match config.map(Regex::new).transpose() {
+			Ok(skipped) => skipped,
+			Err(e) => {
+				errors.push(Diagnostic::new(format!(
+					"Invalid config of check_commodity: {}",
+					e
+				)));
+				None
+			}
+		};
+
+		let mut declared: HashSet<&Commodity> = entries
+			.iter()
+			.filter_map(|entry| match &entry.kind {
+				DirectiveKind::Commodity(commodity) => Some(commodity),
+				_ => None,
+			})
+			.collect();
+		for entry in &entries {
+			for commodity in used_commodities(entry) {
+				let is_skipped = skipped
+					.as_ref()
+					.is_some_and(|skipped| skipped.is_match(commodity.as_str()));
+				// Reported once, as if it was declared afterwards
+				if !is_skipped && declared.insert(commodity) {
+					errors.push(Diagnostic::at(
+						entry,
+						format!("Missing Commodity directive for '{}'", commodity),
+					));
+				}
+			}
+		}
+		(entries, errors)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_check_commodity() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			plugin "beancount.plugins.check_commodity" "^CAD$"
+			2025-01-01 commodity USD
+			2025-01-01 open Assets:Broker HOOL
+			2025-01-01 open Assets:Cash
+			2025-01-02 * "Buy"
+				Assets:Broker 20 HOOL {100.00 USD}
+				Assets:Cash
+			2025-01-03 price HOOL 1.30 CAD
+			2025-01-04 price EUR 1.10 USD
+			2025-01-05 price EUR 1.12 USD
+			"#,
+		);
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			errors,
+			vec![
+				"test:4: Missing Commodity directive for 'HOOL'",
+				"test:10: Missing Commodity directive for 'EUR'",
+			]
+		);
+	}
+}
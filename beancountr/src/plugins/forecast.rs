@@ -0,0 +1,226 @@
+use chrono::{Datelike, Days, Local, Months, NaiveDate};
+use regex::Regex;
+
+use super::Plugin;
+use crate::core::{
+	directive::{Directive, DirectiveKind},
+	error::Diagnostic,
+	options::Options,
+};
+
+/// Repeats the transactions flagged `#` whose narration ends with a pattern
+/// like `[MONTHLY UNTIL 2027-01-01]`, `[WEEKLY REPEAT 10 TIMES]` or
+/// `[YEARLY SKIP 1 TIME REPEAT 5 TIMES]`, the way Beancount's forecast plugin
+/// does. The transaction is replaced by its occurrences, from its own date,
+/// with the pattern removed from their narration.
+///
+/// Without `UNTIL` or `REPEAT`, the transaction is repeated until the end of
+/// the current year. Monthly and yearly occurrences falling on a day the month
+/// doesn't have are moved to its last day.
+pub struct Forecast;
+
+/// The flag of the transactions to repeat.
+const FLAG_FORECAST: char = '#';
+
+#[derive(Debug, Clone, Copy)]
+enum Interval {
+	Daily,
+	Weekly,
+	Monthly,
+	Yearly,
+}
+
+impl Interval {
+	/// Returns the date of an occurrence, counting from the first one.
+	fn nth(self, start: NaiveDate, n: u32) -> Option<NaiveDate> {
+		match self {
+			Interval::Daily => start.checked_add_days(Days::new(n.into())),
+			Interval::Weekly => start.checked_add_days(Days::new(7 * u64::from(n))),
+			Interval::Monthly => start.checked_add_months(Months::new(n)),
+			Interval::Yearly => start.checked_add_months(Months::new(n.checked_mul(12)?)),
+		}
+	}
+}
+
+impl Plugin for Forecast {
+	fn run(
+		&self,
+		entries: Vec<Directive>,
+		_options: &Options,
+		_config: Option<&str>,
+	) -> (Vec<Directive>, Vec<Diagnostic>) {
+		let pattern = Regex::new(
+			r"^(.*?)\s*\[(DAILY|WEEKLY|MONTHLY|YEARLY)(?:\s+SKIP\s+(\d+)\s+TIMES?)?(?:\s+REPEAT\s+(\d+)\s+TIMES?)?(?:\s+UNTIL\s+(\d{4}-\d{2}-\d{2}))?\]$",
+		)
+		.unwrap();
+		let end_of_year = NaiveDate::from_ymd_opt(Local::now().year(), 12, 31);
+
+		let mut errors = vec![];
+		let mut output = Vec::with_capacity(entries.len());
+		for entry in entries {
+			let DirectiveKind::Transaction {
+				flag: Some(FLAG_FORECAST),
+				narration: Some(narration),
+				..
+			} = &entry.kind
+			else {
+				output.push(entry);
+				continue;
+			};
+			let Some(captures) = pattern.captures(narration) else {
+				output.push(entry);
+				continue;
+			};
+
+			let interval = match &captures[2] {
+				"DAILY" => Interval::Daily,
+				"WEEKLY" => Interval::Weekly,
+				"MONTHLY" => Interval::Monthly,
+				_ => Interval::Yearly,
+			};
+			let number = |i| {
+				captures
+					.get(i)
+					.map(|m| m.as_str().parse::<u32>().map_err(|_| m.as_str()))
+					.transpose()
+			};
+			let counts = match (number(3), number(4)) {
+				(Ok(skip), Ok(repeat)) => skip
+					.unwrap_or(0)
+					.checked_add(1)
+					.map(|step| (step, repeat))
+					.ok_or_else(|| format!("cannot skip {} times", u32::MAX)),
+				(Err(count), _) | (_, Err(count)) => Err(format!("{} times is too many", count)),
+			};
+			let (step, repeat) = match counts {
+				Ok(counts) => counts,
+				Err(reason) => {
+					errors.push(Diagnostic::at(
+						&entry,
+						format!("Invalid forecast: {}", reason),
+					));
+					output.push(entry);
+					continue;
+				}
+			};
+			let until = match captures.get(5) {
+				Some(until) => match until.as_str().parse::<NaiveDate>() {
+					Ok(until) => Some(until),
+					Err(e) => {
+						errors.push(Diagnostic::at(
+							&entry,
+							format!("Invalid forecast date: {}", e),
+						));
+						output.push(entry);
+						continue;
+					}
+				},
+				None if repeat.is_none() => end_of_year,
+				None => None,
+			};
+
+			let stripped = captures[1].to_string();
+			let occurrences = (0..)
+				.map_while(|n: u32| interval.nth(entry.date, n.checked_mul(step)?))
+				.take_while(|date| until.is_none_or(|until| *date <= until))
+				.take(repeat.map_or(usize::MAX, |repeat| repeat as usize));
+			for date in occurrences {
+				let mut occurrence = entry.clone();
+				occurrence.date = date;
+				if let DirectiveKind::Transaction { narration, .. } = &mut occurrence.kind {
+					*narration = Some(stripped.clone());
+				}
+				output.push(occurrence);
+			}
+		}
+		(output, errors)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use crate::core::directive::DirectiveKind;
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_forecast() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			plugin "beancount.plugins.forecast"
+			2025-01-31 # "Rent [MONTHLY UNTIL 2025-05-01]"
+				Expenses:Rent 1000.00 USD
+				Assets:Bank
+			2025-01-01 # "Gym [WEEKLY SKIP 1 TIME REPEAT 3 TIMES]"
+				Expenses:Gym 10.00 USD
+				Assets:Bank
+			2025-01-01 # "Unplanned"
+				Expenses:Misc 10.00 USD
+				Assets:Bank
+			2025-01-01 open Assets:Bank
+			2025-01-01 open Expenses:Gym
+			2025-01-01 open Expenses:Misc
+			2025-01-01 open Expenses:Rent
+			"#,
+		);
+		assert!(ledger.errors.is_empty());
+		let transactions: Vec<String> = ledger
+			.directives
+			.iter()
+			.filter_map(|directive| match &directive.kind {
+				DirectiveKind::Transaction { narration, .. } => {
+					Some(format!("{} {}", directive.date, narration.clone().unwrap()))
+				}
+				_ => None,
+			})
+			.collect();
+		assert_eq!(
+			transactions,
+			vec![
+				"2025-01-01 Gym",
+				"2025-01-01 Unplanned",
+				"2025-01-15 Gym",
+				"2025-01-29 Gym",
+				"2025-01-31 Rent",
+				"2025-02-28 Rent",
+				"2025-03-31 Rent",
+				"2025-04-30 Rent",
+			]
+		);
+	}
+
+	#[test]
+	fn test_forecast_invalid_count() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			plugin "beancount.plugins.forecast"
+			2025-01-01 # "Gym [MONTHLY SKIP 4294967295 TIMES REPEAT 2 TIMES]"
+				Expenses:Gym 10.00 USD
+				Assets:Bank
+			2025-01-01 # "Rent [MONTHLY REPEAT 99999999999 TIMES]"
+				Expenses:Rent 1000.00 USD
+				Assets:Bank
+			2025-01-01 open Assets:Bank
+			2025-01-01 open Expenses:Gym
+			2025-01-01 open Expenses:Rent
+			"#,
+		);
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			errors,
+			vec![
+				"test:3: Invalid forecast: cannot skip 4294967295 times",
+				"test:6: Invalid forecast: 99999999999 times is too many",
+			]
+		);
+		// The transactions are kept as they are
+		let transactions = ledger
+			.directives
+			.iter()
+			.filter(|directive| matches!(directive.kind, DirectiveKind::Transaction { .. }));
+		assert_eq!(transactions.count(), 2);
+	}
+}
@@ -0,0 +1,83 @@
+use std::collections::HashSet;
+
+use super::Plugin;
+use crate::core::{
+	directive::{Directive, DirectiveKind},
+	error::Diagnostic,
+	options::Options,
+	types::Account,
+};
+
+/// Reports the postings made to accounts that have sub-accounts, once per
+/// account.
+pub struct LeafOnly;
+
+impl Plugin for LeafOnly {
+	fn run(
+		&self,
+		entries: Vec<Directive>,
+		_options: &Options,
+		_config: Option<&str>,
+	) -> (Vec<Directive>, Vec<Diagnostic>) {
+		let parents: HashSet<&str> = entries
+			.iter()
+			.flat_map(|entry| entry.accounts())
+			.flat_map(|account| {
+				let name = account.as_str();
+				name.match_indices(':').map(move |(i, _)| &name[..i])
+			})
+			.collect();
+
+		let mut reported: HashSet<&Account> = HashSet::new();
+		let mut errors = vec![];
+		for entry in &entries {
+			let DirectiveKind::Transaction { postings, .. } = &entry.kind else {
+				continue;
+			};
+			for posting in postings {
+				let account = posting.account();
+				if parents.contains(account.as_str()) && reported.insert(account) {
+					errors.push(Diagnostic::at(
+						entry,
+						format!("Non-leaf account '{}' has postings on it", account),
+					));
+				}
+			}
+		}
+		(entries, errors)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_leafonly() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			plugin "beancount.plugins.leafonly"
+			2025-01-01 open Expenses:Food:Restaurant
+			2025-01-05 * "Lunch"
+				Expenses:Food 10.00 USD
+				Assets:Cash
+			2025-01-06 * "Dinner"
+				Expenses:Food 20.00 USD
+				Assets:Cash
+			2025-01-07 * "Dinner"
+				Expenses:Food:Restaurant 20.00 USD
+				Assets:Cash
+			2025-01-01 open Assets:Cash
+			2025-01-01 open Expenses:Food
+			"#,
+		);
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			errors,
+			vec!["test:4: Non-leaf account 'Expenses:Food' has postings on it"]
+		);
+	}
+}
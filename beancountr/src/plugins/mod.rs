@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+
+use crate::core::{
+	budget::{budget_schema, BUDGET},
+	custom::{CustomSchema, CustomSchemas},
+	directive::Directive,
+	error::Diagnostic,
+	options::Options,
+};
+
+pub mod auto_accounts;
+pub mod check_commodity;
+pub mod forecast;
+pub mod leafonly;
+pub mod noduplicates;
+pub mod onecommodity;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod sellgains;
+pub mod unique_prices;
+pub mod unrealized;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+/// The kinds of plugins loaded from a file, declared as `kind:path`.
+pub const FILE_PLUGINS: [&str; 2] = ["script", "wasm"];
+
+/// A transformation of the directives of a ledger, run on them once they are
+/// booked and before they are validated. Plugins are declared in the input
+/// with `plugin "name" "config"`.
+pub trait Plugin {
+	/// Returns the directives transformed, along with the problems found.
+	fn run(
+		&self,
+		entries: Vec<Directive>,
+		options: &Options,
+		config: Option<&str>,
+	) -> (Vec<Directive>, Vec<Diagnostic>);
+}
+
+/// The plugins that can be declared in the input, by name, along with the
+/// schemas of the custom directives they and the reports use.
+pub struct PluginRegistry {
+	plugins: HashMap<String, Box<dyn Plugin>>,
+	customs: CustomSchemas,
+}
+
+impl PluginRegistry {
+	/// Creates a registry without any plugin.
+	pub fn new() -> Self {
+		Self {
+			plugins: HashMap::new(),
+			customs: CustomSchemas::new(),
+		}
+	}
+
+	/// Creates a registry with the plugins built into the library, under the
+	/// names Beancount gives them.
+	pub fn builtins() -> Self {
+		let mut registry = Self::new();
+		registry.register(
+			"beancount.plugins.auto_accounts",
+			auto_accounts::AutoAccounts,
+		);
+		registry.register(
+			"beancount.plugins.check_commodity",
+			check_commodity::CheckCommodity,
+		);
+		registry.register("beancount.plugins.forecast", forecast::Forecast);
+		registry.register("beancount.plugins.leafonly", leafonly::LeafOnly);
+		registry.register("beancount.plugins.noduplicates", noduplicates::NoDuplicates);
+		registry.register("beancount.plugins.onecommodity", onecommodity::OneCommodity);
+		registry.register("beancount.plugins.sellgains", sellgains::SellGains);
+		registry.register(
+			"beancount.plugins.unique_prices",
+			unique_prices::UniquePrices,
+		);
+		registry.register("beancount.plugins.unrealized", unrealized::Unrealized);
+		// Budgets as Fava reads them, like `custom "budget" Expenses:Food "monthly" 400.00 USD`
+		registry.register_custom(BUDGET, budget_schema());
+		registry
+	}
+
+	/// Registers a plugin, replacing any with the same name.
+	pub fn register(&mut self, name: impl Into<String>, plugin: impl Plugin + 'static) {
+		self.plugins.insert(name.into(), Box::new(plugin));
+	}
+
+	pub fn get(&self, name: &str) -> Option<&dyn Plugin> {
+		self.plugins.get(name).map(|plugin| plugin.as_ref())
+	}
+
+	/// Registers the schema of a type of custom directives, checked once the
+	/// plugins have run.
+	pub fn register_custom(&mut self, kind: impl Into<String>, schema: CustomSchema) {
+		self.customs.register(kind, schema);
+	}
+
+	pub fn custom_schemas(&self) -> &CustomSchemas {
+		&self.customs
+	}
+
+	/// Runs the plugins in the order they were declared, each on the output of
+	/// the previous one. The directives are sorted again afterwards.
+	pub fn run(
+		&self,
+		mut directives: Vec<Directive>,
+		options: &Options,
+		declared: &[(String, Option<String>)],
+	) -> (Vec<Directive>, Vec<Diagnostic>) {
+		let mut errors = vec![];
+		for (name, config) in declared {
+			let file_plugin = file_plugin(name);
+			let Some(plugin) = file_plugin.as_deref().or_else(|| self.get(name)) else {
+				errors.push(Diagnostic::new(format!(
+					"Plugin \"{}\" is not available",
+					name
+				)));
+				continue;
+			};
+			let (output, plugin_errors) = plugin.run(directives, options, config.as_deref());
+			directives = output;
+			errors.extend(plugin_errors);
+		}
+		directives.sort_by_key(|directive| directive.sort_key());
+		(directives, errors)
+	}
+}
+
+/// Returns the plugin loaded from a file, for the kinds of plugins enabled.
+fn file_plugin(name: &str) -> Option<Box<dyn Plugin>> {
+	match name.split_once(':')? {
+		#[cfg(feature = "scripting")]
+		("script", path) => Some(Box::new(script::ScriptPlugin::new(path))),
+		#[cfg(feature = "wasm")]
+		("wasm", path) => Some(Box::new(wasm::WasmPlugin::new(path))),
+		_ => None,
+	}
+}
+
+impl Default for PluginRegistry {
+	fn default() -> Self {
+		Self::builtins()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::core::directive::{DirectiveKind, Metadata};
+	use crate::loader::load_str_with;
+
+	/// Marks every transaction with its config, and counts the transactions
+	/// already marked.
+	struct Mark;
+
+	impl Plugin for Mark {
+		fn run(
+			&self,
+			mut entries: Vec<Directive>,
+			_options: &Options,
+			config: Option<&str>,
+		) -> (Vec<Directive>, Vec<Diagnostic>) {
+			let mut marked = 0;
+			for entry in &mut entries {
+				if let DirectiveKind::Transaction { .. } = entry.kind {
+					let config = config.unwrap_or_default().to_string();
+					if entry
+						.meta
+						.insert("mark", Metadata::String(config))
+						.is_some()
+					{
+						marked += 1;
+					}
+				}
+			}
+			(entries, vec![Diagnostic::new(format!("{} marked", marked))])
+		}
+	}
+
+	#[test]
+	fn test_plugins() {
+		let mut registry = PluginRegistry::new();
+		registry.register("mark", Mark);
+		let ledger = load_str_with(
+			Rc::from("test"),
+			r#"
+			plugin "mark" "first"
+			plugin "missing"
+			plugin "mark" "second"
+			2025-01-01 open Assets:Cash
+			2025-01-02 * "Unbalanced"
+				Assets:Cash 10.00 USD
+			"#,
+			&registry,
+		);
+
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			errors,
+			vec![
+				"0 marked",
+				"Plugin \"missing\" is not available",
+				"1 marked",
+				"test:6: Transaction does not balance: (10.00 USD)",
+			]
+		);
+		assert_eq!(ledger.directives[1].meta.get_str("mark"), Some("second"));
+	}
+}
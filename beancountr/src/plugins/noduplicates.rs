@@ -0,0 +1,61 @@
+use super::Plugin;
+use crate::core::{directive::Directive, error::Diagnostic, options::Options};
+
+/// Reports the directives written more than once, such as a transaction
+/// imported twice.
+pub struct NoDuplicates;
+
+impl Plugin for NoDuplicates {
+	fn run(
+		&self,
+		entries: Vec<Directive>,
+		_options: &Options,
+		_config: Option<&str>,
+	) -> (Vec<Directive>, Vec<Diagnostic>) {
+		let mut errors = vec![];
+		// The directives are sorted, so duplicates are on the same day
+		for day in entries.chunk_by(|a, b| a.date == b.date) {
+			for (i, entry) in day.iter().enumerate() {
+				if day[..i].contains(entry) {
+					errors.push(Diagnostic::at(entry, "Duplicate entry"));
+				}
+			}
+		}
+		(entries, errors)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_noduplicates() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			plugin "beancount.plugins.noduplicates"
+			2025-01-05 * "Lunch"
+				Expenses:Food 10.00 USD
+				Assets:Cash
+			2025-01-05 * "Lunch"
+				Expenses:Food 12.00 USD
+				Assets:Cash
+			2025-01-05 * "Lunch"
+				Expenses:Food 10.00 USD
+				Assets:Cash
+			2025-01-06 price HOOL 10 USD
+			2025-01-06 price HOOL 10 USD
+			2025-01-01 open Assets:Cash
+			2025-01-01 open Expenses:Food
+			"#,
+		);
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			errors,
+			vec!["test:9: Duplicate entry", "test:13: Duplicate entry"]
+		);
+	}
+}
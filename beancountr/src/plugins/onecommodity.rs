@@ -0,0 +1,157 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use regex::Regex;
+
+use super::Plugin;
+use crate::core::{
+	directive::{Directive, DirectiveKind},
+	error::Diagnostic,
+	options::Options,
+	position::CostOrSpec,
+	types::{Account, Commodity},
+};
+
+/// Reports the accounts holding more than one commodity, or more than one
+/// cost commodity. Accounts opened with several commodities, or with the
+/// metadata `onecommodity: FALSE`, are skipped, as well as the accounts
+/// matching the regular expression of the config.
+pub struct OneCommodity;
+
+/// The commodities used in an account, and where the second one was.
+#[derive(Default)]
+struct Used<'a> {
+	commodities: BTreeSet<&'a Commodity>,
+	first_extra: Option<&'a Directive>,
+}
+
+impl<'a> Used<'a> {
+	fn add(&mut self, commodity: &'a Commodity, entry: &'a Directive) {
+		if self.commodities.insert(commodity) && self.commodities.len() == 2 {
+			self.first_extra = Some(entry);
+		}
+	}
+}
+
+impl Plugin for OneCommodity {
+	fn run(
+		&self,
+		entries: Vec<Directive>,
+		_options: &Options,
+		config: Option<&str>,
+	) -> (Vec<Directive>, Vec<Diagnostic>) {
+		let mut errors = vec![];
+		let skipped = match config.map(Regex::new).transpose() {
+			Ok(skipped) => skipped,
+			Err(e) => {
+				errors.push(Diagnostic::new(format!(
+					"Invalid config of onecommodity: {}",
+					e
+				)));
+				None
+			}
+		};
+
+		let mut allowed: HashSet<&Account> = HashSet::new();
+		let mut units: HashMap<&Account, Used> = HashMap::new();
+		let mut costs: HashMap<&Account, Used> = HashMap::new();
+		for entry in &entries {
+			match &entry.kind {
+				DirectiveKind::Open(account, commodities, _)
+					if commodities.len() > 1
+						|| entry.meta.get_bool("onecommodity") == Some(false) =>
+				{
+					allowed.insert(account);
+				}
+				DirectiveKind::Balance {
+					account, amount, ..
+				} => units
+					.entry(account)
+					.or_default()
+					.add(amount.commodity(), entry),
+				DirectiveKind::Transaction { postings, .. } => {
+					for posting in postings {
+						let account = posting.account();
+						if let Some(amount) = posting.units() {
+							units
+								.entry(account)
+								.or_default()
+								.add(amount.commodity(), entry);
+						}
+						if let Some(CostOrSpec::Cost(cost)) = posting.cost() {
+							costs
+								.entry(account)
+								.or_default()
+								.add(cost.commodity(), entry);
+						}
+					}
+				}
+				_ => {}
+			}
+		}
+
+		let is_skipped = |account: &Account| {
+			allowed.contains(account)
+				|| skipped
+					.as_ref()
+					.is_some_and(|skipped| skipped.is_match(account.as_str()))
+		};
+		for (kind, used) in [("currency", units), ("cost currency", costs)] {
+			let mut used: Vec<(&Account, Used)> = used.into_iter().collect();
+			used.sort_by_key(|(account, _)| *account);
+			for (account, used) in used {
+				let (Some(entry), false) = (used.first_extra, is_skipped(account)) else {
+					continue;
+				};
+				let commodities: Vec<&str> = used.commodities.iter().map(|c| c.as_str()).collect();
+				errors.push(Diagnostic::at(
+					entry,
+					format!(
+						"More than one {} in account '{}': {}",
+						kind,
+						account,
+						commodities.join(", ")
+					),
+				));
+			}
+		}
+		(entries, errors)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_onecommodity() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			plugin "beancount.plugins.onecommodity" "^Equity:"
+			2025-01-01 open Assets:Cash
+			2025-01-01 open Assets:Wallet USD,CAD
+			2025-01-01 open Assets:Travel
+				onecommodity: FALSE
+			2025-01-05 * "Exchange"
+				Assets:Cash 10.00 USD
+				Assets:Wallet 10.00 CAD
+				Assets:Travel 10.00 EUR
+				Equity:Opening-Balances
+			2025-01-06 * "Exchange"
+				Assets:Cash 10.00 CAD
+				Assets:Wallet 10.00 USD
+				Assets:Travel 10.00 USD
+				Equity:Opening-Balances
+			2025-01-07 balance Assets:Cash 0 EUR
+			2025-01-01 open Equity:Opening-Balances
+			"#,
+		);
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			errors,
+			vec!["test:12: More than one currency in account 'Assets:Cash': CAD, EUR, USD"]
+		);
+	}
+}
@@ -0,0 +1,765 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use chrono::NaiveDate;
+use rhai::{Array, Dynamic, Engine, ImmutableString, Map, Scope, INT};
+use rust_decimal::Decimal;
+
+use super::Plugin;
+use crate::core::{
+	directive::{Directive, DirectiveKind, Metadata, MetadataMap, Posting},
+	error::Diagnostic,
+	options::Options,
+	position::{Cost, CostOrSpec},
+	types::Amount,
+};
+
+/// How deeply the expressions of scripts can nest.
+const MAX_EXPR_DEPTH: usize = 256;
+
+/// The number of operations a script can run by default.
+pub const DEFAULT_OPERATIONS: u64 = 100_000_000;
+
+/// A problem reported by a script, with the id of its entry if any.
+type Reported = (Option<INT>, String);
+
+/// A plugin written in Rhai, declared with `plugin "script:path/to/file.rhai"`.
+///
+/// The script defines `fn run(entries, config)`, returning the entries to keep.
+/// Each entry is an object map with its `date` as a string, its `type` (like
+/// `"transaction"` or `"open"`), its `meta` and the fields of its kind: for
+/// example `account`, `amount`, or `flag`, `payee`, `narration`, `tags`,
+/// `links` and `postings` for a transaction. Amounts are maps of a `number`
+/// and a `currency`. Metadata and custom values that aren't strings, numbers,
+/// booleans or amounts are maps of their `type`, one of `"account"`,
+/// `"currency"`, `"date"`, `"tag"` or `"link"`, and their `value` as a string.
+/// The entries of the ledger also have an `id`, so they keep their location in
+/// the input when modified.
+///
+/// The script can report problems with `error(entry, message)`, or
+/// `error(message)` when they aren't about an entry.
+pub struct ScriptPlugin {
+	path: PathBuf,
+	operations: u64,
+}
+
+impl ScriptPlugin {
+	pub fn new(path: impl Into<PathBuf>) -> Self {
+		Self {
+			path: path.into(),
+			operations: DEFAULT_OPERATIONS,
+		}
+	}
+
+	/// Sets the number of operations the script can run.
+	pub fn with_limits(mut self, operations: u64) -> Self {
+		self.operations = operations;
+		self
+	}
+}
+
+impl Plugin for ScriptPlugin {
+	fn run(
+		&self,
+		entries: Vec<Directive>,
+		_options: &Options,
+		config: Option<&str>,
+	) -> (Vec<Directive>, Vec<Diagnostic>) {
+		let reported: Rc<RefCell<Vec<Reported>>> = Rc::default();
+		let mut engine = Engine::new();
+		// The defaults are too low for loops over postings nested in functions
+		engine.set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH);
+		engine.set_max_operations(self.operations);
+		let errors = reported.clone();
+		engine.register_fn("error", move |entry: Map, message: ImmutableString| {
+			let id = entry.get("id").and_then(|id| id.as_int().ok());
+			errors.borrow_mut().push((id, message.to_string()));
+		});
+		let errors = reported.clone();
+		engine.register_fn("error", move |message: ImmutableString| {
+			errors.borrow_mut().push((None, message.to_string()));
+		});
+
+		let input: Array = entries
+			.iter()
+			.enumerate()
+			.map(|(id, entry)| Dynamic::from_map(entry_to_map(entry, id as INT)))
+			.collect();
+		let config = config.map_or(Dynamic::UNIT, string);
+		let output = engine
+			.compile_file(self.path.clone())
+			.map_err(|e| e.to_string())
+			.and_then(|ast| {
+				engine
+					.call_fn::<Array>(&mut Scope::new(), &ast, "run", (input, config))
+					.map_err(|e| e.to_string())
+			})
+			.and_then(|output| {
+				output
+					.into_iter()
+					.map(|value| entry_from_dynamic(value, &entries))
+					.collect::<Result<Vec<_>, String>>()
+			});
+
+		let mut diagnostics: Vec<Diagnostic> = reported
+			.take()
+			.into_iter()
+			.map(|(id, message)| {
+				let entry = id.and_then(|id| entries.get(usize::try_from(id).ok()?));
+				match entry {
+					Some(entry) => Diagnostic::at(entry, message),
+					None => Diagnostic::new(message),
+				}
+			})
+			.collect();
+		match output {
+			Ok(output) => (output, diagnostics),
+			Err(e) => {
+				diagnostics.push(Diagnostic::new(format!(
+					"Error in plugin script {}: {}",
+					self.path.display(),
+					e
+				)));
+				(entries, diagnostics)
+			}
+		}
+	}
+}
+
+fn string(value: impl ToString) -> Dynamic {
+	Dynamic::from(value.to_string())
+}
+
+fn optional_string(value: Option<impl ToString>) -> Dynamic {
+	value.map_or(Dynamic::UNIT, string)
+}
+
+fn string_array<'a>(values: impl IntoIterator<Item = &'a String>) -> Dynamic {
+	let mut values: Vec<&String> = values.into_iter().collect();
+	values.sort();
+	Dynamic::from_array(values.into_iter().map(string).collect())
+}
+
+fn amount_to_dynamic(amount: &Amount) -> Dynamic {
+	let mut map = Map::new();
+	map.insert("number".into(), Dynamic::from_decimal(amount.number()));
+	map.insert("currency".into(), string(amount.commodity()));
+	Dynamic::from_map(map)
+}
+
+/// Wraps a value that has no Rhai type of its own into a map with its type, so
+/// it's converted back the same.
+fn typed(kind: &str, value: impl ToString) -> Dynamic {
+	let mut map = Map::new();
+	map.insert("type".into(), string(kind));
+	map.insert("value".into(), string(value));
+	Dynamic::from_map(map)
+}
+
+/// Converts a metadata value. Accounts, commodities, dates, tags and links are
+/// typed maps like `#{type: "date", value: "2025-01-31"}`.
+fn metadata_to_dynamic(value: &Metadata) -> Dynamic {
+	match value {
+		Metadata::String(s) => string(s),
+		Metadata::Account(account) => typed("account", account),
+		Metadata::Date(date) => typed("date", date),
+		Metadata::Commodity(commodity) => typed("currency", commodity),
+		Metadata::Tags(tag) => typed("tag", tag),
+		Metadata::Link(link) => typed("link", link),
+		Metadata::Bool(b) => Dynamic::from_bool(*b),
+		Metadata::None => Dynamic::UNIT,
+		Metadata::Number(number) => Dynamic::from_decimal(*number),
+		Metadata::Amount(amount) => amount_to_dynamic(amount),
+	}
+}
+
+fn meta_to_map(meta: &MetadataMap) -> Dynamic {
+	Dynamic::from_map(
+		meta.iter()
+			.map(|(key, value)| (key.into(), metadata_to_dynamic(value)))
+			.collect(),
+	)
+}
+
+fn posting_to_map(posting: &Posting) -> Dynamic {
+	let cost = match posting.cost() {
+		Some(CostOrSpec::Cost(cost)) => {
+			let mut map = Map::new();
+			map.insert("number".into(), Dynamic::from_decimal(cost.number()));
+			map.insert("currency".into(), string(cost.commodity()));
+			map.insert("date".into(), string(cost.date()));
+			map.insert("label".into(), optional_string(cost.label()));
+			Dynamic::from_map(map)
+		}
+		_ => Dynamic::UNIT,
+	};
+	let fields = [
+		("account", string(posting.account())),
+		(
+			"units",
+			posting.units().map_or(Dynamic::UNIT, amount_to_dynamic),
+		),
+		("cost", cost),
+		// Like in the input, a posting has either a price or a total price
+		(
+			"price",
+			match posting.total_price() {
+				Some(_) => Dynamic::UNIT,
+				None => posting.price().map_or(Dynamic::UNIT, amount_to_dynamic),
+			},
+		),
+		(
+			"total_price",
+			posting
+				.total_price()
+				.map_or(Dynamic::UNIT, amount_to_dynamic),
+		),
+		("flag", optional_string(posting.flag())),
+		("meta", meta_to_map(&posting.meta)),
+	];
+	Dynamic::from_map(fields.into_iter().map(|(k, v)| (k.into(), v)).collect())
+}
+
+fn entry_to_map(entry: &Directive, id: INT) -> Map {
+	let (kind, fields): (&str, Vec<(&str, Dynamic)>) = match &entry.kind {
+		DirectiveKind::Open(account, commodities, booking) => (
+			"open",
+			vec![
+				("account", string(account)),
+				(
+					"currencies",
+					Dynamic::from_array(commodities.iter().map(string).collect()),
+				),
+				("booking", optional_string(booking.as_ref())),
+			],
+		),
+		DirectiveKind::Close(account) => ("close", vec![("account", string(account))]),
+		DirectiveKind::Commodity(commodity) => ("commodity", vec![("currency", string(commodity))]),
+		DirectiveKind::Pad {
+			account,
+			source_account,
+		} => (
+			"pad",
+			vec![
+				("account", string(account)),
+				("source_account", string(source_account)),
+			],
+		),
+		DirectiveKind::Balance {
+			account,
+			amount,
+			tolerance,
+			..
+		} => (
+			"balance",
+			vec![
+				("account", string(account)),
+				("amount", amount_to_dynamic(amount)),
+				(
+					"tolerance",
+					tolerance.map_or(Dynamic::UNIT, Dynamic::from_decimal),
+				),
+			],
+		),
+		DirectiveKind::Transaction {
+			flag,
+			payee,
+			narration,
+			tags,
+			links,
+			postings,
+		} => (
+			"transaction",
+			vec![
+				("flag", optional_string(*flag)),
+				("payee", optional_string(payee.as_ref())),
+				("narration", optional_string(narration.as_ref())),
+				("tags", string_array(tags)),
+				("links", string_array(links)),
+				(
+					"postings",
+					Dynamic::from_array(postings.iter().map(posting_to_map).collect()),
+				),
+			],
+		),
+		DirectiveKind::Note {
+			account,
+			comment,
+			tags,
+			links,
+		} => (
+			"note",
+			vec![
+				("account", string(account)),
+				("comment", string(comment)),
+				("tags", string_array(tags)),
+				("links", string_array(links)),
+			],
+		),
+		DirectiveKind::Event { kind, description } => (
+			"event",
+			vec![
+				("event", string(kind)),
+				("description", string(description)),
+			],
+		),
+		DirectiveKind::Query { name, query } => (
+			"query",
+			vec![("name", string(name)), ("query", string(query))],
+		),
+		DirectiveKind::Price { commodity, amount } => (
+			"price",
+			vec![
+				("currency", string(commodity)),
+				("amount", amount_to_dynamic(amount)),
+			],
+		),
+		DirectiveKind::Document {
+			account,
+			filename,
+			tags,
+			links,
+		} => (
+			"document",
+			vec![
+				("account", string(account)),
+				("filename", string(filename)),
+				("tags", string_array(tags)),
+				("links", string_array(links)),
+			],
+		),
+		DirectiveKind::Custom { kind, values } => (
+			"custom",
+			vec![
+				("custom", string(kind)),
+				(
+					"values",
+					Dynamic::from_array(values.iter().map(metadata_to_dynamic).collect()),
+				),
+			],
+		),
+	};
+
+	let mut map = Map::new();
+	map.insert("id".into(), Dynamic::from_int(id));
+	map.insert("date".into(), string(entry.date));
+	map.insert("type".into(), string(kind));
+	map.insert("meta".into(), meta_to_map(&entry.meta));
+	map.extend(fields.into_iter().map(|(k, v)| (k.into(), v)));
+	map
+}
+
+/// Reads the fields of an object map returned by a script.
+struct Fields(Map);
+
+impl Fields {
+	fn from_dynamic(value: Dynamic, name: &str) -> Result<Self, String> {
+		value
+			.try_cast::<Map>()
+			.map(Fields)
+			.ok_or_else(|| format!("{} must be object maps", name))
+	}
+
+	fn get(&self, key: &str) -> Option<&Dynamic> {
+		self.0.get(key).filter(|value| !value.is_unit())
+	}
+
+	fn string(&self, key: &str) -> Result<String, String> {
+		self.optional_string(key)?
+			.ok_or_else(|| format!("missing \"{}\"", key))
+	}
+
+	fn optional_string(&self, key: &str) -> Result<Option<String>, String> {
+		self.get(key)
+			.map(|value| {
+				value
+					.clone()
+					.into_string()
+					.map_err(|_| format!("\"{}\" must be a string", key))
+			})
+			.transpose()
+	}
+
+	fn parse<T: std::str::FromStr>(&self, key: &str) -> Result<T, String> {
+		self.string(key)?
+			.parse()
+			.map_err(|_| format!("invalid \"{}\"", key))
+	}
+
+	fn optional_parse<T: std::str::FromStr>(&self, key: &str) -> Result<Option<T>, String> {
+		match self.get(key) {
+			Some(_) => self.parse(key).map(Some),
+			None => Ok(None),
+		}
+	}
+
+	fn number(&self, key: &str) -> Result<Decimal, String> {
+		self.get(key)
+			.and_then(number)
+			.ok_or_else(|| format!("\"{}\" must be a number", key))
+	}
+
+	fn map(&self, key: &str) -> Result<Option<Fields>, String> {
+		self.get(key)
+			.map(|value| {
+				value
+					.clone()
+					.try_cast::<Map>()
+					.map(Fields)
+					.ok_or_else(|| format!("\"{}\" must be an object map", key))
+			})
+			.transpose()
+	}
+
+	fn array(&self, key: &str) -> Result<Array, String> {
+		match self.get(key) {
+			Some(value) => value
+				.clone()
+				.try_cast::<Array>()
+				.ok_or_else(|| format!("\"{}\" must be an array", key)),
+			None => Ok(Array::new()),
+		}
+	}
+
+	fn strings(&self, key: &str) -> Result<HashSet<String>, String> {
+		self.array(key)?
+			.into_iter()
+			.map(|value| {
+				value
+					.into_string()
+					.map_err(|_| format!("\"{}\" must only have strings", key))
+			})
+			.collect()
+	}
+
+	fn to_amount(&self) -> Result<Amount, String> {
+		Ok(Amount::new(self.number("number")?, self.parse("currency")?))
+	}
+
+	fn amount(&self, key: &str) -> Result<Option<Amount>, String> {
+		self.map(key)?.map(|amount| amount.to_amount()).transpose()
+	}
+
+	fn flag(&self, key: &str) -> Result<Option<char>, String> {
+		Ok(self
+			.optional_string(key)?
+			.and_then(|flag| flag.chars().next()))
+	}
+
+	fn meta(&self) -> Result<MetadataMap, String> {
+		let Some(meta) = self.map("meta")? else {
+			return Ok(MetadataMap::new());
+		};
+		meta.0
+			.into_iter()
+			.map(|(key, value)| Ok((key.to_string(), metadata_from_dynamic(value)?)))
+			.collect()
+	}
+}
+
+fn number(value: &Dynamic) -> Option<Decimal> {
+	value
+		.as_decimal()
+		.ok()
+		.or_else(|| value.as_int().ok().map(Decimal::from))
+		.or_else(|| Decimal::try_from(value.as_float().ok()?).ok())
+}
+
+/// Converts a metadata value back, typed maps into the value of their type.
+fn metadata_from_dynamic(value: Dynamic) -> Result<Metadata, String> {
+	if value.is_unit() {
+		return Ok(Metadata::None);
+	}
+	if let Ok(b) = value.as_bool() {
+		return Ok(Metadata::Bool(b));
+	}
+	if let Some(number) = number(&value) {
+		return Ok(Metadata::Number(number));
+	}
+	if value.is_map() {
+		let fields = Fields::from_dynamic(value, "metadata")?;
+		let Some(kind) = fields.optional_string("type")? else {
+			return fields.to_amount().map(Metadata::Amount);
+		};
+		return match kind.as_str() {
+			"account" => fields.parse("value").map(Metadata::Account),
+			"currency" => fields.parse("value").map(Metadata::Commodity),
+			"date" => fields.parse("value").map(Metadata::Date),
+			"tag" => fields.string("value").map(Metadata::Tags),
+			"link" => fields.string("value").map(Metadata::Link),
+			other => Err(format!("unknown metadata type \"{}\"", other)),
+		};
+	}
+	match value.into_string() {
+		Ok(s) => Ok(Metadata::String(s)),
+		Err(type_name) => Err(format!("unsupported metadata of type {}", type_name)),
+	}
+}
+
+fn posting_from_dynamic(value: Dynamic, date: NaiveDate) -> Result<Posting, String> {
+	let fields = Fields::from_dynamic(value, "postings")?;
+	let cost = fields
+		.map("cost")?
+		.map(|cost| -> Result<_, String> {
+			let date = cost.optional_parse("date")?.unwrap_or(date);
+			let label = cost.optional_string("label")?.map(Rc::from);
+			Ok(CostOrSpec::Cost(Cost::new(
+				cost.number("number")?,
+				cost.parse("currency")?,
+				date,
+				label,
+			)))
+		})
+		.transpose()?;
+	let posting = Posting::new(
+		fields.parse("account")?,
+		fields.amount("units")?,
+		cost,
+		fields.amount("price")?,
+		fields.flag("flag")?,
+		fields.meta()?,
+	);
+	Ok(match fields.amount("total_price")? {
+		Some(total) => posting.with_total_price(total),
+		None => posting,
+	})
+}
+
+/// Converts an entry returned by a script back into a directive, located where
+/// the entry of the ledger with the same id was.
+fn entry_from_dynamic(value: Dynamic, originals: &[Directive]) -> Result<Directive, String> {
+	let fields = Fields::from_dynamic(value, "entries")?;
+	let kind = match fields.string("type")?.as_str() {
+		"open" => DirectiveKind::Open(
+			fields.parse("account")?,
+			fields
+				.strings("currencies")?
+				.iter()
+				.map(|currency| currency.parse().map_err(|_| "invalid currency".to_string()))
+				.collect::<Result<_, _>>()?,
+			fields.optional_parse("booking")?,
+		),
+		"close" => DirectiveKind::Close(fields.parse("account")?),
+		"commodity" => DirectiveKind::Commodity(fields.parse("currency")?),
+		"pad" => DirectiveKind::Pad {
+			account: fields.parse("account")?,
+			source_account: fields.parse("source_account")?,
+		},
+		"balance" => DirectiveKind::Balance {
+			account: fields.parse("account")?,
+			amount: fields.amount("amount")?.ok_or("missing \"amount\"")?,
+			tolerance: fields.get("tolerance").and_then(number),
+			diff_amount: None,
+		},
+		"transaction" => {
+			let date = fields.parse("date")?;
+			DirectiveKind::Transaction {
+				flag: fields.flag("flag")?,
+				payee: fields.optional_string("payee")?,
+				narration: fields.optional_string("narration")?,
+				tags: fields.strings("tags")?,
+				links: fields.strings("links")?,
+				postings: fields
+					.array("postings")?
+					.into_iter()
+					.map(|posting| posting_from_dynamic(posting, date))
+					.collect::<Result<_, _>>()?,
+			}
+		}
+		"note" => DirectiveKind::Note {
+			account: fields.parse("account")?,
+			comment: fields.string("comment")?,
+			tags: fields.strings("tags")?,
+			links: fields.strings("links")?,
+		},
+		"event" => DirectiveKind::Event {
+			kind: fields.string("event")?,
+			description: fields.string("description")?,
+		},
+		"query" => DirectiveKind::Query {
+			name: fields.string("name")?,
+			query: fields.string("query")?,
+		},
+		"price" => DirectiveKind::Price {
+			commodity: fields.parse("currency")?,
+			amount: fields.amount("amount")?.ok_or("missing \"amount\"")?,
+		},
+		"document" => DirectiveKind::Document {
+			account: fields.parse("account")?,
+			filename: fields.string("filename")?,
+			tags: fields.strings("tags")?,
+			links: fields.strings("links")?,
+		},
+		"custom" => DirectiveKind::Custom {
+			kind: fields.string("custom")?,
+			values: fields
+				.array("values")?
+				.into_iter()
+				.map(metadata_from_dynamic)
+				.collect::<Result<_, _>>()?,
+		},
+		other => return Err(format!("unknown entry type \"{}\"", other)),
+	};
+
+	let directive = Directive::new(fields.parse("date")?, kind, fields.meta()?);
+	let original = fields
+		.get("id")
+		.and_then(|id| id.as_int().ok())
+		.and_then(|id| originals.get(usize::try_from(id).ok()?));
+	Ok(
+		match original.and_then(|original| original.location.clone()) {
+			Some(location) => directive.with_location(location),
+			None => directive,
+		},
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::rc::Rc;
+
+	use super::ScriptPlugin;
+	use crate::core::budget::budgets;
+	use crate::core::directive::{DirectiveKind, Metadata};
+	use crate::loader::{load_str, load_str_with};
+	use crate::plugins::PluginRegistry;
+
+	const SCRIPT: &str = r#"
+		fn run(entries, config) {
+			let output = [];
+			for entry in entries {
+				if entry.type == "price" {
+					continue;
+				}
+				if entry.type == "transaction" {
+					entry.tags.push(config);
+					for posting in entry.postings {
+						if type_of(posting.units) == "map" && posting.units.number > 100 {
+							error(entry, "Too large for " + posting.account);
+						}
+					}
+				}
+				output.push(entry);
+			}
+			output.push(#{
+				date: "2025-01-31",
+				type: "note",
+				account: "Assets:Cash",
+				comment: "Checked",
+				meta: #{ checked: #{ type: "date", value: "2025-02-01" } },
+			});
+			output
+		}
+	"#;
+
+	#[test]
+	fn test_script() {
+		let path = std::env::temp_dir().join(format!("beancountr-{}.rhai", std::process::id()));
+		fs::write(&path, SCRIPT).unwrap();
+		let ledger = load_str(
+			Rc::from("test"),
+			&format!(
+				r#"
+				plugin "script:{}" "scripted"
+				2025-01-01 open Assets:Cash
+				2025-01-01 open Expenses:Food
+				2025-01-02 * "Lunch"
+					Expenses:Food 10.00 USD
+					Assets:Cash
+				2025-01-03 * "Party"
+					Expenses:Food 150.00 USD
+					Assets:Cash
+				2025-01-04 price EUR 1.10 USD
+				"#,
+				path.display()
+			),
+		);
+		fs::remove_file(&path).unwrap();
+
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(errors, vec!["test:8: Too large for Expenses:Food"]);
+		let kinds: Vec<String> = ledger
+			.directives
+			.iter()
+			.map(|directive| match &directive.kind {
+				DirectiveKind::Transaction { tags, .. } => {
+					format!("{} {:?}", directive.date, tags)
+				}
+				DirectiveKind::Note { comment, .. } => {
+					format!(
+						"{} {} {}",
+						directive.date,
+						comment,
+						directive.meta.get_date("checked").unwrap()
+					)
+				}
+				_ => directive.date.to_string(),
+			})
+			.collect();
+		assert_eq!(
+			kinds,
+			vec![
+				"2025-01-01",
+				"2025-01-01",
+				"2025-01-02 {\"scripted\"}",
+				"2025-01-03 {\"scripted\"}",
+				"2025-01-31 Checked 2025-02-01",
+			]
+		);
+	}
+
+	#[test]
+	fn test_script_noop() {
+		let input = r#"
+			2025-01-01 open Assets:Cash
+			2025-01-01 open Expenses:Food
+			2025-01-01 custom "budget" Expenses:Food "monthly" 500.00 USD
+			2025-01-02 * "Lunch" #food ^receipt-1
+				owner: Assets:Cash
+				paid: 2025-01-03
+				currency: USD
+				note: "2025-01-04"
+				Expenses:Food 10.00 EUR @@ 11.00 USD
+				Assets:Cash
+		"#;
+		let path =
+			std::env::temp_dir().join(format!("beancountr-noop-{}.rhai", std::process::id()));
+		fs::write(&path, "fn run(entries, config) { entries }").unwrap();
+		let scripted = load_str(
+			Rc::from("test"),
+			&format!("plugin \"script:{}\"\n{}", path.display(), input),
+		);
+		fs::remove_file(&path).unwrap();
+		let ledger = load_str(Rc::from("test"), input);
+
+		assert!(scripted.errors.is_empty(), "{:?}", scripted.errors);
+		assert_eq!(scripted.directives, ledger.directives);
+		assert_eq!(budgets(&scripted.directives).0.len(), 1);
+		let meta = &scripted.directives[3].meta;
+		assert!(matches!(meta.get("owner"), Some(Metadata::Account(_))));
+		assert!(matches!(meta.get("note"), Some(Metadata::String(_))));
+	}
+
+	#[test]
+	fn test_script_limits() {
+		let path =
+			std::env::temp_dir().join(format!("beancountr-loop-{}.rhai", std::process::id()));
+		fs::write(&path, "fn run(entries, config) { loop {} }").unwrap();
+		let mut registry = PluginRegistry::new();
+		registry.register("loop", ScriptPlugin::new(&path).with_limits(10_000));
+		let ledger = load_str_with(
+			Rc::from("test"),
+			"plugin \"loop\"\n2025-01-01 open Assets:Cash",
+			&registry,
+		);
+		fs::remove_file(&path).unwrap();
+
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(errors.len(), 1);
+		assert!(errors[0].contains("Too many operations"), "{:?}", errors);
+		assert_eq!(ledger.directives.len(), 1);
+	}
+}
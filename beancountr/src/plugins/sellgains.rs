@@ -0,0 +1,129 @@
+use rust_decimal::Decimal;
+
+use super::Plugin;
+use crate::core::{
+	amounts::Amounts,
+	directive::{Directive, DirectiveKind},
+	error::{Diagnostic, Result},
+	options::Options,
+	tolerance::infer_tolerances,
+};
+
+/// Tolerances are loosened, since the prices of sales are often rounded.
+const EXTRA_TOLERANCE_MULTIPLIER: Decimal = Decimal::TWO;
+
+/// Checks that the units sold at their price add up to the proceeds of the
+/// sale, that is the postings outside of the income accounts. Otherwise the
+/// gains booked to an income account are likely to be wrong.
+pub struct SellGains;
+
+impl Plugin for SellGains {
+	fn run(
+		&self,
+		entries: Vec<Directive>,
+		options: &Options,
+		_config: Option<&str>,
+	) -> (Vec<Directive>, Vec<Diagnostic>) {
+		let is_income = |name: &str| {
+			name.strip_prefix(options.name_income.as_str())
+				.is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+		};
+
+		let mut errors = vec![];
+		for entry in &entries {
+			let DirectiveKind::Transaction { postings, .. } = &entry.kind else {
+				continue;
+			};
+			let sold = postings
+				.iter()
+				.any(|posting| posting.cost().is_some() && posting.price().is_some());
+			if !sold {
+				continue;
+			}
+
+			// The units sold are negative, so the sale price adds up to minus
+			// the proceeds
+			let sums = || -> Result<(Amounts, Amounts, Amounts)> {
+				let mut price = Amounts::new();
+				let mut proceeds = Amounts::new();
+				for posting in postings {
+					match (posting.units(), posting.cost(), posting.price()) {
+						(Some(units), Some(_), Some(per_unit)) => {
+							price.add_amount(&per_unit.checked_mul(units.number())?)?;
+						}
+						_ if is_income(posting.account().as_str()) => {}
+						_ => {
+							if let Some(weight) = posting.weight()? {
+								proceeds.add_amount(&weight)?;
+							}
+						}
+					}
+				}
+				let mut difference = price.clone();
+				difference.add_amounts(&proceeds)?;
+				Ok((price, proceeds, difference))
+			};
+			let (price, proceeds, difference) = match sums() {
+				Ok(sums) => sums,
+				Err(e) => {
+					errors.push(Diagnostic::at(entry, e.to_string()));
+					continue;
+				}
+			};
+
+			let tolerances = infer_tolerances(postings, &entry.meta, options);
+			let mismatched = difference.iter().any(|amount| {
+				amount.number().abs()
+					> tolerances.get(amount.commodity()) * EXTRA_TOLERANCE_MULTIPLIER
+			});
+			if mismatched {
+				errors.push(Diagnostic::at(
+					entry,
+					format!(
+						"Invalid price vs. proceeds/gains: {} vs. {}; difference: {}",
+						-price, proceeds, difference
+					),
+				));
+			}
+		}
+		(entries, errors)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_sellgains() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			plugin "beancount.plugins.sellgains"
+			2025-01-01 open Assets:Broker
+			2025-01-01 open Assets:Cash
+			2025-01-01 open Expenses:Commissions
+			2025-01-01 open Income:Gains
+			2025-01-02 * "Buy"
+				Assets:Broker 20 HOOL {100.00 USD}
+				Assets:Cash
+			2025-02-01 * "Sell"
+				Assets:Broker -10 HOOL {100.00 USD} @ 130.00 USD
+				Assets:Cash 1290.00 USD
+				Expenses:Commissions 10.00 USD
+				Income:Gains -300.00 USD
+			2025-03-01 * "Sell at the wrong price"
+				Assets:Broker -10 HOOL {100.00 USD} @ 120.00 USD
+				Assets:Cash 1300.00 USD
+				Income:Gains -300.00 USD
+			"#,
+		);
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			errors,
+			vec!["test:15: Invalid price vs. proceeds/gains: (1200.00 USD) vs. (1300.00 USD); difference: (100.00 USD)"]
+		);
+	}
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use super::Plugin;
+use crate::core::{
+	directive::{Directive, DirectiveKind},
+	error::Diagnostic,
+	options::Options,
+	types::Commodity,
+};
+
+/// Reports the prices of a commodity given on the same day in the same
+/// currency, but with different numbers.
+pub struct UniquePrices;
+
+impl Plugin for UniquePrices {
+	fn run(
+		&self,
+		entries: Vec<Directive>,
+		_options: &Options,
+		_config: Option<&str>,
+	) -> (Vec<Directive>, Vec<Diagnostic>) {
+		let mut prices: HashMap<(NaiveDate, &Commodity, &Commodity), Decimal> = HashMap::new();
+		let mut errors = vec![];
+		for entry in &entries {
+			let DirectiveKind::Price { commodity, amount } = &entry.kind else {
+				continue;
+			};
+			let key = (entry.date, commodity, amount.commodity());
+			match prices.get(&key) {
+				Some(number) if *number != amount.number() => errors.push(Diagnostic::at(
+					entry,
+					format!(
+						"Disagreeing price for {}: {} and {} {}",
+						commodity,
+						number,
+						amount.number(),
+						amount.commodity()
+					),
+				)),
+				Some(_) => {}
+				None => {
+					prices.insert(key, amount.number());
+				}
+			}
+		}
+		(entries, errors)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_unique_prices() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			plugin "beancount.plugins.unique_prices"
+			2025-01-06 price HOOL 10.00 USD
+			2025-01-06 price HOOL 10.0 USD
+			2025-01-06 price HOOL 12.00 CAD
+			2025-01-06 price HOOL 11.00 USD
+			2025-01-07 price HOOL 11.00 USD
+			"#,
+		);
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			errors,
+			vec!["test:6: Disagreeing price for HOOL: 10.00 and 11.00 USD"]
+		);
+	}
+}
@@ -0,0 +1,290 @@
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use super::Plugin;
+use crate::core::{
+	directive::{Directive, DirectiveKind, MetadataMap, Posting},
+	error::Diagnostic,
+	options::Options,
+	position::CostOrSpec,
+	prices::PriceMap,
+	types::{Account, Amount, Commodity},
+};
+
+/// The flag of the transactions booking unrealized gains.
+const FLAG_UNREALIZED: char = 'U';
+
+/// Books the difference between the market value and the cost of the lots
+/// held at the end of the ledger, with a transaction per account and
+/// commodity. The gains of `Assets:Broker` go to `Assets:Broker:Unrealized`,
+/// against `Income:Broker:Unrealized`, both opened as needed.
+///
+/// The config gives the name of the sub-account, `Unrealized` by default, and
+/// optionally a date to value the lots at instead, like `"Gains 2024-12-31"`.
+pub struct Unrealized;
+
+/// The units of a commodity held in an account, and what they cost.
+#[derive(Default)]
+struct Held {
+	units: Decimal,
+	cost: Decimal,
+	/// Whether the sums overflowed, leaving the lots out.
+	overflowed: bool,
+}
+
+/// Reports lots whose value can't be computed.
+fn overflow(commodity: &Commodity, account: &Account) -> String {
+	format!(
+		"Arithmetic overflow valuing the {} held in {}",
+		commodity, account
+	)
+}
+
+fn posting(account: &Account, units: Amount) -> Posting {
+	Posting::new(
+		account.clone(),
+		Some(units),
+		None,
+		None,
+		None,
+		MetadataMap::new(),
+	)
+}
+
+impl Plugin for Unrealized {
+	fn run(
+		&self,
+		mut entries: Vec<Directive>,
+		options: &Options,
+		config: Option<&str>,
+	) -> (Vec<Directive>, Vec<Diagnostic>) {
+		let mut subaccount = "Unrealized";
+		let mut date = None;
+		for part in config.unwrap_or_default().split_whitespace() {
+			match part.parse::<NaiveDate>() {
+				Ok(parsed) => date = Some(parsed),
+				Err(_) => subaccount = part,
+			}
+		}
+		let Some(date) = date.or(entries.last().map(|entry| entry.date)) else {
+			return (entries, vec![]);
+		};
+
+		let prices = PriceMap::from_directives(&entries);
+		let mut errors = vec![];
+		let mut held: BTreeMap<(&Account, &Commodity, &Commodity), Held> = BTreeMap::new();
+		for entry in entries.iter().take_while(|entry| entry.date <= date) {
+			let DirectiveKind::Transaction { postings, .. } = &entry.kind else {
+				continue;
+			};
+			for posting in postings {
+				let (Some(units), Some(CostOrSpec::Cost(cost))) = (posting.units(), posting.cost())
+				else {
+					continue;
+				};
+				let key = (posting.account(), units.commodity(), cost.commodity());
+				let lots = held.entry(key).or_default();
+				let sums = units.number().checked_mul(cost.number()).and_then(|cost| {
+					Some((
+						lots.units.checked_add(units.number())?,
+						lots.cost.checked_add(cost)?,
+					))
+				});
+				match sums {
+					Some((units, cost)) => (lots.units, lots.cost) = (units, cost),
+					None if !lots.overflowed => {
+						lots.overflowed = true;
+						let message = overflow(units.commodity(), posting.account());
+						errors.push(Diagnostic::at(entry, message));
+					}
+					None => {}
+				}
+			}
+		}
+
+		let opened: HashSet<&Account> = entries
+			.iter()
+			.filter_map(|entry| match &entry.kind {
+				DirectiveKind::Open(account, ..) => Some(account),
+				_ => None,
+			})
+			.collect();
+		let mut opens: BTreeMap<Account, NaiveDate> = BTreeMap::new();
+		let mut gains = vec![];
+		for ((account, commodity, cost_commodity), lots) in held {
+			if lots.units.is_zero() || lots.overflowed {
+				continue;
+			}
+			let Some((price_date, price)) = prices.get_rate(commodity, cost_commodity, Some(date))
+			else {
+				continue;
+			};
+			let values = lots.units.checked_mul(price).and_then(|value| {
+				Some((
+					value.checked_sub(lots.cost)?,
+					lots.cost.checked_div(lots.units)?,
+				))
+			});
+			let Some((gain, average_cost)) = values else {
+				errors.push(Diagnostic::new(overflow(commodity, account)));
+				continue;
+			};
+			if gain.is_zero() {
+				continue;
+			}
+
+			let name = account.as_str();
+			let relative = name.split_once(':').map_or("", |(_, rest)| rest);
+			let asset_account = Account::from(vec![name, subaccount]);
+			let income_account = Account::from(
+				[options.name_income.as_str(), relative, subaccount]
+					.into_iter()
+					.filter(|part| !part.is_empty())
+					.collect::<Vec<_>>(),
+			);
+			let kind = match gain.is_sign_positive() {
+				true => "gain",
+				false => "loss",
+			};
+			let narration = format!(
+				"Unrealized {} for {} units of {} (price: {} {} as of {}, average cost: {} {})",
+				kind,
+				lots.units,
+				commodity,
+				price,
+				cost_commodity,
+				price_date,
+				average_cost.round_dp(lots.cost.scale().max(2)),
+				cost_commodity
+			);
+			let postings = vec![
+				posting(&asset_account, Amount::new(gain, cost_commodity.clone())),
+				posting(&income_account, Amount::new(-gain, cost_commodity.clone())),
+			];
+			gains.push(Directive::new(
+				date,
+				DirectiveKind::Transaction {
+					flag: Some(FLAG_UNREALIZED),
+					payee: None,
+					narration: Some(narration),
+					tags: HashSet::new(),
+					links: HashSet::new(),
+					postings,
+				},
+				MetadataMap::new(),
+			));
+			for account in [asset_account, income_account] {
+				if !opened.contains(&account) {
+					opens.entry(account).or_insert(date);
+				}
+			}
+		}
+
+		entries.extend(opens.into_iter().map(|(account, date)| {
+			let open = DirectiveKind::Open(account, vec![], None);
+			Directive::new(date, open, MetadataMap::new())
+		}));
+		entries.extend(gains);
+		(entries, errors)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use crate::core::directive::DirectiveKind;
+	use crate::loader::load_str;
+	use crate::realization::realize;
+
+	const LEDGER: &str = r#"
+		2025-01-01 open Assets:Broker
+		2025-01-01 open Assets:Cash
+		2025-01-02 * "Buy"
+			Assets:Broker 10 HOOL {100.00 USD}
+			Assets:Broker 5 HOOL {120.00 USD}
+			Assets:Broker 4 AAPL {50.00 USD}
+			Assets:Cash
+		2025-02-01 price HOOL 130.00 USD
+		2025-03-01 price AAPL 45.00 USD
+		2025-04-01 price HOOL 140.00 USD
+	"#;
+
+	#[test]
+	fn test_unrealized() {
+		let ledger = load_str(
+			Rc::from("test"),
+			&format!("plugin \"beancount.plugins.unrealized\"\n{}", LEDGER),
+		);
+		assert!(ledger.errors.is_empty());
+		let narrations: Vec<String> = ledger
+			.directives
+			.iter()
+			.filter_map(|directive| match &directive.kind {
+				DirectiveKind::Transaction {
+					flag: Some('U'),
+					narration,
+					..
+				} => Some(format!("{} {}", directive.date, narration.clone().unwrap())),
+				_ => None,
+			})
+			.collect();
+		assert_eq!(
+			narrations,
+			vec![
+				"2025-04-01 Unrealized loss for 4 units of AAPL (price: 45.00 USD as of 2025-03-01, average cost: 50.00 USD)",
+				"2025-04-01 Unrealized gain for 15 units of HOOL (price: 140.00 USD as of 2025-04-01, average cost: 106.67 USD)",
+			]
+		);
+		let root = realize(&ledger.directives);
+		let balance = |account: &str| root.get(account).unwrap().balance.to_string();
+		assert_eq!(balance("Assets:Broker:Unrealized"), "(480.00 USD)");
+		assert_eq!(balance("Income:Broker:Unrealized"), "(-480.00 USD)");
+
+		let ledger = load_str(
+			Rc::from("test"),
+			&format!(
+				"plugin \"beancount.plugins.unrealized\" \"Gains 2025-02-15\"\n{}",
+				LEDGER
+			),
+		);
+		let root = realize(&ledger.directives);
+		let balance = |account: &str| root.get(account).unwrap().balance.to_string();
+		assert_eq!(balance("Assets:Broker:Gains"), "(350.00 USD)");
+		assert!(root.get("Income:Broker:Unrealized").is_none());
+	}
+
+	#[test]
+	fn test_unrealized_overflow() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			plugin "beancount.plugins.unrealized"
+			2025-01-01 open Assets:Broker
+			2025-01-01 open Assets:Cash
+			2025-01-01 open Assets:Bank
+			2025-01-02 * "Buy"
+				Assets:Broker 10000000000000000000000000 HOOL {1 USD}
+				Assets:Cash
+			2025-01-02 * "Buy"
+				Assets:Broker 30000000000000000000000000000 AAPL {2 USD, 2025-01-01}
+				Assets:Cash
+			2025-01-03 * "Buy"
+				Assets:Broker 30000000000000000000000000000 AAPL {2 USD, 2025-01-02}
+				Assets:Bank
+			2025-02-01 price HOOL 100000 USD
+			2025-02-01 price AAPL 2 USD
+			"#,
+		);
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			errors,
+			vec![
+				"test:12: Arithmetic overflow valuing the AAPL held in Assets:Broker",
+				"Arithmetic overflow valuing the HOOL held in Assets:Broker",
+			]
+		);
+	}
+}
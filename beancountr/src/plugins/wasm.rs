@@ -0,0 +1,335 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use wasmi::{
+	Caller, Config, Engine, Extern, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder,
+};
+
+use super::Plugin;
+use crate::booking::book;
+use crate::core::{
+	directive::{Directive, SourceLocation},
+	error::Diagnostic,
+	options::Options,
+};
+use crate::parser::{parse_str, Statement};
+
+/// The fuel a plugin gets by default, roughly the number of instructions it
+/// can run.
+pub const DEFAULT_FUEL: u64 = 1_000_000_000;
+
+/// The memory a plugin can use by default, in bytes.
+pub const DEFAULT_MEMORY: usize = 512 << 20;
+
+/// A plugin compiled to WebAssembly, declared with
+/// `plugin "wasm:path/to/file.wasm"`. It runs sandboxed, without any access to
+/// the host but its input, and is stopped once it runs out of fuel or memory.
+///
+/// The module exports its `memory`, `alloc(len: i32) -> i32` returning where
+/// the host can write an input of `len` bytes, and `run(ptr: i32, len: i32)
+/// -> i64` returning where its output is, in the upper 32 bits, and its length,
+/// in the lower ones. It can import `env.error(ptr: i32, len: i32)` to report
+/// a problem.
+///
+/// The input is a Beancount file, in UTF-8, with the options of the ledger, a
+/// `plugin` statement with the config of the plugin, and the directives once
+/// booked. The output is a Beancount file with the directives to keep. These
+/// are booked again, and keep their location in the input when unchanged.
+pub struct WasmPlugin {
+	path: PathBuf,
+	fuel: u64,
+	memory: usize,
+}
+
+/// The data of a running plugin.
+struct Host {
+	limits: StoreLimits,
+	errors: Vec<String>,
+}
+
+impl WasmPlugin {
+	pub fn new(path: impl Into<PathBuf>) -> Self {
+		Self {
+			path: path.into(),
+			fuel: DEFAULT_FUEL,
+			memory: DEFAULT_MEMORY,
+		}
+	}
+
+	/// Sets the fuel and the memory, in bytes, the plugin can use.
+	pub fn with_limits(mut self, fuel: u64, memory: usize) -> Self {
+		self.fuel = fuel;
+		self.memory = memory;
+		self
+	}
+
+	/// Runs the module on the input, returning its output and the problems it
+	/// reported.
+	fn call(&self, input: &str) -> Result<(String, Vec<String>), String> {
+		let wasm = std::fs::read(&self.path).map_err(|e| e.to_string())?;
+		let mut config = Config::default();
+		config.consume_fuel(true);
+		let engine = Engine::new(&config);
+		let module = Module::new(&engine, &wasm).map_err(|e| e.to_string())?;
+
+		let host = Host {
+			limits: StoreLimitsBuilder::new().memory_size(self.memory).build(),
+			errors: vec![],
+		};
+		let mut store = Store::new(&engine, host);
+		store.limiter(|host| &mut host.limits);
+		store.set_fuel(self.fuel).map_err(|e| e.to_string())?;
+
+		let mut linker = Linker::new(&engine);
+		linker
+			.func_wrap(
+				"env",
+				"error",
+				|mut caller: Caller<'_, Host>, ptr: i32, len: i32| -> Result<(), wasmi::Error> {
+					let memory = caller
+						.get_export("memory")
+						.and_then(Extern::into_memory)
+						.ok_or_else(|| wasmi::Error::new("missing memory"))?;
+					let message = read_string(&caller, memory, ptr, len)?;
+					caller.data_mut().errors.push(message);
+					Ok(())
+				},
+			)
+			.map_err(|e| e.to_string())?;
+		let instance = linker
+			.instantiate(&mut store, &module)
+			.and_then(|instance| instance.start(&mut store))
+			.map_err(|e| e.to_string())?;
+		let memory = instance
+			.get_memory(&store, "memory")
+			.ok_or("missing memory export")?;
+		let alloc = instance
+			.get_typed_func::<i32, i32>(&store, "alloc")
+			.map_err(|e| e.to_string())?;
+		let run = instance
+			.get_typed_func::<(i32, i32), i64>(&store, "run")
+			.map_err(|e| e.to_string())?;
+
+		let len = i32::try_from(input.len()).map_err(|_| "input too large")?;
+		let ptr = alloc.call(&mut store, len).map_err(|e| e.to_string())?;
+		memory
+			.write(&mut store, ptr as u32 as usize, input.as_bytes())
+			.map_err(|e| e.to_string())?;
+		let output = run
+			.call(&mut store, (ptr, len))
+			.map_err(|e| e.to_string())?;
+		let output = read_string(&store, memory, (output >> 32) as i32, output as i32)
+			.map_err(|e| e.to_string())?;
+		Ok((output, store.into_data().errors))
+	}
+}
+
+/// Reads a string from the memory of a plugin, failing if it's out of the
+/// bounds of the memory.
+fn read_string<T>(
+	store: impl wasmi::AsContext<Data = T>,
+	memory: Memory,
+	ptr: i32,
+	len: i32,
+) -> Result<String, wasmi::Error> {
+	let (ptr, len) = (ptr as u32 as usize, len as u32 as usize);
+	let bytes = ptr
+		.checked_add(len)
+		.and_then(|end| memory.data(&store).get(ptr..end))
+		.ok_or_else(|| wasmi::Error::new("The output is out of the bounds of the memory"))?;
+	String::from_utf8(bytes.to_vec()).map_err(|e| wasmi::Error::new(e.to_string()))
+}
+
+impl Plugin for WasmPlugin {
+	fn run(
+		&self,
+		entries: Vec<Directive>,
+		options: &Options,
+		config: Option<&str>,
+	) -> (Vec<Directive>, Vec<Diagnostic>) {
+		let mut input = options.to_string();
+		input.push_str(&format!("plugin \"wasm:{}\"", self.path.display()));
+		if let Some(config) = config {
+			input.push_str(&format!(" {:?}", config));
+		}
+		input.push('\n');
+		// Entries are found back from how they are rendered
+		let mut locations: HashMap<String, VecDeque<SourceLocation>> = HashMap::new();
+		for entry in &entries {
+			let rendered = entry.to_string();
+			input.push_str(&format!("\n{}\n", rendered));
+			if let Some(location) = &entry.location {
+				locations
+					.entry(rendered)
+					.or_default()
+					.push_back(location.clone());
+			}
+		}
+
+		let failed = |message: String| {
+			Diagnostic::new(format!(
+				"Error in plugin {}: {}",
+				self.path.display(),
+				message
+			))
+		};
+		let (output, messages) = match self.call(&input) {
+			Ok(output) => output,
+			Err(e) => return (entries, vec![failed(e)]),
+		};
+		let mut errors: Vec<Diagnostic> = messages.into_iter().map(Diagnostic::new).collect();
+
+		let filename: Rc<str> = Rc::from(format!("{} output", self.path.display()));
+		let (statements, parse_errors) = parse_str(filename, &output);
+		if let Some(e) = parse_errors.first() {
+			errors.push(failed(format!("invalid output, {}", e)));
+			return (entries, errors);
+		}
+		let mut output = vec![];
+		for statement in statements {
+			let Statement::Directive(mut directive) = statement else {
+				continue;
+			};
+			directive.location = locations
+				.get_mut(&directive.to_string())
+				.and_then(|locations| locations.pop_front());
+			output.push(directive);
+		}
+		let (output, booking_errors) = book(output, options);
+		errors.extend(booking_errors);
+		(output, errors)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::rc::Rc;
+
+	use super::WasmPlugin;
+	use crate::core::directive::DirectiveKind;
+	use crate::loader::load_str_with;
+	use crate::plugins::PluginRegistry;
+
+	/// Reports a problem and adds a note to its input.
+	fn note_plugin() -> String {
+		let note = r#"\n2025-01-31 note Assets:Cash \"From wasm\"\n"#;
+		let len = note.replace(r#"\n"#, "\n").replace(r#"\""#, "\"").len();
+		format!(
+			r#"
+			(module
+				(import "env" "error" (func $error (param i32 i32)))
+				(memory (export "memory") 2)
+				(data (i32.const 0) "Checked by wasm")
+				(data (i32.const 16) "{note}")
+				(func (export "alloc") (param i32) (result i32)
+					i32.const 1024)
+				(func (export "run") (param $ptr i32) (param $len i32) (result i64)
+					(call $error (i32.const 0) (i32.const 15))
+					(memory.copy
+						(i32.add (local.get $ptr) (local.get $len))
+						(i32.const 16)
+						(i32.const {len}))
+					(i64.or
+						(i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+						(i64.extend_i32_u (i32.add (local.get $len) (i32.const {len}))))))
+			"#
+		)
+	}
+
+	/// Never returns.
+	const LOOP_PLUGIN: &str = r#"
+		(module
+			(memory (export "memory") 1)
+			(func (export "alloc") (param i32) (result i32)
+				i32.const 0)
+			(func (export "run") (param i32 i32) (result i64)
+				(loop $forever (br $forever))
+				unreachable))
+	"#;
+
+	/// Returns an output longer than its memory.
+	const OUT_OF_BOUNDS_PLUGIN: &str = r#"
+		(module
+			(memory (export "memory") 1)
+			(func (export "alloc") (param i32) (result i32)
+				i32.const 0)
+			(func (export "run") (param i32 i32) (result i64)
+				i64.const 0xffffffff))
+	"#;
+
+	const LEDGER: &str = r#"
+		2025-01-01 open Assets:Cash
+		2025-01-01 open Expenses:Food
+		2025-01-02 * "Lunch"
+			Expenses:Food 10.00 USD
+			Assets:Cash
+	"#;
+
+	#[test]
+	fn test_wasm() {
+		let dir = std::env::temp_dir();
+		let note_path = dir.join(format!("beancountr-{}-note.wasm", std::process::id()));
+		let loop_path = dir.join(format!("beancountr-{}-loop.wasm", std::process::id()));
+		let bounds_path = dir.join(format!("beancountr-{}-bounds.wasm", std::process::id()));
+		fs::write(&note_path, wat::parse_str(note_plugin()).unwrap()).unwrap();
+		fs::write(&loop_path, wat::parse_str(LOOP_PLUGIN).unwrap()).unwrap();
+		fs::write(&bounds_path, wat::parse_str(OUT_OF_BOUNDS_PLUGIN).unwrap()).unwrap();
+
+		let mut registry = PluginRegistry::new();
+		registry.register("note", WasmPlugin::new(&note_path));
+		registry.register(
+			"loop",
+			WasmPlugin::new(&loop_path).with_limits(100_000, 1 << 20),
+		);
+		registry.register("bounds", WasmPlugin::new(&bounds_path));
+		let ledger = load_str_with(
+			Rc::from("test"),
+			&format!("plugin \"note\"\n{}", LEDGER),
+			&registry,
+		);
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(errors, vec!["Checked by wasm"]);
+		let entries: Vec<String> = ledger
+			.directives
+			.iter()
+			.map(|directive| {
+				let line = directive.location.as_ref().map(|location| location.lineno);
+				format!("{} {:?}", directive, line)
+			})
+			.collect();
+		assert_eq!(
+			entries,
+			vec![
+				"2025-01-01 open Assets:Cash Some(3)",
+				"2025-01-01 open Expenses:Food Some(4)",
+				"2025-01-02 * \"Lunch\"\n  Expenses:Food  10.00 USD\n  Assets:Cash  -10.00 USD Some(5)",
+				"2025-01-31 note Assets:Cash \"From wasm\" None",
+			]
+		);
+
+		let ledger = load_str_with(
+			Rc::from("test"),
+			&format!("plugin \"loop\"\n{}", LEDGER),
+			&registry,
+		);
+		assert_eq!(ledger.errors.len(), 1);
+		assert!(ledger.errors[0].to_string().contains("fuel"));
+		assert!(ledger
+			.directives
+			.iter()
+			.any(|directive| matches!(directive.kind, DirectiveKind::Transaction { .. })));
+
+		let ledger = load_str_with(
+			Rc::from("test"),
+			&format!("plugin \"bounds\"\n{}", LEDGER),
+			&registry,
+		);
+		fs::remove_file(&note_path).unwrap();
+		fs::remove_file(&loop_path).unwrap();
+		fs::remove_file(&bounds_path).unwrap();
+		assert_eq!(ledger.errors.len(), 1);
+		assert!(ledger.errors[0].to_string().contains("out of the bounds"));
+	}
+}
@@ -0,0 +1,216 @@
+use std::fmt::Display;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+/// A parsed BQL statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+	Select(Select),
+	/// `BALANCES`, shorthand for the sum of positions of each account.
+	Balances {
+		summary_func: Option<String>,
+		from: Option<FromClause>,
+		where_clause: Option<Expr>,
+	},
+	/// `JOURNAL`, shorthand for the postings of accounts matching a pattern
+	/// with their running balance.
+	Journal {
+		account: Option<String>,
+		summary_func: Option<String>,
+		from: Option<FromClause>,
+	},
+	/// `PRINT`, renders the matching entries in Beancount syntax.
+	Print {
+		from: Option<FromClause>,
+	},
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+	pub distinct: bool,
+	pub targets: Targets,
+	pub from: Option<FromClause>,
+	pub where_clause: Option<Expr>,
+	pub group_by: Option<GroupBy>,
+	pub order_by: Vec<OrderBy>,
+	pub limit: Option<u64>,
+}
+
+/// The columns of a `SELECT`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Targets {
+	/// `SELECT *`, the default columns of the table.
+	All,
+	List(Vec<Target>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+	pub expr: Expr,
+	/// The name given with `AS`.
+	pub name: Option<String>,
+}
+
+/// The `FROM` clause, selecting a table and filtering the entries the rows
+/// are taken from, optionally summarizing the periods before and after.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FromClause {
+	/// The table given with `FROM #name` or by one of the known table names.
+	pub table: Option<String>,
+	/// An expression entries must match.
+	pub expr: Option<Expr>,
+	/// `OPEN ON`, summarizes entries before the date into opening balances.
+	pub open: Option<NaiveDate>,
+	/// `CLOSE [ON]`, drops entries from the date on, or just transfers the
+	/// earnings if no date is given.
+	pub close: Option<Option<NaiveDate>>,
+	/// `CLEAR`, transfers the income and expenses balances to equity.
+	pub clear: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupBy {
+	/// Expressions or 1-based indices of the targets to group by.
+	pub columns: Vec<Expr>,
+	pub having: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy {
+	/// An expression or a 1-based index of the target to order by.
+	pub expr: Expr,
+	pub descending: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+	Null,
+	Bool(bool),
+	Number(Decimal),
+	String(String),
+	Date(NaiveDate),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+	Not,
+	Neg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+	Or,
+	And,
+	Eq,
+	NotEq,
+	Lt,
+	LtEq,
+	Gt,
+	GtEq,
+	/// `~`, a regular expression search.
+	Match,
+	/// `!~`
+	NotMatch,
+	In,
+	NotIn,
+	Add,
+	Sub,
+	Mul,
+	Div,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+	Literal(Literal),
+	Column(String),
+	Function(String, Vec<Expr>),
+	/// `*` as the argument of a function, as in `count(*)`.
+	Wildcard,
+	/// A parenthesized list of values, used with `IN`.
+	Tuple(Vec<Expr>),
+	Unary(UnaryOp, Box<Expr>),
+	Binary(BinaryOp, Box<Expr>, Box<Expr>),
+	/// `IS NULL`, or `IS NOT NULL` if negated.
+	IsNull(Box<Expr>, bool),
+	/// `x BETWEEN a AND b`
+	Between(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+impl Display for Literal {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Literal::Null => write!(f, "NULL"),
+			Literal::Bool(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+			Literal::Number(n) => write!(f, "{}", n),
+			Literal::String(s) => {
+				write!(f, "'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+			}
+			Literal::Date(d) => write!(f, "{}", d),
+		}
+	}
+}
+
+impl Display for BinaryOp {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let op = match self {
+			BinaryOp::Or => "OR",
+			BinaryOp::And => "AND",
+			BinaryOp::Eq => "=",
+			BinaryOp::NotEq => "!=",
+			BinaryOp::Lt => "<",
+			BinaryOp::LtEq => "<=",
+			BinaryOp::Gt => ">",
+			BinaryOp::GtEq => ">=",
+			BinaryOp::Match => "~",
+			BinaryOp::NotMatch => "!~",
+			BinaryOp::In => "IN",
+			BinaryOp::NotIn => "NOT IN",
+			BinaryOp::Add => "+",
+			BinaryOp::Sub => "-",
+			BinaryOp::Mul => "*",
+			BinaryOp::Div => "/",
+		};
+		write!(f, "{}", op)
+	}
+}
+
+fn write_list(f: &mut std::fmt::Formatter<'_>, exprs: &[Expr]) -> std::fmt::Result {
+	for (i, expr) in exprs.iter().enumerate() {
+		if i > 0 {
+			write!(f, ", ")?;
+		}
+		write!(f, "{}", expr)?;
+	}
+	Ok(())
+}
+
+/// Renders the expression back to BQL, parenthesizing every operation so the
+/// result parses to the same tree.
+impl Display for Expr {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Expr::Literal(literal) => write!(f, "{}", literal),
+			Expr::Column(name) => write!(f, "{}", name),
+			Expr::Function(name, args) => {
+				write!(f, "{}(", name)?;
+				write_list(f, args)?;
+				write!(f, ")")
+			}
+			Expr::Wildcard => write!(f, "*"),
+			Expr::Tuple(exprs) => {
+				write!(f, "(")?;
+				write_list(f, exprs)?;
+				write!(f, ")")
+			}
+			Expr::Unary(UnaryOp::Not, expr) => write!(f, "(NOT {})", expr),
+			Expr::Unary(UnaryOp::Neg, expr) => write!(f, "(-{})", expr),
+			Expr::Binary(op, lhs, rhs) => write!(f, "({} {} {})", lhs, op, rhs),
+			Expr::IsNull(expr, false) => write!(f, "({} IS NULL)", expr),
+			Expr::IsNull(expr, true) => write!(f, "({} IS NOT NULL)", expr),
+			Expr::Between(expr, low, high) => {
+				write!(f, "({} BETWEEN {} AND {})", expr, low, high)
+			}
+		}
+	}
+}
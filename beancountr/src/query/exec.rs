@@ -0,0 +1,1524 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use chrono::{Datelike, NaiveDate, TimeDelta};
+use regex::Regex;
+use rust_decimal::prelude::*;
+
+use super::ast::{
+	BinaryOp, Expr, FromClause, GroupBy, Literal, OrderBy, Query, Select, Target, Targets, UnaryOp,
+};
+use super::value::Value;
+use crate::core::{
+	directive::{Directive, DirectiveKind, MetadataMap, Posting},
+	error::{BeanError, Result},
+	inventory::{Inventory, Position},
+	prices::PriceMap,
+	types::{Amount, Commodity},
+};
+use crate::loader::Ledger;
+use crate::summarize;
+
+/// The result of a query, with a name for each column.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryResult {
+	pub columns: Vec<String>,
+	pub rows: Vec<Vec<Value>>,
+}
+
+/// Columns available on every row.
+pub const ENTRY_COLUMNS: &[&str] = &[
+	"type",
+	"date",
+	"year",
+	"month",
+	"day",
+	"flag",
+	"payee",
+	"narration",
+	"description",
+	"tags",
+	"links",
+	"account",
+	"accounts",
+	"filename",
+	"lineno",
+];
+
+/// Columns only available on the rows of the postings table.
+pub const POSTING_COLUMNS: &[&str] = &[
+	"other_accounts",
+	"posting_flag",
+	"position",
+	"number",
+	"currency",
+	"cost_number",
+	"cost_currency",
+	"cost_date",
+	"cost_label",
+	"price",
+	"weight",
+	"balance",
+];
+
+/// Functions that compute a single value from the rows of a group.
+pub const AGGREGATES: &[&str] = &["sum", "count", "first", "last", "min", "max"];
+
+/// Functions that compute a value for each row.
+pub const FUNCTIONS: &[&str] = &[
+	"units",
+	"cost",
+	"value",
+	"convert",
+	"getprice",
+	"year",
+	"month",
+	"day",
+	"quarter",
+	"date",
+	"parent",
+	"root",
+	"leaf",
+	"account_sortkey",
+	"number",
+	"currency",
+	"abs",
+	"length",
+	"str",
+	"coalesce",
+	"meta",
+	"entry_meta",
+	"any_meta",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Table {
+	Entries,
+	Postings,
+}
+
+impl Table {
+	fn from_name(name: &str) -> Result<Table> {
+		match name {
+			"entries" => Ok(Table::Entries),
+			"postings" => Ok(Table::Postings),
+			_ => Err(invalid(format!("Unknown table '{}'", name))),
+		}
+	}
+
+	/// The columns of `SELECT *`.
+	fn default_columns(&self) -> &'static [&'static str] {
+		match self {
+			Table::Entries => &["date", "type", "flag", "payee", "narration"],
+			Table::Postings => &["date", "flag", "payee", "narration", "account", "position"],
+		}
+	}
+}
+
+/// Returns the columns that can be selected from a table.
+pub fn table_columns(table: &str) -> Result<Vec<&'static str>> {
+	Ok(match Table::from_name(table)? {
+		Table::Entries => ENTRY_COLUMNS.to_vec(),
+		Table::Postings => ENTRY_COLUMNS
+			.iter()
+			.chain(POSTING_COLUMNS)
+			.copied()
+			.collect(),
+	})
+}
+
+fn invalid(reason: impl Into<String>) -> BeanError {
+	BeanError::InvalidQuery(reason.into())
+}
+
+fn column(name: &str) -> Expr {
+	Expr::Column(name.to_string())
+}
+
+fn call(name: &str, args: Vec<Expr>) -> Expr {
+	Expr::Function(name.to_string(), args)
+}
+
+fn target(expr: Expr, name: Option<&str>) -> Target {
+	Target {
+		expr,
+		name: name.map(str::to_string),
+	}
+}
+
+/// Turns the `BALANCES` and `JOURNAL` shorthands into the `SELECT` they
+/// stand for.
+fn desugar(query: &Query) -> Select {
+	let summarize = |summary_func: &Option<String>, expr| match summary_func {
+		Some(f) => call(f, vec![expr]),
+		None => expr,
+	};
+	match query {
+		Query::Select(select) => select.clone(),
+		Query::Balances {
+			summary_func,
+			from,
+			where_clause,
+		} => Select {
+			distinct: false,
+			targets: Targets::List(vec![
+				target(column("account"), None),
+				target(
+					call("sum", vec![summarize(summary_func, column("position"))]),
+					None,
+				),
+			]),
+			from: from.clone(),
+			where_clause: where_clause.clone(),
+			group_by: Some(GroupBy {
+				columns: vec![column("account")],
+				having: None,
+			}),
+			order_by: vec![OrderBy {
+				expr: call("account_sortkey", vec![column("account")]),
+				descending: false,
+			}],
+			limit: None,
+		},
+		Query::Journal {
+			account,
+			summary_func,
+			from,
+		} => {
+			// Summarized columns keep their names
+			let alias = |name| summary_func.as_ref().map(|_| name);
+			Select {
+				distinct: false,
+				targets: Targets::List(vec![
+					target(column("date"), None),
+					target(column("flag"), None),
+					target(column("payee"), None),
+					target(column("narration"), None),
+					target(column("account"), None),
+					target(
+						summarize(summary_func, column("position")),
+						alias("position"),
+					),
+					target(summarize(summary_func, column("balance")), alias("balance")),
+				]),
+				from: from.clone(),
+				where_clause: account.as_ref().map(|account| {
+					Expr::Binary(
+						BinaryOp::Match,
+						Box::new(column("account")),
+						Box::new(Expr::Literal(Literal::String(account.clone()))),
+					)
+				}),
+				group_by: None,
+				order_by: vec![],
+				limit: None,
+			}
+		}
+		Query::Print { from } => Select {
+			distinct: false,
+			targets: Targets::All,
+			from: from.clone(),
+			where_clause: None,
+			group_by: None,
+			order_by: vec![],
+			limit: None,
+		},
+	}
+}
+
+/// Names a column after its alias, or after its expression.
+fn target_name(target: &Target) -> String {
+	match (&target.name, &target.expr) {
+		(Some(name), _) => name.clone(),
+		(None, Expr::Binary(..) | Expr::Unary(..) | Expr::IsNull(..) | Expr::Between(..)) => {
+			let s = target.expr.to_string();
+			s[1..s.len() - 1].to_string()
+		}
+		(None, expr) => expr.to_string(),
+	}
+}
+
+fn is_aggregate(name: &str) -> bool {
+	AGGREGATES.contains(&name.to_lowercase().as_str())
+}
+
+fn walk(expr: &Expr, f: &mut impl FnMut(&Expr) -> bool) {
+	if !f(expr) {
+		return;
+	}
+	match expr {
+		Expr::Function(_, args) | Expr::Tuple(args) => args.iter().for_each(|e| walk(e, f)),
+		Expr::Unary(_, e) | Expr::IsNull(e, _) => walk(e, f),
+		Expr::Binary(_, lhs, rhs) => {
+			walk(lhs, f);
+			walk(rhs, f);
+		}
+		Expr::Between(e, low, high) => {
+			walk(e, f);
+			walk(low, f);
+			walk(high, f);
+		}
+		Expr::Literal(_) | Expr::Column(_) | Expr::Wildcard => {}
+	}
+}
+
+fn has_aggregate(expr: &Expr) -> bool {
+	let mut found = false;
+	walk(expr, &mut |e| {
+		found |= matches!(e, Expr::Function(name, _) if is_aggregate(name));
+		!found
+	});
+	found
+}
+
+/// Collects the aggregate calls of an expression, without duplicates.
+fn collect_aggregates(expr: &Expr, aggregates: &mut Vec<Expr>) {
+	walk(expr, &mut |e| match e {
+		Expr::Function(name, _) if is_aggregate(name) => {
+			if !aggregates.contains(e) {
+				aggregates.push(e.clone());
+			}
+			false
+		}
+		_ => true,
+	});
+}
+
+fn uses_column(expr: &Expr, name: &str) -> bool {
+	let mut found = false;
+	walk(expr, &mut |e| {
+		found |= matches!(e, Expr::Column(c) if c == name);
+		!found
+	});
+	found
+}
+
+/// Replaces the names given to targets with `AS` by their expression.
+fn resolve_aliases(expr: &Expr, targets: &[Target]) -> Expr {
+	let resolve = |e: &Expr| resolve_aliases(e, targets);
+	let boxed = |e: &Expr| Box::new(resolve_aliases(e, targets));
+	match expr {
+		Expr::Column(name) => targets
+			.iter()
+			.find(|t| t.name.as_ref() == Some(name))
+			.map(|t| t.expr.clone())
+			.unwrap_or_else(|| expr.clone()),
+		Expr::Function(name, args) => {
+			Expr::Function(name.clone(), args.iter().map(resolve).collect())
+		}
+		Expr::Tuple(items) => Expr::Tuple(items.iter().map(resolve).collect()),
+		Expr::Unary(op, e) => Expr::Unary(*op, boxed(e)),
+		Expr::Binary(op, lhs, rhs) => Expr::Binary(*op, boxed(lhs), boxed(rhs)),
+		Expr::IsNull(e, not) => Expr::IsNull(boxed(e), *not),
+		Expr::Between(e, low, high) => Expr::Between(boxed(e), boxed(low), boxed(high)),
+		Expr::Literal(_) | Expr::Wildcard => expr.clone(),
+	}
+}
+
+/// Finds the target an expression of `GROUP BY` or `ORDER BY` refers to, by
+/// its 1-based index, its name or its expression.
+fn find_target(expr: &Expr, targets: &[Target], names: &[String]) -> Result<Option<usize>> {
+	match expr {
+		Expr::Literal(Literal::Number(n)) => match n.to_usize() {
+			Some(i) if i >= 1 && i <= names.len() && n.fract().is_zero() => Ok(Some(i - 1)),
+			_ => Err(invalid(format!("Column {} is out of range", n))),
+		},
+		Expr::Column(name) if names.contains(name) => Ok(names.iter().position(|n| n == name)),
+		_ => Ok(targets.iter().position(|t| t.expr == *expr)),
+	}
+}
+
+/// A row of a table: an entry, and one of its postings for the postings
+/// table.
+struct Row<'a> {
+	entry: &'a Directive,
+	posting: Option<&'a Posting>,
+	/// The running balance of the selected postings, if needed.
+	balance: Option<Inventory>,
+}
+
+/// The values an expression of a grouped query can use.
+struct Group<'r> {
+	keys: &'r [Expr],
+	values: &'r [Value],
+	aggregates: &'r [Expr],
+	results: &'r [Value],
+}
+
+enum Scope<'r, 'a> {
+	Row(&'r Row<'a>),
+	Group(&'r Group<'r>),
+}
+
+/// The state of an aggregate function over the rows of a group.
+enum Accumulator {
+	Sum(Value),
+	Count(u64),
+	First(Value),
+	Last(Value),
+	Min(Value),
+	Max(Value),
+}
+
+impl Accumulator {
+	fn new(name: &str) -> Accumulator {
+		match name.to_lowercase().as_str() {
+			"sum" => Accumulator::Sum(Value::Null),
+			"count" => Accumulator::Count(0),
+			"first" => Accumulator::First(Value::Null),
+			"last" => Accumulator::Last(Value::Null),
+			"min" => Accumulator::Min(Value::Null),
+			_ => Accumulator::Max(Value::Null),
+		}
+	}
+
+	/// Adds the value of a row, nulls are skipped.
+	fn update(&mut self, value: Value) -> Result<()> {
+		if value == Value::Null {
+			return Ok(());
+		}
+		match self {
+			Accumulator::Sum(sum) => *sum = add_values(std::mem::replace(sum, Value::Null), value)?,
+			Accumulator::Count(count) => *count += 1,
+			Accumulator::First(first) => {
+				if *first == Value::Null {
+					*first = value
+				}
+			}
+			Accumulator::Last(last) => *last = value,
+			Accumulator::Min(min) => {
+				if *min == Value::Null || value.sort_cmp(min).is_lt() {
+					*min = value
+				}
+			}
+			Accumulator::Max(max) => {
+				if *max == Value::Null || value.sort_cmp(max).is_gt() {
+					*max = value
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn finish(self) -> Value {
+		match self {
+			Accumulator::Count(count) => Value::Number(Decimal::from(count)),
+			Accumulator::Sum(value)
+			| Accumulator::First(value)
+			| Accumulator::Last(value)
+			| Accumulator::Min(value)
+			| Accumulator::Max(value) => value,
+		}
+	}
+}
+
+fn to_inventory(value: Value) -> Option<Inventory> {
+	match value {
+		Value::Amount(amount) => Some(Inventory::from(Position::from(amount))),
+		Value::Position(position) => Some(Inventory::from(position)),
+		Value::Inventory(inventory) => Some(inventory),
+		_ => None,
+	}
+}
+
+/// Sums two values, positions of any commodity sum into an inventory.
+fn add_values(lhs: Value, rhs: Value) -> Result<Value> {
+	match (lhs, rhs) {
+		(Value::Null, value) | (value, Value::Null) => Ok(match value {
+			Value::Amount(_) | Value::Position(_) => Value::Inventory(to_inventory(value).unwrap()),
+			value => value,
+		}),
+		(Value::Number(a), Value::Number(b)) => a
+			.checked_add(b)
+			.map(Value::Number)
+			.ok_or_else(|| overflow(BinaryOp::Add)),
+		(lhs, rhs) => match (
+			lhs.type_name(),
+			rhs.type_name(),
+			to_inventory(lhs),
+			to_inventory(rhs),
+		) {
+			(_, _, Some(mut lhs), Some(rhs)) => {
+				lhs.add_inventory(&rhs)
+					.map_err(|_| overflow(BinaryOp::Add))?;
+				Ok(Value::Inventory(lhs))
+			}
+			(lhs, rhs, _, _) => Err(invalid(format!("Cannot sum {} and {}", lhs, rhs))),
+		},
+	}
+}
+
+fn entry_type(entry: &Directive) -> &'static str {
+	match entry.kind {
+		DirectiveKind::Open(..) => "open",
+		DirectiveKind::Close(..) => "close",
+		DirectiveKind::Commodity(..) => "commodity",
+		DirectiveKind::Pad { .. } => "pad",
+		DirectiveKind::Balance { .. } => "balance",
+		DirectiveKind::Transaction { .. } => "transaction",
+		DirectiveKind::Note { .. } => "note",
+		DirectiveKind::Event { .. } => "event",
+		DirectiveKind::Query { .. } => "query",
+		DirectiveKind::Price { .. } => "price",
+		DirectiveKind::Document { .. } => "document",
+		DirectiveKind::Custom { .. } => "custom",
+	}
+}
+
+fn string_set<'a>(items: impl IntoIterator<Item = &'a String>) -> Value {
+	Value::Set(items.into_iter().cloned().collect())
+}
+
+fn optional<T>(value: Option<T>, f: impl FnOnce(T) -> Value) -> Value {
+	value.map(f).unwrap_or(Value::Null)
+}
+
+fn date_value(date: NaiveDate, f: impl FnOnce(NaiveDate) -> u32) -> Value {
+	Value::Number(Decimal::from(f(date)))
+}
+
+fn overflow(op: BinaryOp) -> BeanError {
+	invalid(format!("The result of {} is too large", op))
+}
+
+/// Reports an overflow in an operation on amounts as an invalid query.
+fn checked<T>(op: BinaryOp, result: Result<T>) -> Result<T> {
+	result.map_err(|e| match e {
+		BeanError::Overflow => overflow(op),
+		e => e,
+	})
+}
+
+/// Multiplies the units of a position, failing if they overflow.
+fn scale_position(position: &Position, n: Decimal) -> Result<Position> {
+	position
+		.units()
+		.number()
+		.checked_mul(n)
+		.map(|number| position.with_number(number))
+		.ok_or_else(|| overflow(BinaryOp::Mul))
+}
+
+fn add_days(date: NaiveDate, days: Decimal) -> Result<Value> {
+	days.to_i64()
+		.and_then(|days| date.checked_add_signed(TimeDelta::try_days(days)?))
+		.map(Value::Date)
+		.ok_or_else(|| invalid(format!("Cannot add {} days to {}", days, date)))
+}
+
+struct Executor<'a> {
+	ledger: &'a Ledger,
+	prices: PriceMap,
+	/// Regular expressions already compiled, by pattern.
+	regexes: RefCell<HashMap<String, Regex>>,
+}
+
+/// Runs a query against a ledger. `PRINT` returns a single `entry` column with
+/// each matching entry rendered in Beancount syntax.
+pub fn execute(query: &Query, ledger: &Ledger) -> Result<QueryResult> {
+	let executor = Executor {
+		ledger,
+		prices: PriceMap::from_directives(&ledger.directives),
+		regexes: RefCell::new(HashMap::new()),
+	};
+	match query {
+		Query::Print { from } => {
+			let rows = executor
+				.entries(from.as_ref())?
+				.into_iter()
+				.map(|entry| vec![Value::String(entry.to_string())])
+				.collect();
+			Ok(QueryResult {
+				columns: vec!["entry".to_string()],
+				rows,
+			})
+		}
+		query => executor.select(&desugar(query)),
+	}
+}
+
+impl<'a> Executor<'a> {
+	/// Returns the entries matching the `FROM` clause, then summarized by
+	/// its `OPEN`, `CLOSE` and `CLEAR` operations.
+	fn entries(&self, from: Option<&FromClause>) -> Result<Vec<Cow<'a, Directive>>> {
+		let Some(from) = from else {
+			return Ok(self.ledger.directives.iter().map(Cow::Borrowed).collect());
+		};
+		let mut entries = vec![];
+		for entry in &self.ledger.directives {
+			let row = Row {
+				entry,
+				posting: None,
+				balance: None,
+			};
+			let matches = match &from.expr {
+				Some(expr) => self.eval(expr, &Scope::Row(&row))?.is_truthy(),
+				None => true,
+			};
+			if matches {
+				entries.push(Cow::Borrowed(entry));
+			}
+		}
+		if from.open.is_none() && from.close.is_none() && !from.clear {
+			return Ok(entries);
+		}
+
+		let options = &self.ledger.options;
+		let mut entries: Vec<Directive> = entries.into_iter().map(Cow::into_owned).collect();
+		if let Some(date) = from.open {
+			entries = summarize::open(entries, date, options);
+		}
+		if let Some(date) = from.close {
+			entries = summarize::close(entries, date, options);
+		}
+		if from.clear {
+			entries = summarize::clear(entries, None, options);
+		}
+		Ok(entries.into_iter().map(Cow::Owned).collect())
+	}
+
+	fn select(&self, select: &Select) -> Result<QueryResult> {
+		let table = match select.from.as_ref().and_then(|from| from.table.as_ref()) {
+			Some(name) => Table::from_name(name)?,
+			None => Table::Postings,
+		};
+
+		let mut targets: Vec<Target> = match &select.targets {
+			Targets::All => table
+				.default_columns()
+				.iter()
+				.map(|name| target(column(name), None))
+				.collect(),
+			Targets::List(targets) => targets.clone(),
+		};
+		let names: Vec<String> = targets.iter().map(target_name).collect();
+		let visible = targets.len();
+
+		let group_keys = match &select.group_by {
+			Some(group_by) => Some(
+				group_by
+					.columns
+					.iter()
+					.map(|expr| {
+						Ok(match find_target(expr, &targets, &names)? {
+							Some(i) => targets[i].expr.clone(),
+							None => resolve_aliases(expr, &targets),
+						})
+					})
+					.collect::<Result<Vec<_>>>()?,
+			),
+			None => None,
+		};
+		let having = select
+			.group_by
+			.as_ref()
+			.and_then(|group_by| group_by.having.as_ref())
+			.map(|having| resolve_aliases(having, &targets));
+
+		// Orderings that aren't selected are computed as hidden columns
+		let mut ordering = vec![];
+		for order_by in &select.order_by {
+			let i = match find_target(&order_by.expr, &targets, &names)? {
+				Some(i) => i,
+				None => {
+					targets.push(target(resolve_aliases(&order_by.expr, &targets), None));
+					targets.len() - 1
+				}
+			};
+			ordering.push((i, order_by.descending));
+		}
+
+		let entries = self.entries(select.from.as_ref())?;
+		let rows = self.rows(table, &entries, select.where_clause.as_ref(), &targets)?;
+
+		let is_aggregate = group_keys.is_some()
+			|| having.is_some()
+			|| targets.iter().any(|t| has_aggregate(&t.expr));
+		let mut results = match is_aggregate {
+			false => rows
+				.iter()
+				.map(|row| {
+					targets
+						.iter()
+						.map(|t| self.eval(&t.expr, &Scope::Row(row)))
+						.collect::<Result<Vec<_>>>()
+				})
+				.collect::<Result<Vec<_>>>()?,
+			true => {
+				// Without GROUP BY, rows are grouped by the targets that aren't
+				// aggregates
+				let keys = group_keys.unwrap_or_else(|| {
+					targets
+						.iter()
+						.filter(|t| !has_aggregate(&t.expr))
+						.map(|t| t.expr.clone())
+						.collect()
+				});
+				self.aggregate(&rows, &keys, &targets, having.as_ref())?
+			}
+		};
+
+		if select.distinct {
+			let mut seen = HashSet::new();
+			results.retain(|row| seen.insert(row[..visible].to_vec()));
+		}
+
+		results.sort_by(|a, b| {
+			ordering
+				.iter()
+				.map(|(i, descending)| match descending {
+					false => a[*i].sort_cmp(&b[*i]),
+					true => b[*i].sort_cmp(&a[*i]),
+				})
+				.find(|ordering| ordering.is_ne())
+				.unwrap_or(std::cmp::Ordering::Equal)
+		});
+
+		if let Some(limit) = select.limit {
+			results.truncate(limit as usize);
+		}
+		for row in &mut results {
+			row.truncate(visible);
+		}
+
+		Ok(QueryResult {
+			columns: names,
+			rows: results,
+		})
+	}
+
+	/// Returns the rows of a table that match the `WHERE` clause.
+	fn rows<'e>(
+		&self,
+		table: Table,
+		entries: &'e [Cow<'a, Directive>],
+		where_clause: Option<&Expr>,
+		targets: &[Target],
+	) -> Result<Vec<Row<'e>>> {
+		let uses_balance = targets.iter().any(|t| uses_column(&t.expr, "balance"));
+		let mut balance = Inventory::new();
+		let mut rows = vec![];
+
+		for entry in entries {
+			let entry: &Directive = entry;
+			let candidates: Vec<Row> = match (&entry.kind, table) {
+				(_, Table::Entries) => vec![Row {
+					entry,
+					posting: None,
+					balance: None,
+				}],
+				(DirectiveKind::Transaction { postings, .. }, Table::Postings) => postings
+					.iter()
+					.map(|posting| Row {
+						entry,
+						posting: Some(posting),
+						balance: None,
+					})
+					.collect(),
+				_ => vec![],
+			};
+			for mut row in candidates {
+				if let Some(condition) = where_clause {
+					if !self.eval(condition, &Scope::Row(&row))?.is_truthy() {
+						continue;
+					}
+				}
+				if uses_balance {
+					let posting = row.posting.and_then(|p| Some((p, p.position()?)));
+					if let Some((posting, position)) = posting {
+						balance.add_position(&position).map_err(|_| {
+							invalid(format!("The balance after {} is too large", posting))
+						})?;
+					}
+					row.balance = Some(balance.clone());
+				}
+				rows.push(row);
+			}
+		}
+		Ok(rows)
+	}
+
+	fn aggregate(
+		&self,
+		rows: &[Row],
+		keys: &[Expr],
+		targets: &[Target],
+		having: Option<&Expr>,
+	) -> Result<Vec<Vec<Value>>> {
+		let mut aggregates = vec![];
+		for expr in targets.iter().map(|t| &t.expr).chain(having) {
+			collect_aggregates(expr, &mut aggregates);
+		}
+
+		let mut groups: Vec<(Vec<Value>, Vec<Accumulator>)> = vec![];
+		let mut index: HashMap<Vec<Value>, usize> = HashMap::new();
+		for row in rows {
+			let scope = Scope::Row(row);
+			let key = keys
+				.iter()
+				.map(|key| self.eval(key, &scope))
+				.collect::<Result<Vec<_>>>()?;
+			let i = *index.entry(key.clone()).or_insert_with(|| {
+				groups.push((key, aggregates.iter().map(new_accumulator).collect()));
+				groups.len() - 1
+			});
+			for (accumulator, aggregate) in groups[i].1.iter_mut().zip(&aggregates) {
+				let Expr::Function(name, args) = aggregate else {
+					unreachable!()
+				};
+				let value = match args.as_slice() {
+					[Expr::Wildcard] if name.eq_ignore_ascii_case("count") => Value::Bool(true),
+					[arg] => self.eval(arg, &scope)?,
+					_ => return Err(invalid(format!("{}() takes a single argument", name))),
+				};
+				accumulator.update(value)?;
+			}
+		}
+		// Aggregating no rows still gives a row, unless grouping
+		if groups.is_empty() && keys.is_empty() {
+			groups.push((vec![], aggregates.iter().map(new_accumulator).collect()));
+		}
+
+		let mut results = vec![];
+		for (values, accumulators) in groups {
+			let finished: Vec<Value> = accumulators.into_iter().map(Accumulator::finish).collect();
+			let group = Group {
+				keys,
+				values: &values,
+				aggregates: &aggregates,
+				results: &finished,
+			};
+			let scope = Scope::Group(&group);
+			if let Some(having) = having {
+				if !self.eval(having, &scope)?.is_truthy() {
+					continue;
+				}
+			}
+			results.push(
+				targets
+					.iter()
+					.map(|t| self.eval(&t.expr, &scope))
+					.collect::<Result<Vec<_>>>()?,
+			);
+		}
+		Ok(results)
+	}
+
+	fn eval(&self, expr: &Expr, scope: &Scope) -> Result<Value> {
+		if let Scope::Group(group) = scope {
+			if let Some(i) = group.keys.iter().position(|key| key == expr) {
+				return Ok(group.values[i].clone());
+			}
+			if let Some(i) = group.aggregates.iter().position(|a| a == expr) {
+				return Ok(group.results[i].clone());
+			}
+		}
+
+		match expr {
+			Expr::Literal(literal) => Ok(match literal {
+				Literal::Null => Value::Null,
+				Literal::Bool(b) => Value::Bool(*b),
+				Literal::Number(n) => Value::Number(*n),
+				Literal::String(s) => Value::String(s.clone()),
+				Literal::Date(d) => Value::Date(*d),
+			}),
+			Expr::Column(name) => match scope {
+				Scope::Row(row) => self.column(name, row),
+				Scope::Group(_) => Err(invalid(format!(
+					"Column '{}' must be grouped or used in an aggregate",
+					name
+				))),
+			},
+			Expr::Function(name, args) => {
+				let name = name.to_lowercase();
+				if is_aggregate(&name) {
+					return Err(invalid(format!(
+						"Aggregate function '{}' can't be used here",
+						name
+					)));
+				}
+				let args = args
+					.iter()
+					.map(|arg| self.eval(arg, scope))
+					.collect::<Result<Vec<_>>>()?;
+				match (name.as_str(), scope) {
+					("meta" | "entry_meta" | "any_meta", Scope::Row(row)) => {
+						self.meta(&name, &args, row)
+					}
+					("meta" | "entry_meta" | "any_meta", Scope::Group(_)) => Err(invalid(format!(
+						"{}() must be grouped or used in an aggregate",
+						name
+					))),
+					_ => self.call(&name, args),
+				}
+			}
+			Expr::Wildcard => Err(invalid("'*' can only be used in count(*)")),
+			Expr::Tuple(_) => Err(invalid("Lists can only be used with IN")),
+			Expr::Unary(UnaryOp::Not, expr) => {
+				Ok(Value::Bool(!self.eval(expr, scope)?.is_truthy()))
+			}
+			Expr::Unary(UnaryOp::Neg, expr) => match self.eval(expr, scope)? {
+				Value::Null => Ok(Value::Null),
+				Value::Number(n) => Ok(Value::Number(-n)),
+				Value::Amount(amount) => Ok(Value::Amount(-amount)),
+				Value::Position(position) => Ok(Value::Position(-&position)),
+				Value::Inventory(inventory) => Ok(Value::Inventory(-&inventory)),
+				value => Err(invalid(format!("Cannot negate a {}", value.type_name()))),
+			},
+			Expr::Binary(BinaryOp::And, lhs, rhs) => Ok(Value::Bool(
+				self.eval(lhs, scope)?.is_truthy() && self.eval(rhs, scope)?.is_truthy(),
+			)),
+			Expr::Binary(BinaryOp::Or, lhs, rhs) => Ok(Value::Bool(
+				self.eval(lhs, scope)?.is_truthy() || self.eval(rhs, scope)?.is_truthy(),
+			)),
+			Expr::Binary(op @ (BinaryOp::In | BinaryOp::NotIn), lhs, rhs) => {
+				let lhs = self.eval(lhs, scope)?;
+				if lhs == Value::Null {
+					return Ok(Value::Null);
+				}
+				let found = match rhs.as_ref() {
+					Expr::Tuple(items) => {
+						let mut found = false;
+						for item in items {
+							found |= self.eval(item, scope)? == lhs;
+						}
+						found
+					}
+					rhs => match (&lhs, self.eval(rhs, scope)?) {
+						(Value::String(s), Value::Set(set)) => set.contains(s),
+						(Value::String(s), Value::String(rhs)) => rhs.contains(s.as_str()),
+						(_, Value::Null) => false,
+						(lhs, rhs) => {
+							return Err(invalid(format!(
+								"Cannot look for a {} in a {}",
+								lhs.type_name(),
+								rhs.type_name()
+							)))
+						}
+					},
+				};
+				Ok(Value::Bool(found == (*op == BinaryOp::In)))
+			}
+			Expr::Binary(op, lhs, rhs) => {
+				let lhs = self.eval(lhs, scope)?;
+				let rhs = self.eval(rhs, scope)?;
+				self.binary(*op, lhs, rhs)
+			}
+			Expr::IsNull(expr, not) => Ok(Value::Bool(
+				(self.eval(expr, scope)? == Value::Null) != *not,
+			)),
+			Expr::Between(expr, low, high) => {
+				let value = self.eval(expr, scope)?;
+				let low = self.binary(BinaryOp::GtEq, value.clone(), self.eval(low, scope)?)?;
+				let high = self.binary(BinaryOp::LtEq, value, self.eval(high, scope)?)?;
+				Ok(Value::Bool(low.is_truthy() && high.is_truthy()))
+			}
+		}
+	}
+
+	fn binary(&self, op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value> {
+		if lhs == Value::Null || rhs == Value::Null {
+			return Ok(Value::Null);
+		}
+		let mismatch = |lhs: &Value, rhs: &Value| {
+			invalid(format!(
+				"Cannot apply {} to a {} and a {}",
+				op,
+				lhs.type_name(),
+				rhs.type_name()
+			))
+		};
+		let value = match op {
+			BinaryOp::Eq => Value::Bool(lhs == rhs),
+			BinaryOp::NotEq => Value::Bool(lhs != rhs),
+			BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq => {
+				let ordering = lhs
+					.partial_compare(&rhs)
+					.ok_or_else(|| mismatch(&lhs, &rhs))?;
+				Value::Bool(match op {
+					BinaryOp::Lt => ordering.is_lt(),
+					BinaryOp::LtEq => ordering.is_le(),
+					BinaryOp::Gt => ordering.is_gt(),
+					_ => ordering.is_ge(),
+				})
+			}
+			BinaryOp::Match | BinaryOp::NotMatch => {
+				let Value::String(pattern) = &rhs else {
+					return Err(mismatch(&lhs, &rhs));
+				};
+				let found = match &lhs {
+					Value::String(s) => self.is_match(pattern, s)?,
+					Value::Set(set) => {
+						let mut found = false;
+						for s in set {
+							found |= self.is_match(pattern, s)?;
+						}
+						found
+					}
+					_ => return Err(mismatch(&lhs, &rhs)),
+				};
+				Value::Bool(found == (op == BinaryOp::Match))
+			}
+			BinaryOp::Add => match (lhs, rhs) {
+				(Value::Number(a), Value::Number(b)) => {
+					Value::Number(a.checked_add(b).ok_or_else(|| overflow(op))?)
+				}
+				(Value::Amount(a), Value::Amount(b)) => {
+					Value::Amount(checked(op, a.checked_add(&b))?)
+				}
+				(Value::Date(date), Value::Number(days))
+				| (Value::Number(days), Value::Date(date)) => add_days(date, days)?,
+				(lhs @ Value::Inventory(_), rhs) | (rhs, lhs @ Value::Inventory(_)) => {
+					add_values(lhs, rhs)?
+				}
+				(lhs, rhs) => return Err(mismatch(&lhs, &rhs)),
+			},
+			BinaryOp::Sub => match (lhs, rhs) {
+				(Value::Number(a), Value::Number(b)) => {
+					Value::Number(a.checked_sub(b).ok_or_else(|| overflow(op))?)
+				}
+				(Value::Amount(a), Value::Amount(b)) => {
+					Value::Amount(checked(op, a.checked_sub(&b))?)
+				}
+				(Value::Date(a), Value::Date(b)) => {
+					Value::Number(Decimal::from((a - b).num_days()))
+				}
+				(Value::Date(date), Value::Number(days)) => add_days(date, -days)?,
+				(lhs @ Value::Inventory(_), rhs) => {
+					let rhs = self.binary(BinaryOp::Mul, rhs, Value::Number(-Decimal::ONE))?;
+					add_values(lhs, rhs)?
+				}
+				(lhs, rhs) => return Err(mismatch(&lhs, &rhs)),
+			},
+			BinaryOp::Mul => match (lhs, rhs) {
+				(Value::Number(a), Value::Number(b)) => {
+					Value::Number(a.checked_mul(b).ok_or_else(|| overflow(op))?)
+				}
+				(Value::Amount(a), Value::Number(n)) | (Value::Number(n), Value::Amount(a)) => {
+					Value::Amount(checked(op, a.checked_mul(n))?)
+				}
+				(Value::Position(p), Value::Number(n)) | (Value::Number(n), Value::Position(p)) => {
+					Value::Position(scale_position(&p, n)?)
+				}
+				(Value::Inventory(i), Value::Number(n))
+				| (Value::Number(n), Value::Inventory(i)) => {
+					let positions = i
+						.iter()
+						.map(|p| scale_position(p, n))
+						.collect::<Result<Vec<_>>>()?;
+					Value::Inventory(checked(op, Inventory::sum(positions))?)
+				}
+				(lhs, rhs) => return Err(mismatch(&lhs, &rhs)),
+			},
+			BinaryOp::Div => match (lhs, rhs) {
+				(_, Value::Number(n)) if n.is_zero() => Value::Null,
+				(Value::Number(a), Value::Number(b)) => {
+					Value::Number(a.checked_div(b).ok_or_else(|| overflow(op))?)
+				}
+				(Value::Amount(a), Value::Number(n)) => {
+					Value::Amount(checked(op, a.checked_div(n))?)
+				}
+				(lhs, rhs) => return Err(mismatch(&lhs, &rhs)),
+			},
+			BinaryOp::And | BinaryOp::Or | BinaryOp::In | BinaryOp::NotIn => {
+				unreachable!("handled by eval")
+			}
+		};
+		Ok(value)
+	}
+
+	fn is_match(&self, pattern: &str, s: &str) -> Result<bool> {
+		let mut regexes = self.regexes.borrow_mut();
+		if !regexes.contains_key(pattern) {
+			let regex = Regex::new(pattern)
+				.map_err(|e| invalid(format!("Invalid regular expression '{}': {}", pattern, e)))?;
+			regexes.insert(pattern.to_string(), regex);
+		}
+		Ok(regexes[pattern].is_match(s))
+	}
+
+	fn column(&self, name: &str, row: &Row) -> Result<Value> {
+		let entry = row.entry;
+		let transaction = match &entry.kind {
+			DirectiveKind::Transaction {
+				flag,
+				payee,
+				narration,
+				tags,
+				links,
+				postings,
+			} => Some((flag, payee, narration, tags, links, postings)),
+			_ => None,
+		};
+		let value = match name {
+			"type" => Value::String(entry_type(entry).to_string()),
+			"date" => Value::Date(entry.date),
+			"year" => Value::Number(Decimal::from(entry.date.year())),
+			"month" => date_value(entry.date, |d| d.month()),
+			"day" => date_value(entry.date, |d| d.day()),
+			"flag" => optional(transaction.and_then(|t| *t.0), |flag| {
+				Value::String(flag.to_string())
+			}),
+			"payee" => optional(transaction.and_then(|t| t.1.clone()), Value::String),
+			"narration" => optional(transaction.and_then(|t| t.2.clone()), Value::String),
+			"description" => optional(transaction, |t| {
+				let parts: Vec<&str> = [t.1, t.2]
+					.into_iter()
+					.flatten()
+					.map(|s| s.as_str())
+					.collect();
+				Value::String(parts.join(" | "))
+			}),
+			"tags" | "links" => match &entry.kind {
+				DirectiveKind::Transaction { tags, links, .. }
+				| DirectiveKind::Note { tags, links, .. }
+				| DirectiveKind::Document { tags, links, .. } => {
+					string_set(if name == "tags" { tags } else { links })
+				}
+				_ => Value::Set(BTreeSet::new()),
+			},
+			"account" => match row.posting {
+				Some(posting) => Value::String(posting.account().to_string()),
+				None => optional(entry.accounts().first(), |account| {
+					Value::String(account.to_string())
+				}),
+			},
+			"accounts" => Value::Set(entry.accounts().iter().map(|a| a.to_string()).collect()),
+			"filename" => optional(entry.location.as_ref(), |location| {
+				Value::String(location.filename.to_string())
+			}),
+			"lineno" => optional(entry.location.as_ref(), |location| {
+				Value::Number(Decimal::from(location.lineno))
+			}),
+			name if POSTING_COLUMNS.contains(&name) => {
+				let Some(posting) = row.posting else {
+					return Err(invalid(format!(
+						"Column '{}' is only available in the postings table",
+						name
+					)));
+				};
+				self.posting_column(name, posting, row)?
+			}
+			_ => return Err(invalid(format!("Unknown column '{}'", name))),
+		};
+		Ok(value)
+	}
+
+	fn posting_column(&self, name: &str, posting: &Posting, row: &Row) -> Result<Value> {
+		let cost = posting
+			.position()
+			.and_then(|position| position.cost().cloned());
+		let value = match name {
+			"other_accounts" => Value::Set(
+				row.entry
+					.accounts()
+					.into_iter()
+					.filter(|account| *account != posting.account())
+					.map(|account| account.to_string())
+					.collect(),
+			),
+			"posting_flag" => optional(posting.flag(), |flag| Value::String(flag.to_string())),
+			"position" => optional(posting.position(), Value::Position),
+			"number" => optional(posting.units(), |units| Value::Number(units.number())),
+			"currency" => optional(posting.units(), |units| {
+				Value::String(units.commodity().to_string())
+			}),
+			"cost_number" => optional(cost.as_ref(), |cost| Value::Number(cost.number())),
+			"cost_currency" => optional(cost.as_ref(), |cost| {
+				Value::String(cost.commodity().to_string())
+			}),
+			"cost_date" => optional(cost.as_ref(), |cost| Value::Date(cost.date())),
+			"cost_label" => optional(cost.as_ref().and_then(|cost| cost.label()), |label| {
+				Value::String(label.to_string())
+			}),
+			"price" => optional(posting.price(), |price| Value::Amount(price.clone())),
+			"weight" => optional(
+				posting
+					.weight()
+					.map_err(|_| invalid(format!("The weight of {} is too large", posting)))?,
+				Value::Amount,
+			),
+			"balance" => optional(row.balance.clone(), Value::Inventory),
+			_ => Value::Null,
+		};
+		Ok(value)
+	}
+
+	fn meta(&self, name: &str, args: &[Value], row: &Row) -> Result<Value> {
+		let [Value::String(key)] = args else {
+			return Err(invalid(format!("{}() takes the key as a string", name)));
+		};
+		let get = |meta: &MetadataMap| meta.get(key).map(Value::from);
+		let posting_meta = row.posting.and_then(|posting| get(&posting.meta));
+		let value = match name {
+			"meta" => posting_meta,
+			"entry_meta" => get(&row.entry.meta),
+			_ => posting_meta.or_else(|| get(&row.entry.meta)),
+		};
+		Ok(value.unwrap_or(Value::Null))
+	}
+
+	/// Converts a position to a commodity, directly or through the commodity
+	/// of its cost. Positions without a known rate are kept as they are.
+	fn convert_position(
+		&self,
+		position: &Position,
+		commodity: &Commodity,
+		date: Option<NaiveDate>,
+	) -> Amount {
+		self.prices
+			.convert(position.units(), commodity, date)
+			.or_else(|| {
+				let value = self.prices.value(position, date);
+				self.prices.convert(&value, commodity, date)
+			})
+			.unwrap_or_else(|| position.units().clone())
+	}
+
+	fn call(&self, name: &str, args: Vec<Value>) -> Result<Value> {
+		if name == "coalesce" {
+			return Ok(args
+				.into_iter()
+				.find(|v| *v != Value::Null)
+				.unwrap_or(Value::Null));
+		}
+		if args.contains(&Value::Null) {
+			return Ok(Value::Null);
+		}
+		let date_arg = |date: Option<&Value>| match date {
+			Some(Value::Date(date)) => Ok(Some(*date)),
+			None => Ok(None),
+			Some(_) => Err(invalid(format!(
+				"{}() takes a date as its last argument",
+				name
+			))),
+		};
+		let too_large = |_| invalid(format!("The result of {}() is too large", name));
+		let amounts = |inventory: &Inventory, f: &dyn Fn(&Position) -> Amount| {
+			inventory
+				.map(|p| Position::from(f(p)))
+				.map(Value::Inventory)
+				.map_err(too_large)
+		};
+
+		let value = match (name, args.as_slice()) {
+			("units", [Value::Amount(a)]) => Value::Amount(a.clone()),
+			("units", [Value::Position(p)]) => Value::Amount(p.units().clone()),
+			("units", [Value::Inventory(i)]) => amounts(i, &|p| p.units().clone())?,
+			("cost", [Value::Amount(a)]) => Value::Amount(a.clone()),
+			("cost", [Value::Position(p)]) => Value::Amount(p.at_cost()),
+			("cost", [Value::Inventory(i)]) => amounts(i, &Position::at_cost)?,
+			("value", [value, rest @ ..]) if rest.len() <= 1 => {
+				let date = date_arg(rest.first())?;
+				match value {
+					Value::Amount(a) => Value::Amount(a.clone()),
+					Value::Position(p) => Value::Amount(self.prices.value(p, date)),
+					Value::Inventory(i) => {
+						Value::Inventory(self.prices.value_inventory(i, date).map_err(too_large)?)
+					}
+					_ => return self.bad_arguments(name, &args),
+				}
+			}
+			("convert", [value, Value::String(commodity), rest @ ..]) if rest.len() <= 1 => {
+				let date = date_arg(rest.first())?;
+				let commodity: Commodity = commodity.parse()?;
+				match value {
+					Value::Amount(a) => {
+						Value::Amount(self.convert_position(&a.clone().into(), &commodity, date))
+					}
+					Value::Position(p) => Value::Amount(self.convert_position(p, &commodity, date)),
+					Value::Inventory(i) => {
+						amounts(i, &|p| self.convert_position(p, &commodity, date))?
+					}
+					_ => return self.bad_arguments(name, &args),
+				}
+			}
+			("getprice", [Value::String(base), Value::String(quote), rest @ ..])
+				if rest.len() <= 1 =>
+			{
+				let date = date_arg(rest.first())?;
+				optional(
+					self.prices.get_rate(&base.parse()?, &quote.parse()?, date),
+					|(_, rate)| Value::Number(rate),
+				)
+			}
+			("year", [Value::Date(d)]) => Value::Number(Decimal::from(d.year())),
+			("month", [Value::Date(d)]) => date_value(*d, |d| d.month()),
+			("day", [Value::Date(d)]) => date_value(*d, |d| d.day()),
+			("quarter", [Value::Date(d)]) => {
+				Value::String(format!("{}-Q{}", d.year(), d.month0() / 3 + 1))
+			}
+			("date", [Value::Number(y), Value::Number(m), Value::Number(d)]) => optional(
+				NaiveDate::from_ymd_opt(
+					y.to_i32().unwrap_or_default(),
+					m.to_u32().unwrap_or_default(),
+					d.to_u32().unwrap_or_default(),
+				),
+				Value::Date,
+			),
+			("parent", [Value::String(account)]) => {
+				optional(account.rsplit_once(':'), |(parent, _)| {
+					Value::String(parent.to_string())
+				})
+			}
+			("root", [Value::String(account), Value::Number(n)]) => {
+				let n = n.to_usize().unwrap_or_default();
+				Value::String(account.split(':').take(n).collect::<Vec<_>>().join(":"))
+			}
+			("leaf", [Value::String(account)]) => {
+				Value::String(account.rsplit(':').next().unwrap_or_default().to_string())
+			}
+			("account_sortkey", [Value::String(account)]) => {
+				let options = &self.ledger.options;
+				let root = account.split(':').next().unwrap_or_default();
+				let index = [
+					&options.name_assets,
+					&options.name_liabilities,
+					&options.name_equity,
+					&options.name_income,
+					&options.name_expenses,
+				]
+				.iter()
+				.position(|name| *name == root)
+				.unwrap_or(5);
+				Value::String(format!("{}-{}", index, account))
+			}
+			("number", [Value::Amount(a)]) => Value::Number(a.number()),
+			("number", [Value::Position(p)]) => Value::Number(p.units().number()),
+			("currency", [Value::Amount(a)]) => Value::String(a.commodity().to_string()),
+			("currency", [Value::Position(p)]) => Value::String(p.units().commodity().to_string()),
+			("abs", [Value::Number(n)]) => Value::Number(n.abs()),
+			("abs", [Value::Amount(a)]) => Value::Amount(a.abs()),
+			("length", [Value::String(s)]) => Value::Number(Decimal::from(s.chars().count())),
+			("length", [Value::Set(set)]) => Value::Number(Decimal::from(set.len())),
+			("length", [Value::Inventory(i)]) => Value::Number(Decimal::from(i.len())),
+			("str", [value]) => Value::String(value.to_string()),
+			_ => return self.bad_arguments(name, &args),
+		};
+		Ok(value)
+	}
+
+	fn bad_arguments(&self, name: &str, args: &[Value]) -> Result<Value> {
+		if !FUNCTIONS.contains(&name) {
+			return Err(invalid(format!("Unknown function '{}'", name)));
+		}
+		let types: Vec<&str> = args.iter().map(Value::type_name).collect();
+		Err(invalid(format!(
+			"Invalid arguments for {}({})",
+			name,
+			types.join(", ")
+		)))
+	}
+}
+
+fn new_accumulator(aggregate: &Expr) -> Accumulator {
+	match aggregate {
+		Expr::Function(name, _) => Accumulator::new(name),
+		_ => unreachable!("only aggregate calls are collected"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::loader::load_str;
+	use crate::query::parse_query;
+
+	const LEDGER: &str = r#"
+		2025-01-01 open Assets:Cash
+		2025-01-01 open Assets:Invest
+		2025-01-01 open Expenses:Food
+		2025-01-01 open Income:Salary
+		2025-01-05 * "Employer" "Pay" #work
+			Assets:Cash 1000.00 USD
+			Income:Salary
+		2025-01-10 * "Grocer" "Food"
+			category: "groceries"
+			Expenses:Food 40.00 USD
+			Assets:Cash
+		2025-01-15 * "Food again"
+			Expenses:Food 60.00 USD
+			Assets:Cash
+		2025-01-20 * "Buy"
+			Assets:Invest 10 HOOL {50.00 USD}
+			Assets:Cash
+		2025-02-01 price HOOL 60.00 USD
+		"#;
+
+	fn query(src: &str) -> Vec<Vec<String>> {
+		let ledger = load_str(Rc::from("test"), LEDGER);
+		assert!(ledger.errors.is_empty(), "{:?}", ledger.errors);
+		let query = parse_query(src).unwrap();
+		let result = execute(&query, &ledger).unwrap();
+		result
+			.rows
+			.iter()
+			.map(|row| row.iter().map(|v| v.to_string()).collect())
+			.collect()
+	}
+
+	fn error(src: &str) -> String {
+		let ledger = load_str(Rc::from("test"), LEDGER);
+		execute(&parse_query(src).unwrap(), &ledger)
+			.unwrap_err()
+			.to_string()
+	}
+
+	#[test]
+	fn test_select() {
+		assert_eq!(
+			query("SELECT date, account, position WHERE account ~ '^Expenses' ORDER BY date DESC"),
+			vec![
+				vec!["2025-01-15", "Expenses:Food", "60.00 USD"],
+				vec!["2025-01-10", "Expenses:Food", "40.00 USD"],
+			]
+		);
+		assert_eq!(
+			query("SELECT narration, any_meta('category') WHERE 'work' IN tags OR entry_meta('category') = 'groceries'"),
+			vec![vec!["Pay", ""], vec!["Pay", ""], vec!["Food", "groceries"], vec!["Food", "groceries"]]
+		);
+		assert_eq!(
+			query("SELECT DISTINCT payee FROM year = 2025 AND payee IS NOT NULL ORDER BY 1"),
+			vec![vec!["Employer"], vec!["Grocer"]]
+		);
+		assert_eq!(
+			query("SELECT DISTINCT abs(number), currency WHERE narration = 'Pay'"),
+			vec![vec!["1000.00", "USD"]]
+		);
+		assert_eq!(
+			query("SELECT type, count(*) FROM entries GROUP BY type ORDER BY type LIMIT 2"),
+			vec![vec!["open", "4"], vec!["price", "1"]]
+		);
+	}
+
+	#[test]
+	fn test_aggregates() {
+		assert_eq!(
+			query("SELECT account, sum(position), count(*) AS n GROUP BY account HAVING n > 1 ORDER BY account"),
+			vec![
+				vec!["Assets:Cash", "400.00 USD", "4"],
+				vec!["Expenses:Food", "100.00 USD", "2"],
+			]
+		);
+		assert_eq!(
+			query("SELECT sum(number), min(date), max(date) WHERE account = 'Expenses:Food'"),
+			vec![vec!["100.00", "2025-01-10", "2025-01-15"]]
+		);
+		assert_eq!(
+			query("SELECT sum(number) WHERE account = 'Nothing'"),
+			vec![vec![""]]
+		);
+		assert_eq!(
+			query(
+				"SELECT value(sum(position)), cost(sum(position)) WHERE account = 'Assets:Invest'"
+			),
+			vec![vec!["600.00 USD", "500.00 USD"]]
+		);
+	}
+
+	#[test]
+	fn test_balances_and_journal() {
+		assert_eq!(
+			query("BALANCES"),
+			vec![
+				vec!["Assets:Cash", "400.00 USD"],
+				vec!["Assets:Invest", "10 HOOL {50.00 USD, 2025-01-20}"],
+				vec!["Income:Salary", "-1000.00 USD"],
+				vec!["Expenses:Food", "100.00 USD"],
+			]
+		);
+		assert_eq!(
+			query("JOURNAL 'Expenses' AT cost"),
+			vec![
+				vec![
+					"2025-01-10",
+					"*",
+					"Grocer",
+					"Food",
+					"Expenses:Food",
+					"40.00 USD",
+					"40.00 USD"
+				],
+				vec![
+					"2025-01-15",
+					"*",
+					"",
+					"Food again",
+					"Expenses:Food",
+					"60.00 USD",
+					"100.00 USD"
+				],
+			]
+		);
+		let printed = query("PRINT FROM type = 'price'");
+		assert_eq!(printed, vec![vec!["2025-02-01 price HOOL 60.00 USD"]]);
+	}
+
+	#[test]
+	fn test_open_close_clear() {
+		assert_eq!(
+			query("SELECT account, sum(position) FROM OPEN ON 2025-01-10 CLOSE ON 2025-01-16 GROUP BY 1 ORDER BY 1"),
+			vec![
+				vec!["Assets:Cash", "900.00 USD"],
+				vec!["Equity:Earnings:Previous", "-1000.00 USD"],
+				vec!["Equity:Opening-Balances", ""],
+				vec!["Expenses:Food", "100.00 USD"],
+			]
+		);
+		assert_eq!(
+			query("SELECT account, sum(position) FROM CLOSE ON 2025-01-16 CLEAR WHERE account ~ '^(Income|Equity)' GROUP BY 1 ORDER BY 1"),
+			vec![
+				vec!["Equity:Earnings:Current", "-900.00 USD"],
+				vec!["Income:Salary", ""],
+			]
+		);
+	}
+
+	#[test]
+	fn test_errors() {
+		assert_eq!(error("SELECT foo"), "Invalid query: Unknown column 'foo'");
+		assert_eq!(
+			error("SELECT account, sum(number) GROUP BY date"),
+			"Invalid query: Column 'account' must be grouped or used in an aggregate"
+		);
+		assert_eq!(
+			error("SELECT position FROM entries"),
+			"Invalid query: Column 'position' is only available in the postings table"
+		);
+		assert_eq!(
+			error("SELECT date + payee"),
+			"Invalid query: Cannot apply + to a date and a string"
+		);
+		assert_eq!(
+			error("SELECT date ORDER BY 3"),
+			"Invalid query: Column 3 is out of range"
+		);
+	}
+
+	#[test]
+	fn test_overflow() {
+		assert_eq!(
+			error("SELECT 79228162514264337593543950335 * 2"),
+			"Invalid query: The result of * is too large"
+		);
+		assert_eq!(
+			error("SELECT 79228162514264337593543950335 + 1"),
+			"Invalid query: The result of + is too large"
+		);
+		assert_eq!(
+			error("SELECT position * 79228162514264337593543950335"),
+			"Invalid query: The result of * is too large"
+		);
+		assert_eq!(
+			error("SELECT units(position) / 0.0000000000000000000000000001"),
+			"Invalid query: The result of / is too large"
+		);
+		assert_eq!(
+			error("SELECT sum(position * 1000000000000000000000000000) WHERE account = 'Expenses:Food'"),
+			"Invalid query: The result of + is too large"
+		);
+	}
+}
@@ -0,0 +1,10 @@
+pub mod ast;
+pub mod exec;
+pub mod parser;
+pub mod value;
+
+pub use exec::{execute, QueryResult};
+pub use parser::parse_query;
+
+/// The tables rows can be selected from.
+pub const TABLES: &[&str] = &["entries", "postings"];
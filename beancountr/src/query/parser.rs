@@ -0,0 +1,694 @@
+// chumsky's `select!` closures return its large `Simple` error type
+#![allow(clippy::result_large_err)]
+
+use std::fmt;
+use std::ops::Range;
+
+use chrono::NaiveDate;
+use chumsky::{error::Simple, prelude::*, text, Parser, Stream};
+use rust_decimal::prelude::*;
+
+use super::ast::{
+	BinaryOp, Expr, FromClause, GroupBy, Literal, OrderBy, Query, Select, Target, Targets, UnaryOp,
+};
+use super::TABLES;
+
+/// Words with a meaning in BQL, matched without regard to case. They can't be
+/// used as column or function names.
+const KEYWORDS: &[&str] = &[
+	"SELECT", "DISTINCT", "FROM", "WHERE", "GROUP", "BY", "HAVING", "ORDER", "ASC", "DESC",
+	"LIMIT", "AS", "AND", "OR", "NOT", "IN", "IS", "NULL", "TRUE", "FALSE", "BETWEEN", "BALANCES",
+	"JOURNAL", "PRINT", "AT", "OPEN", "CLOSE", "ON", "CLEAR",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Token {
+	Keyword(&'static str),
+	Ident(String),
+	Number(Decimal),
+	String(String),
+	Date(NaiveDate),
+
+	Eq,         // =
+	NotEq,      // != or <>
+	Lt,         // <
+	LtEq,       // <=
+	Gt,         // >
+	GtEq,       // >=
+	Tilde,      // ~
+	NotTilde,   // !~
+	Plus,       // +
+	Minus,      // -
+	Star,       // *
+	Slash,      // /
+	LeftParen,  // (
+	RightParen, // )
+	Comma,      // ,
+	Semicolon,  // ;
+	Hash,       // #
+}
+
+impl fmt::Display for Token {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Token::Keyword(k) => write!(f, "{}", k),
+			Token::Ident(s) => write!(f, "{}", s),
+			Token::Number(n) => write!(f, "{}", n),
+			Token::String(s) => write!(f, "'{}'", s),
+			Token::Date(d) => write!(f, "{}", d),
+			Token::Eq => write!(f, "="),
+			Token::NotEq => write!(f, "!="),
+			Token::Lt => write!(f, "<"),
+			Token::LtEq => write!(f, "<="),
+			Token::Gt => write!(f, ">"),
+			Token::GtEq => write!(f, ">="),
+			Token::Tilde => write!(f, "~"),
+			Token::NotTilde => write!(f, "!~"),
+			Token::Plus => write!(f, "+"),
+			Token::Minus => write!(f, "-"),
+			Token::Star => write!(f, "*"),
+			Token::Slash => write!(f, "/"),
+			Token::LeftParen => write!(f, "("),
+			Token::RightParen => write!(f, ")"),
+			Token::Comma => write!(f, ","),
+			Token::Semicolon => write!(f, ";"),
+			Token::Hash => write!(f, "#"),
+		}
+	}
+}
+
+pub fn lexer() -> impl Parser<char, Vec<(Token, Range<usize>)>, Error = Simple<char>> {
+	let digits = |n| {
+		filter(|c: &char| c.is_ascii_digit())
+			.repeated()
+			.exactly(n)
+			.collect::<String>()
+	};
+
+	let date = digits(4)
+		.then_ignore(just('-'))
+		.then(digits(2))
+		.then_ignore(just('-'))
+		.then(digits(2))
+		.try_map(|((year, month), day), span| {
+			NaiveDate::from_ymd_opt(
+				year.parse().unwrap(),
+				month.parse().unwrap(),
+				day.parse().unwrap(),
+			)
+			.ok_or_else(|| Simple::custom(span, "Invalid date"))
+			.map(Token::Date)
+		})
+		.boxed();
+
+	let number = text::digits(10)
+		.chain::<char, _, _>(
+			just('.')
+				.chain(filter(|c: &char| c.is_ascii_digit()).repeated())
+				.or_not()
+				.flatten(),
+		)
+		.or(just('.').chain(text::digits(10)))
+		.collect::<String>()
+		.try_map(|s, span| {
+			Decimal::from_str(&s)
+				.map(Token::Number)
+				.map_err(|e| Simple::custom(span, format!("Error parsing decimal: {}", e)))
+		})
+		.boxed();
+
+	let escape = just('\\').ignore_then(
+		just('\\')
+			.or(just('\''))
+			.or(just('"'))
+			.or(just('n').to('\n'))
+			.or(just('t').to('\t'))
+			.map(String::from)
+			// Other escapes are kept, like `\d` in a regular expression
+			.or(any().map(|c| format!("\\{}", c))),
+	);
+	let quoted = |quote: char| {
+		just(quote)
+			.ignore_then(
+				filter(move |c| *c != '\\' && *c != quote)
+					.map(String::from)
+					.or(escape)
+					.repeated(),
+			)
+			.then_ignore(just(quote))
+			.map(|parts| parts.concat())
+	};
+	let string = quoted('\'')
+		.or(quoted('"'))
+		.map(Token::String)
+		.labelled("string")
+		.boxed();
+
+	let word = text::ident().map(|s: String| {
+		let upper = s.to_uppercase();
+		match KEYWORDS.iter().find(|k| **k == upper) {
+			Some(keyword) => Token::Keyword(keyword),
+			None => Token::Ident(s),
+		}
+	});
+
+	let punctuation = choice((
+		just("!=").to(Token::NotEq),
+		just("<>").to(Token::NotEq),
+		just("!~").to(Token::NotTilde),
+		just("<=").to(Token::LtEq),
+		just(">=").to(Token::GtEq),
+		just('<').to(Token::Lt),
+		just('>').to(Token::Gt),
+		just('=').to(Token::Eq),
+		just('~').to(Token::Tilde),
+		just('+').to(Token::Plus),
+		just('-').to(Token::Minus),
+		just('*').to(Token::Star),
+		just('/').to(Token::Slash),
+		just('(').to(Token::LeftParen),
+		just(')').to(Token::RightParen),
+		just(',').to(Token::Comma),
+		just(';').to(Token::Semicolon),
+		just('#').to(Token::Hash),
+	))
+	.boxed();
+
+	let token = choice((date, number, string, word, punctuation));
+
+	token
+		.map_with_span(|tok, span| (tok, span))
+		.padded()
+		.repeated()
+		.then_ignore(end())
+}
+
+fn kw(keyword: &'static str) -> impl Parser<Token, Token, Error = Simple<Token>> + Clone {
+	just(Token::Keyword(keyword))
+}
+
+fn ident() -> impl Parser<Token, String, Error = Simple<Token>> + Clone {
+	(select! {
+		Token::Ident(s) => s,
+	})
+	.labelled("identifier")
+}
+
+pub fn expr_parser() -> impl Parser<Token, Expr, Error = Simple<Token>> + Clone {
+	// What may follow the left hand side of a comparison
+	#[derive(Clone)]
+	enum Suffix {
+		Binary(BinaryOp, Expr),
+		IsNull(bool),
+		Between(Expr, Expr),
+	}
+
+	recursive(|expr| {
+		let literal = (select! {
+			Token::Number(n) => Literal::Number(n),
+			Token::String(s) => Literal::String(s),
+			Token::Date(d) => Literal::Date(d),
+			Token::Keyword("TRUE") => Literal::Bool(true),
+			Token::Keyword("FALSE") => Literal::Bool(false),
+			Token::Keyword("NULL") => Literal::Null,
+		})
+		.map(Expr::Literal);
+
+		let args = just(Token::Star)
+			.to(vec![Expr::Wildcard])
+			.or(expr.clone().separated_by(just(Token::Comma)))
+			.delimited_by(just(Token::LeftParen), just(Token::RightParen));
+
+		let call = ident()
+			.then(args)
+			.map(|(name, args)| Expr::Function(name, args));
+
+		let parens = expr
+			.clone()
+			.separated_by(just(Token::Comma))
+			.at_least(1)
+			.delimited_by(just(Token::LeftParen), just(Token::RightParen))
+			.map(|mut exprs| match exprs.len() {
+				1 => exprs.remove(0),
+				_ => Expr::Tuple(exprs),
+			});
+
+		let atom = choice((literal, call, ident().map(Expr::Column), parens))
+			.labelled("expression")
+			.boxed();
+
+		let unary = just(Token::Minus)
+			.repeated()
+			.then(atom)
+			.foldr(|_, expr| match expr {
+				Expr::Literal(Literal::Number(n)) => Expr::Literal(Literal::Number(-n)),
+				expr => Expr::Unary(UnaryOp::Neg, Box::new(expr)),
+			})
+			.boxed();
+
+		let product = unary
+			.clone()
+			.then(
+				just(Token::Star)
+					.to(BinaryOp::Mul)
+					.or(just(Token::Slash).to(BinaryOp::Div))
+					.then(unary)
+					.repeated(),
+			)
+			.foldl(|lhs, (op, rhs)| Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+			.boxed();
+
+		let sum = product
+			.clone()
+			.then(
+				just(Token::Plus)
+					.to(BinaryOp::Add)
+					.or(just(Token::Minus).to(BinaryOp::Sub))
+					.then(product)
+					.repeated(),
+			)
+			.foldl(|lhs, (op, rhs)| Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+			.boxed();
+
+		let compare_op = select! {
+			Token::Eq => BinaryOp::Eq,
+			Token::NotEq => BinaryOp::NotEq,
+			Token::Lt => BinaryOp::Lt,
+			Token::LtEq => BinaryOp::LtEq,
+			Token::Gt => BinaryOp::Gt,
+			Token::GtEq => BinaryOp::GtEq,
+			Token::Tilde => BinaryOp::Match,
+			Token::NotTilde => BinaryOp::NotMatch,
+		};
+
+		let suffix = choice((
+			compare_op
+				.then(sum.clone())
+				.map(|(op, rhs)| Suffix::Binary(op, rhs)),
+			kw("NOT")
+				.or_not()
+				.then_ignore(kw("IN"))
+				.then(sum.clone())
+				.map(|(not, rhs)| match not {
+					Some(_) => Suffix::Binary(BinaryOp::NotIn, rhs),
+					None => Suffix::Binary(BinaryOp::In, rhs),
+				}),
+			kw("IS")
+				.ignore_then(kw("NOT").or_not())
+				.then_ignore(kw("NULL"))
+				.map(|not| Suffix::IsNull(not.is_some())),
+			kw("BETWEEN")
+				.ignore_then(sum.clone())
+				.then_ignore(kw("AND"))
+				.then(sum.clone())
+				.map(|(low, high)| Suffix::Between(low, high)),
+		));
+
+		let comparison = sum
+			.then(suffix.or_not())
+			.map(|(lhs, suffix)| match suffix {
+				None => lhs,
+				Some(Suffix::Binary(op, rhs)) => Expr::Binary(op, Box::new(lhs), Box::new(rhs)),
+				Some(Suffix::IsNull(not)) => Expr::IsNull(Box::new(lhs), not),
+				Some(Suffix::Between(low, high)) => {
+					Expr::Between(Box::new(lhs), Box::new(low), Box::new(high))
+				}
+			})
+			.boxed();
+
+		let not = kw("NOT")
+			.repeated()
+			.then(comparison)
+			.foldr(|_, expr| Expr::Unary(UnaryOp::Not, Box::new(expr)))
+			.boxed();
+
+		let and = not
+			.clone()
+			.then(kw("AND").to(BinaryOp::And).then(not).repeated())
+			.foldl(|lhs, (op, rhs)| Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+			.boxed();
+
+		and.clone()
+			.then(kw("OR").to(BinaryOp::Or).then(and).repeated())
+			.foldl(|lhs, (op, rhs)| Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+			.boxed()
+	})
+}
+
+pub fn query_parser() -> impl Parser<Token, Query, Error = Simple<Token>> {
+	let expr = expr_parser();
+
+	let string = (select! {
+		Token::String(s) => s,
+	})
+	.labelled("string");
+
+	let date = (select! {
+		Token::Date(date) => date,
+	})
+	.labelled("date");
+
+	let integer = (select! {
+		Token::Number(n) => n,
+	})
+	.try_map(|n, span| {
+		n.to_u64().filter(|_| n.fract().is_zero()).ok_or_else(|| {
+			Simple::custom(span, format!("Expected a positive integer, found {}", n))
+		})
+	})
+	.labelled("integer");
+
+	let table = just(Token::Hash)
+		.ignore_then(ident())
+		.or(select! {
+			Token::Ident(s) if TABLES.contains(&s.to_lowercase().as_str()) => s.to_lowercase(),
+		})
+		.labelled("table");
+
+	let from = kw("FROM")
+		.ignore_then(table.or_not())
+		.then(expr.clone().or_not())
+		.then(kw("OPEN").ignore_then(kw("ON")).ignore_then(date).or_not())
+		.then(
+			kw("CLOSE")
+				.ignore_then(kw("ON").ignore_then(date).or_not())
+				.or_not(),
+		)
+		.then(kw("CLEAR").or_not())
+		.map(|((((table, expr), open), close), clear)| FromClause {
+			table,
+			expr,
+			open,
+			close,
+			clear: clear.is_some(),
+		})
+		.boxed();
+
+	let where_clause = kw("WHERE").ignore_then(expr.clone()).boxed();
+
+	let target = expr
+		.clone()
+		.then(kw("AS").ignore_then(ident()).or_not())
+		.map(|(expr, name)| Target { expr, name });
+
+	let targets = just(Token::Star).to(Targets::All).or(target
+		.separated_by(just(Token::Comma))
+		.at_least(1)
+		.map(Targets::List));
+
+	let group_by = kw("GROUP")
+		.ignore_then(kw("BY"))
+		.ignore_then(expr.clone().separated_by(just(Token::Comma)).at_least(1))
+		.then(kw("HAVING").ignore_then(expr.clone()).or_not())
+		.map(|(columns, having)| GroupBy { columns, having });
+
+	let order_by = kw("ORDER").ignore_then(kw("BY")).ignore_then(
+		expr.clone()
+			.then(kw("ASC").to(false).or(kw("DESC").to(true)).or_not())
+			.map(|(expr, descending)| OrderBy {
+				expr,
+				descending: descending.unwrap_or(false),
+			})
+			.separated_by(just(Token::Comma))
+			.at_least(1),
+	);
+
+	let limit = kw("LIMIT").ignore_then(integer);
+
+	let select = kw("SELECT")
+		.ignore_then(kw("DISTINCT").or_not())
+		.then(targets)
+		.then(from.clone().or_not())
+		.then(where_clause.clone().or_not())
+		.then(group_by.or_not())
+		.then(order_by.or_not())
+		.then(limit.or_not())
+		.map(
+			|((((((distinct, targets), from), where_clause), group_by), order_by), limit)| {
+				Query::Select(Select {
+					distinct: distinct.is_some(),
+					targets,
+					from,
+					where_clause,
+					group_by,
+					order_by: order_by.unwrap_or_default(),
+					limit,
+				})
+			},
+		);
+
+	let summary_func = kw("AT").ignore_then(ident());
+
+	let balances = kw("BALANCES")
+		.ignore_then(summary_func.clone().or_not())
+		.then(from.clone().or_not())
+		.then(where_clause.or_not())
+		.map(|((summary_func, from), where_clause)| Query::Balances {
+			summary_func,
+			from,
+			where_clause,
+		});
+
+	let journal = kw("JOURNAL")
+		.ignore_then(string.or_not())
+		.then(summary_func.or_not())
+		.then(from.clone().or_not())
+		.map(|((account, summary_func), from)| Query::Journal {
+			account,
+			summary_func,
+			from,
+		});
+
+	let print = kw("PRINT")
+		.ignore_then(from.or_not())
+		.map(|from| Query::Print { from });
+
+	choice((select, balances, journal, print))
+		.labelled("statement")
+		.then_ignore(just(Token::Semicolon).or_not())
+		.then_ignore(end())
+}
+
+/// Parses a single BQL statement. Errors can be reported with
+/// [`crate::parser::print_errors`].
+pub fn parse_query(src: &str) -> Result<Query, Vec<Simple<String>>> {
+	let (tokens, errors) = lexer().parse_recovery(src);
+	if !errors.is_empty() {
+		return Err(errors
+			.into_iter()
+			.map(|e| e.map(|c| c.to_string()))
+			.collect());
+	}
+
+	// Spans are in characters not bytes
+	let end = src.chars().count();
+	query_parser()
+		.parse(Stream::from_iter(
+			end..end + 1,
+			tokens.unwrap_or_default().into_iter(),
+		))
+		.map_err(|errors| {
+			errors
+				.into_iter()
+				.map(|e| e.map(|tok| tok.to_string()))
+				.collect()
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn expr(src: &str) -> String {
+		match parse_query(&format!("SELECT {}", src)) {
+			Ok(Query::Select(Select {
+				targets: Targets::List(targets),
+				..
+			})) => targets[0].expr.to_string(),
+			result => panic!("Unexpected result {:?}", result),
+		}
+	}
+
+	#[test]
+	fn test_query_lexer() {
+		let tokens: Vec<Token> = lexer()
+			.parse("select Sum(position) FROM #postings WHERE date >= 2025-01-31 AND x != 'It\\'s' <> 1.5")
+			.unwrap()
+			.into_iter()
+			.map(|(tok, _)| tok)
+			.collect();
+		assert_eq!(
+			tokens,
+			vec![
+				Token::Keyword("SELECT"),
+				Token::Ident("Sum".to_string()),
+				Token::LeftParen,
+				Token::Ident("position".to_string()),
+				Token::RightParen,
+				Token::Keyword("FROM"),
+				Token::Hash,
+				Token::Ident("postings".to_string()),
+				Token::Keyword("WHERE"),
+				Token::Ident("date".to_string()),
+				Token::GtEq,
+				Token::Date(NaiveDate::from_ymd_opt(2025, 1, 31).unwrap()),
+				Token::Keyword("AND"),
+				Token::Ident("x".to_string()),
+				Token::NotEq,
+				Token::String("It's".to_string()),
+				Token::NotEq,
+				Token::Number(Decimal::new(15, 1)),
+			]
+		);
+	}
+
+	#[test]
+	fn test_query_expressions() {
+		assert_eq!(expr("1 + 2 * -3"), "(1 + (2 * -3))");
+		assert_eq!(expr("(1 + 2) * 3"), "((1 + 2) * 3)");
+		assert_eq!(expr("a OR b AND NOT c = 1"), "(a OR (b AND (NOT (c = 1))))");
+		assert_eq!(
+			expr("account ~ 'Expenses' AND 'trip' NOT IN tags"),
+			"((account ~ 'Expenses') AND ('trip' NOT IN tags))"
+		);
+		assert_eq!(
+			expr("year(date) IN (2024, 2025)"),
+			"(year(date) IN (2024, 2025))"
+		);
+		assert_eq!(
+			expr("date BETWEEN 2025-01-01 AND 2025-02-01 AND cost IS NOT NULL"),
+			"((date BETWEEN 2025-01-01 AND 2025-02-01) AND (cost IS NOT NULL))"
+		);
+		assert_eq!(expr("count(*)"), "count(*)");
+		assert_eq!(
+			expr("account ~ '^Assets:\\d+\\\\'"),
+			"(account ~ '^Assets:\\\\d+\\\\')"
+		);
+		let printed = expr("'\\d \\\\ \\' \\q'");
+		assert_eq!(expr(&printed), printed);
+		assert_eq!(expr("-(number)"), "(-number)");
+	}
+
+	#[test]
+	fn test_query_select() {
+		let query = parse_query(
+			"SELECT DISTINCT account, sum(position) AS total
+			FROM #postings year = 2025 OPEN ON 2025-01-01 CLOSE CLEAR
+			WHERE account ~ '^Expenses'
+			GROUP BY 1 HAVING count(*) > 2
+			ORDER BY total DESC, account
+			LIMIT 10;",
+		)
+		.unwrap();
+
+		let column = |name: &str| Expr::Column(name.to_string());
+		let number = |n| Expr::Literal(Literal::Number(Decimal::from(n)));
+		assert_eq!(
+			query,
+			Query::Select(Select {
+				distinct: true,
+				targets: Targets::List(vec![
+					Target {
+						expr: column("account"),
+						name: None,
+					},
+					Target {
+						expr: Expr::Function("sum".to_string(), vec![column("position")]),
+						name: Some("total".to_string()),
+					},
+				]),
+				from: Some(FromClause {
+					table: Some("postings".to_string()),
+					expr: Some(Expr::Binary(
+						BinaryOp::Eq,
+						Box::new(column("year")),
+						Box::new(number(2025)),
+					)),
+					open: NaiveDate::from_ymd_opt(2025, 1, 1),
+					close: Some(None),
+					clear: true,
+				}),
+				where_clause: Some(Expr::Binary(
+					BinaryOp::Match,
+					Box::new(column("account")),
+					Box::new(Expr::Literal(Literal::String("^Expenses".to_string()))),
+				)),
+				group_by: Some(GroupBy {
+					columns: vec![number(1)],
+					having: Some(Expr::Binary(
+						BinaryOp::Gt,
+						Box::new(Expr::Function("count".to_string(), vec![Expr::Wildcard])),
+						Box::new(number(2)),
+					)),
+				}),
+				order_by: vec![
+					OrderBy {
+						expr: column("total"),
+						descending: true,
+					},
+					OrderBy {
+						expr: column("account"),
+						descending: false,
+					},
+				],
+				limit: Some(10),
+			})
+		);
+
+		let query = parse_query("select * from entries").unwrap();
+		let Query::Select(select) = query else {
+			panic!("Expected a select, found {:?}", query);
+		};
+		assert_eq!(select.targets, Targets::All);
+		assert_eq!(select.from.unwrap().table.as_deref(), Some("entries"));
+	}
+
+	#[test]
+	fn test_query_shorthands() {
+		assert_eq!(
+			parse_query("BALANCES AT cost FROM CLOSE ON 2025-01-01 WHERE account ~ 'Assets'")
+				.unwrap(),
+			Query::Balances {
+				summary_func: Some("cost".to_string()),
+				from: Some(FromClause {
+					close: Some(NaiveDate::from_ymd_opt(2025, 1, 1)),
+					..FromClause::default()
+				}),
+				where_clause: Some(Expr::Binary(
+					BinaryOp::Match,
+					Box::new(Expr::Column("account".to_string())),
+					Box::new(Expr::Literal(Literal::String("Assets".to_string()))),
+				)),
+			}
+		);
+		assert_eq!(
+			parse_query("JOURNAL 'Assets:Cash'").unwrap(),
+			Query::Journal {
+				account: Some("Assets:Cash".to_string()),
+				summary_func: None,
+				from: None,
+			}
+		);
+		assert_eq!(parse_query("PRINT").unwrap(), Query::Print { from: None });
+	}
+
+	#[test]
+	fn test_query_errors() {
+		let errors = parse_query("SELECT account WHERE").unwrap_err();
+		assert_eq!(errors.len(), 1);
+		assert_eq!(errors[0].span(), 20..21);
+		assert_eq!(errors[0].found(), None);
+
+		let errors = parse_query("SELECT account FROM LIMIT 1.5").unwrap_err();
+		assert_eq!(errors.len(), 1);
+		assert_eq!(
+			errors[0].reason(),
+			&chumsky::error::SimpleReason::Custom(
+				"Expected a positive integer, found 1.5".to_string()
+			)
+		);
+
+		let errors = parse_query("SELECT 'unclosed").unwrap_err();
+		assert_eq!(errors.len(), 1);
+	}
+}
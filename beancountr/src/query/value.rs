@@ -0,0 +1,237 @@
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use crate::core::{
+	directive::Metadata,
+	display_context::DisplayContext,
+	inventory::{Inventory, Position},
+	types::Amount,
+};
+
+/// A value computed by a query.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Value {
+	Null,
+	Bool(bool),
+	Number(Decimal),
+	String(String),
+	Date(NaiveDate),
+	Amount(Amount),
+	Position(Position),
+	Inventory(Inventory),
+	/// Tags, links or accounts.
+	Set(BTreeSet<String>),
+}
+
+impl Value {
+	pub fn type_name(&self) -> &'static str {
+		match self {
+			Value::Null => "null",
+			Value::Bool(_) => "bool",
+			Value::Number(_) => "number",
+			Value::String(_) => "string",
+			Value::Date(_) => "date",
+			Value::Amount(_) => "amount",
+			Value::Position(_) => "position",
+			Value::Inventory(_) => "inventory",
+			Value::Set(_) => "set",
+		}
+	}
+
+	/// Whether the value counts as true in a condition. Null and empty values
+	/// are false.
+	pub fn is_truthy(&self) -> bool {
+		match self {
+			Value::Null => false,
+			Value::Bool(b) => *b,
+			Value::Number(n) => !n.is_zero(),
+			Value::String(s) => !s.is_empty(),
+			Value::Inventory(inventory) => !inventory.is_empty(),
+			Value::Set(set) => !set.is_empty(),
+			Value::Date(_) | Value::Amount(_) | Value::Position(_) => true,
+		}
+	}
+
+	/// Renders the value like its `Display`, with the numbers of amounts in
+	/// the style inferred for their commodity.
+	pub fn format(&self, context: &DisplayContext) -> String {
+		match self {
+			Value::Amount(amount) => context.format_amount(amount),
+			Value::Position(position) => context.format_position(position),
+			Value::Inventory(inventory) => context.format_inventory(inventory),
+			value => value.to_string(),
+		}
+	}
+
+	fn rank(&self) -> u8 {
+		match self {
+			Value::Null => 0,
+			Value::Bool(_) => 1,
+			Value::Number(_) => 2,
+			Value::String(_) => 3,
+			Value::Date(_) => 4,
+			Value::Amount(_) => 5,
+			Value::Position(_) => 6,
+			Value::Inventory(_) => 7,
+			Value::Set(_) => 8,
+		}
+	}
+
+	/// Compares values of the same type, or `None` if they can't be compared.
+	/// Amounts are only comparable in the same commodity.
+	pub fn partial_compare(&self, other: &Value) -> Option<Ordering> {
+		match (self, other) {
+			(Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+			(Value::Number(a), Value::Number(b)) => Some(a.cmp(b)),
+			(Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+			(Value::Date(a), Value::Date(b)) => Some(a.cmp(b)),
+			(Value::Amount(a), Value::Amount(b)) => a.partial_cmp(b),
+			_ => None,
+		}
+	}
+
+	/// A total order used for sorting: nulls first, then by type, with values
+	/// that aren't directly comparable sorted by commodity or how they render.
+	pub fn sort_cmp(&self, other: &Value) -> Ordering {
+		if let Some(ordering) = self.partial_compare(other) {
+			return ordering;
+		}
+		match (self, other) {
+			(Value::Amount(a), Value::Amount(b)) => a
+				.commodity()
+				.cmp(b.commodity())
+				.then(a.number().cmp(&b.number())),
+			(Value::Position(a), Value::Position(b)) => Value::Amount(a.units().clone())
+				.sort_cmp(&Value::Amount(b.units().clone()))
+				.then_with(|| a.to_string().cmp(&b.to_string())),
+			(Value::Set(a), Value::Set(b)) => a.cmp(b),
+			_ if self.rank() == other.rank() => self.to_string().cmp(&other.to_string()),
+			_ => self.rank().cmp(&other.rank()),
+		}
+	}
+}
+
+impl From<&Metadata> for Value {
+	fn from(value: &Metadata) -> Self {
+		match value {
+			Metadata::String(s) => Value::String(s.clone()),
+			Metadata::Account(account) => Value::String(account.to_string()),
+			Metadata::Date(date) => Value::Date(*date),
+			Metadata::Commodity(commodity) => Value::String(commodity.to_string()),
+			Metadata::Tags(tag) => Value::String(tag.clone()),
+			Metadata::Link(link) => Value::String(link.clone()),
+			Metadata::Bool(b) => Value::Bool(*b),
+			Metadata::None => Value::Null,
+			Metadata::Number(number) => Value::Number(*number),
+			Metadata::Amount(amount) => Value::Amount(amount.clone()),
+		}
+	}
+}
+
+/// Renders the value for display, nulls are empty.
+impl Display for Value {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Value::Null => Ok(()),
+			Value::Bool(b) => f.write_str(if *b { "TRUE" } else { "FALSE" }),
+			Value::Number(n) => write!(f, "{}", n),
+			Value::String(s) => f.write_str(s),
+			Value::Date(d) => write!(f, "{}", d),
+			Value::Amount(amount) => write!(f, "{}", amount),
+			Value::Position(position) => write!(f, "{}", position),
+			Value::Inventory(inventory) => {
+				let positions: Vec<String> = inventory.iter().map(|p| p.to_string()).collect();
+				f.write_str(&positions.join(", "))
+			}
+			Value::Set(set) => {
+				let items: Vec<&str> = set.iter().map(|s| s.as_str()).collect();
+				f.write_str(&items.join(","))
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::types::amount;
+
+	fn number(s: &str) -> Value {
+		Value::Number(s.parse().unwrap())
+	}
+
+	#[test]
+	fn test_truthy() {
+		assert!(!Value::Null.is_truthy());
+		assert!(!number("0").is_truthy());
+		assert!(number("-1").is_truthy());
+		assert!(!Value::String(String::new()).is_truthy());
+		assert!(!Value::Set(BTreeSet::new()).is_truthy());
+		assert!(!Value::Inventory(Inventory::new()).is_truthy());
+		assert!(Value::Amount(amount("0", "USD")).is_truthy());
+	}
+
+	#[test]
+	fn test_sort_cmp() {
+		let mut values = [
+			Value::Amount(amount("5", "USD")),
+			Value::String("b".to_string()),
+			number("2"),
+			Value::Amount(amount("10", "EUR")),
+			Value::Null,
+			Value::Amount(amount("1", "USD")),
+			number("-3"),
+			Value::String("a".to_string()),
+		];
+		values.sort_by(|a, b| a.sort_cmp(b));
+		let rendered: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+		assert_eq!(
+			rendered,
+			vec!["", "-3", "2", "a", "b", "10 EUR", "1 USD", "5 USD"]
+		);
+
+		assert_eq!(
+			Value::Amount(amount("1", "USD")).partial_compare(&Value::Amount(amount("1", "EUR"))),
+			None
+		);
+		assert_eq!(
+			number("1").partial_compare(&Value::String("1".to_string())),
+			None
+		);
+	}
+
+	#[test]
+	fn test_display_and_format() {
+		let inventory = Inventory::sum([
+			Position::new(amount("1.5", "USD"), None),
+			Position::new(amount("2", "EUR"), None),
+		])
+		.unwrap();
+		let set: BTreeSet<String> = ["trip", "food"].map(String::from).into();
+
+		assert_eq!(Value::Bool(true).to_string(), "TRUE");
+		assert_eq!(Value::Set(set).to_string(), "food,trip");
+		assert_eq!(
+			Value::from(&Metadata::Account("Assets:Cash".parse().unwrap())),
+			Value::String("Assets:Cash".to_string())
+		);
+		assert_eq!(Value::from(&Metadata::None), Value::Null);
+
+		let mut context = DisplayContext::new();
+		context.update(&amount("1.00", "USD"));
+		context.update(&amount("1.000", "EUR"));
+		assert_eq!(
+			Value::Inventory(inventory.clone()).to_string(),
+			"1.5 USD, 2 EUR"
+		);
+		assert_eq!(
+			Value::Inventory(inventory).format(&context),
+			"1.50 USD, 2.000 EUR"
+		);
+		assert_eq!(number("1.5").format(&context), "1.5");
+	}
+}
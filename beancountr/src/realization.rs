@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+
+use crate::core::{
+	directive::{Directive, DirectiveKind},
+	inventory::Inventory,
+};
+
+/// An account of the tree of accounts, with the balance of the postings made
+/// to it directly and its sub-accounts by name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RealAccount {
+	/// The full name of the account, empty for the root of the tree.
+	pub account: String,
+	pub balance: Inventory,
+	pub children: BTreeMap<String, RealAccount>,
+}
+
+impl RealAccount {
+	pub fn new(account: impl Into<String>) -> Self {
+		Self {
+			account: account.into(),
+			..Default::default()
+		}
+	}
+
+	/// Returns the last component of the name of the account.
+	pub fn name(&self) -> &str {
+		self.account.rsplit(':').next().unwrap_or_default()
+	}
+
+	/// Returns the number of components of the name, 0 for the root.
+	pub fn depth(&self) -> usize {
+		match self.account.is_empty() {
+			true => 0,
+			false => self.account.split(':').count(),
+		}
+	}
+
+	pub fn get(&self, account: &str) -> Option<&RealAccount> {
+		let relative = self.relative(account)?;
+		relative
+			.split(':')
+			.filter(|name| !name.is_empty())
+			.try_fold(self, |real, name| real.children.get(name))
+	}
+
+	/// Returns a sub-account, creating it and its parents as needed.
+	pub fn get_or_create(&mut self, account: &str) -> &mut RealAccount {
+		let relative = self.relative(account).unwrap_or(account).to_string();
+		let mut real = self;
+		for name in relative.split(':').filter(|name| !name.is_empty()) {
+			let parent = real.account.clone();
+			real = real.children.entry(name.to_string()).or_insert_with(|| {
+				RealAccount::new(match parent.is_empty() {
+					true => name.to_string(),
+					false => format!("{}:{}", parent, name),
+				})
+			});
+		}
+		real
+	}
+
+	/// Removes a sub-account and its own sub-accounts from the tree.
+	pub fn remove(&mut self, account: &str) -> Option<RealAccount> {
+		let (parent, name) = match account.rsplit_once(':') {
+			Some((parent, name)) => (self.get_mut(parent)?, name),
+			None => (self, account),
+		};
+		parent.children.remove(name)
+	}
+
+	fn get_mut(&mut self, account: &str) -> Option<&mut RealAccount> {
+		let relative = self.relative(account)?.to_string();
+		relative
+			.split(':')
+			.filter(|name| !name.is_empty())
+			.try_fold(self, |real, name| real.children.get_mut(name))
+	}
+
+	/// Returns the name of an account relative to this one, if it's one of
+	/// its sub-accounts.
+	fn relative<'a>(&self, account: &'a str) -> Option<&'a str> {
+		if self.account.is_empty() {
+			return Some(account);
+		}
+		account
+			.strip_prefix(self.account.as_str())
+			.filter(|rest| rest.is_empty() || rest.starts_with(':'))
+	}
+
+	/// Returns the balance of the account along with all its sub-accounts.
+	pub fn total(&self) -> Inventory {
+		let mut total = self.balance.clone();
+		for child in self.children.values() {
+			// Totals too large to represent leave out the sub-account
+			let _ = total.add_inventory(&child.total());
+		}
+		total
+	}
+
+	/// Iterates over the account and its sub-accounts, parents first and
+	/// children in order of their names.
+	pub fn iter(&self) -> Iter<'_> {
+		Iter { stack: vec![self] }
+	}
+}
+
+/// An iterator over the accounts of a tree, see [`RealAccount::iter`].
+pub struct Iter<'a> {
+	stack: Vec<&'a RealAccount>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+	type Item = &'a RealAccount;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let real = self.stack.pop()?;
+		self.stack.extend(real.children.values().rev());
+		Some(real)
+	}
+}
+
+/// Builds the tree of accounts from the postings of the transactions, along
+/// with the accounts that are opened.
+pub fn realize<'a>(directives: impl IntoIterator<Item = &'a Directive>) -> RealAccount {
+	let mut root = RealAccount::default();
+	for directive in directives {
+		match &directive.kind {
+			DirectiveKind::Open(account, ..) => {
+				root.get_or_create(account.as_str());
+			}
+			DirectiveKind::Transaction { postings, .. } => {
+				for posting in postings {
+					let real = root.get_or_create(posting.account().as_str());
+					// Balances that overflow are reported when booking
+					if let Some(position) = posting.position() {
+						let _ = real.balance.add_position(&position);
+					}
+				}
+			}
+			_ => {}
+		}
+	}
+	root
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_realize() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			2025-01-01 open Assets:Bank:Savings
+			2025-01-01 open Assets:Cash
+			2025-01-02 * "Withdraw"
+				Assets:Cash 100.00 USD
+				Assets:Bank -100.00 USD
+			2025-01-03 * "Save"
+				Assets:Bank:Savings 50.00 USD
+				Assets:Bank
+			"#,
+		);
+		let mut root = realize(&ledger.directives);
+
+		let accounts: Vec<(&str, usize)> = root
+			.iter()
+			.map(|real| (real.account.as_str(), real.depth()))
+			.collect();
+		assert_eq!(
+			accounts,
+			vec![
+				("", 0),
+				("Assets", 1),
+				("Assets:Bank", 2),
+				("Assets:Bank:Savings", 3),
+				("Assets:Cash", 2)
+			]
+		);
+
+		let bank = root.get("Assets:Bank").unwrap();
+		assert_eq!(bank.name(), "Bank");
+		assert_eq!(bank.balance.to_string(), "(-150.00 USD)");
+		assert_eq!(bank.total().to_string(), "(-100.00 USD)");
+		assert_eq!(
+			bank.get("Assets:Bank:Savings").unwrap().balance.to_string(),
+			"(50.00 USD)"
+		);
+		assert!(root.get("Assets:Banking").is_none());
+		assert!(root.total().is_empty());
+
+		assert!(root.remove("Assets:Bank").is_some());
+		assert_eq!(root.total().to_string(), "(100.00 USD)");
+	}
+}
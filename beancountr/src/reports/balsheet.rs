@@ -0,0 +1,251 @@
+use chrono::NaiveDate;
+
+use super::{book_unrealized, period, Report, ReportSettings, Section, Valuer};
+use crate::core::prices::PriceMap;
+use crate::loader::Ledger;
+use crate::realization::realize;
+use crate::summarize::clear;
+
+/// Builds the balance sheet on a date, or with every transaction if none is
+/// given. Income and expenses are moved into the current earnings account of
+/// the equity, and conversions between commodities into the current
+/// conversions, so the sections add up to zero. At market value, the
+/// difference with the cost of the assets is booked to the unrealized gains of
+/// the equity.
+pub fn balance_sheet(
+	ledger: &Ledger,
+	date: Option<NaiveDate>,
+	settings: &ReportSettings,
+) -> Report {
+	let options = &ledger.options;
+	let directives = clear(period(ledger, None, date), None, options);
+	let mut root = realize(&directives);
+
+	let prices = PriceMap::from_directives(&ledger.directives);
+	let valuer = Valuer {
+		prices: &prices,
+		settings,
+		date,
+	};
+	let names = [
+		&options.name_assets,
+		&options.name_liabilities,
+		&options.name_equity,
+	];
+	book_unrealized(&mut root, &names, &valuer, options);
+	let sections: Vec<Section> = names
+		.into_iter()
+		.map(|name| Section::from_root(&root, name, &valuer))
+		.collect();
+
+	let mut net_worth = sections[0].total.clone();
+	let _ = net_worth.add_inventory(&sections[1].total);
+	Report {
+		title: "Balance Sheet".to_string(),
+		sections,
+		summary: Some(("Net Worth".to_string(), net_worth)),
+	}
+}
+
+/// Builds the income statement of the transactions between two dates, both
+/// included. The net income is the opposite of the sum of the income and
+/// expenses, so a profit is positive.
+pub fn income_statement(
+	ledger: &Ledger,
+	begin: Option<NaiveDate>,
+	end: Option<NaiveDate>,
+	settings: &ReportSettings,
+) -> Report {
+	let options = &ledger.options;
+	// Income and expenses before the period are in the previous earnings
+	let root = realize(&period(ledger, begin, end));
+
+	let prices = PriceMap::from_directives(&ledger.directives);
+	let valuer = Valuer {
+		prices: &prices,
+		settings,
+		date: end,
+	};
+	let sections: Vec<Section> = [&options.name_income, &options.name_expenses]
+		.into_iter()
+		.map(|name| Section::from_root(&root, name, &valuer))
+		.collect();
+
+	let mut net_income = sections[0].total.clone();
+	let _ = net_income.add_inventory(&sections[1].total);
+	Report {
+		title: "Income Statement".to_string(),
+		sections,
+		summary: Some(("Net Income".to_string(), -&net_income)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::core::inventory::Inventory;
+	use crate::loader::load_str;
+	use crate::reports::Valuation;
+
+	const LEDGER: &str = r#"
+		option "operating_currency" "USD"
+		2025-01-01 open Assets:Bank:Checking
+		2025-01-01 open Assets:Broker
+		2025-01-01 open Liabilities:Card
+		2025-01-01 open Income:Salary
+		2025-01-01 open Expenses:Food
+		2025-01-01 open Expenses:Rent
+		2025-01-05 * "Pay"
+			Assets:Bank:Checking 3000.00 USD
+			Income:Salary
+		2025-01-10 * "Rent"
+			Expenses:Rent 1000.00 USD
+			Assets:Bank:Checking
+		2025-01-15 * "Dinner"
+			Expenses:Food 30.00 USD
+			Liabilities:Card
+		2025-01-20 * "Buy"
+			Assets:Broker 10 HOOL {100.00 USD}
+			Assets:Bank:Checking
+		2025-02-01 * "Lunch"
+			Expenses:Food 20.00 USD
+			Liabilities:Card
+		2025-02-01 price HOOL 120.00 USD
+		"#;
+
+	fn rows(report: &Report) -> Vec<(String, String)> {
+		report
+			.sections
+			.iter()
+			.flat_map(|section| {
+				section
+					.rows
+					.iter()
+					.map(|row| (row.account.clone(), row.balance.to_string()))
+					.chain([(section.title.clone(), section.total.to_string())])
+			})
+			.chain(
+				report
+					.summary
+					.iter()
+					.map(|(title, total)| (title.clone(), total.to_string())),
+			)
+			.collect()
+	}
+
+	fn strs(rows: &[(&str, &str)]) -> Vec<(String, String)> {
+		rows.iter()
+			.map(|(a, b)| (a.to_string(), b.to_string()))
+			.collect()
+	}
+
+	#[test]
+	fn test_balance_sheet() {
+		let ledger = load_str(Rc::from("test"), LEDGER);
+		assert!(ledger.errors.is_empty(), "{:?}", ledger.errors);
+
+		let date = NaiveDate::from_ymd_opt(2025, 1, 31);
+		let report = balance_sheet(&ledger, date, &ReportSettings::default());
+		assert_eq!(
+			rows(&report),
+			strs(&[
+				("Assets", "()"),
+				("Assets:Bank", "()"),
+				("Assets:Bank:Checking", "(1000.00 USD)"),
+				("Assets:Broker", "(10 HOOL {100.00 USD, 2025-01-20})"),
+				("Assets", "(1000.00 USD, 10 HOOL {100.00 USD, 2025-01-20})"),
+				("Liabilities", "()"),
+				("Liabilities:Card", "(-30.00 USD)"),
+				("Liabilities", "(-30.00 USD)"),
+				("Equity", "()"),
+				("Equity:Earnings", "()"),
+				("Equity:Earnings:Current", "(-1970.00 USD)"),
+				("Equity", "(-1970.00 USD)"),
+				(
+					"Net Worth",
+					"(970.00 USD, 10 HOOL {100.00 USD, 2025-01-20})"
+				),
+			])
+		);
+
+		// Converting to a currency leaves the units held at cost alone
+		let settings = ReportSettings {
+			currency: ledger.options.operating_currency.first().cloned(),
+			depth: Some(1),
+			..Default::default()
+		};
+		let report = balance_sheet(&ledger, None, &settings);
+		assert_eq!(
+			rows(&report)[..2],
+			strs(&[
+				("Assets", "(1000.00 USD, 10 HOOL {100.00 USD, 2025-01-20})"),
+				("Assets", "(1000.00 USD, 10 HOOL {100.00 USD, 2025-01-20})"),
+			])
+		);
+
+		let settings = ReportSettings {
+			valuation: Valuation::Market,
+			..settings
+		};
+		let report = balance_sheet(&ledger, None, &settings);
+		assert_eq!(
+			rows(&report),
+			strs(&[
+				("Assets", "(2200.00 USD)"),
+				("Assets", "(2200.00 USD)"),
+				("Liabilities", "(-50.00 USD)"),
+				("Liabilities", "(-50.00 USD)"),
+				("Equity", "(-2150.00 USD)"),
+				("Equity", "(-2150.00 USD)"),
+				("Net Worth", "(2150.00 USD)"),
+			])
+		);
+
+		// The gain on the shares is in the equity, so the sections add up to zero
+		let settings = ReportSettings {
+			depth: None,
+			..settings
+		};
+		let report = balance_sheet(&ledger, None, &settings);
+		let equity = &report.sections[2];
+		assert_eq!(
+			equity
+				.rows
+				.last()
+				.map(|row| (row.account.as_str(), row.balance.to_string())),
+			Some(("Equity:Earnings:Unrealized", "(-200.00 USD)".to_string()))
+		);
+		let mut total = Inventory::new();
+		for section in &report.sections {
+			total.add_inventory(&section.total).unwrap();
+		}
+		assert!(total.is_empty(), "{}", total);
+	}
+
+	#[test]
+	fn test_income_statement() {
+		let ledger = load_str(Rc::from("test"), LEDGER);
+		let report = income_statement(
+			&ledger,
+			NaiveDate::from_ymd_opt(2025, 1, 10),
+			None,
+			&ReportSettings {
+				depth: Some(2),
+				..Default::default()
+			},
+		);
+		assert_eq!(
+			rows(&report),
+			strs(&[
+				("Income", "()"),
+				("Expenses", "()"),
+				("Expenses:Food", "(50.00 USD)"),
+				("Expenses:Rent", "(1000.00 USD)"),
+				("Expenses", "(1050.00 USD)"),
+				("Net Income", "(-1050.00 USD)"),
+			])
+		);
+	}
+}
@@ -0,0 +1,173 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use crate::core::{
+	budget::{Budget, BudgetPeriod},
+	directive::DirectiveKind,
+	types::{Account, Amount, Commodity},
+};
+use crate::loader::Ledger;
+
+/// What was budgeted for an account over a period, and how much it changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetRow {
+	pub begin: NaiveDate,
+	/// The last day of the period.
+	pub end: NaiveDate,
+	pub account: Account,
+	pub budget: Amount,
+	/// The change of the account and its sub-accounts.
+	pub actual: Amount,
+	pub remaining: Amount,
+}
+
+/// Compares the budgets with the changes of their accounts over each period
+/// between two dates, both included. These default to the date of the first
+/// budget and the last directive.
+///
+/// Each day gets its share of the budgets in force on it, so a period can be
+/// budgeted across changes of budgets, and with budgets for other periods:
+/// a month gets 31/365th of a yearly budget in January, but 28/365th in
+/// February.
+pub fn budget_report(
+	ledger: &Ledger,
+	budgets: &[Budget],
+	period: BudgetPeriod,
+	begin: Option<NaiveDate>,
+	end: Option<NaiveDate>,
+) -> Vec<BudgetRow> {
+	let mut budgeted: BTreeMap<(&Account, &Commodity), Vec<&Budget>> = BTreeMap::new();
+	for budget in budgets {
+		let key = (&budget.account, budget.amount.commodity());
+		budgeted.entry(key).or_default().push(budget);
+	}
+	for budgets in budgeted.values_mut() {
+		budgets.sort_by_key(|budget| budget.date);
+	}
+	let begin = begin.or_else(|| budgets.iter().map(|budget| budget.date).min());
+	let end = end.or_else(|| ledger.directives.last().map(|directive| directive.date));
+	let (Some(begin), Some(end)) = (begin, end) else {
+		return vec![];
+	};
+
+	let mut actuals: HashMap<(NaiveDate, &Account, &Commodity), Decimal> = HashMap::new();
+	for directive in &ledger.directives {
+		let DirectiveKind::Transaction { postings, .. } = &directive.kind else {
+			continue;
+		};
+		if directive.date < begin || directive.date > end {
+			continue;
+		}
+		let start = period.start(directive.date);
+		for posting in postings {
+			let Some(units) = posting.units() else {
+				continue;
+			};
+			for &(account, commodity) in budgeted.keys() {
+				if commodity == units.commodity() && account.includes(posting.account()) {
+					*actuals.entry((start, account, commodity)).or_default() += units.number();
+				}
+			}
+		}
+	}
+
+	let mut rows = vec![];
+	let mut start = period.start(begin);
+	while start <= end {
+		let next = period.next(start);
+		let first = start.max(begin);
+		let last = next.pred_opt().unwrap().min(end);
+		for (&(account, commodity), budgets) in &budgeted {
+			let mut budget = Decimal::ZERO;
+			for day in first.iter_days().take_while(|day| *day <= last) {
+				let in_force = budgets.partition_point(|budget| budget.date <= day);
+				if let Some(in_force) = in_force.checked_sub(1).map(|i| budgets[i]) {
+					budget += in_force.amount.number() / Decimal::from(in_force.period.days(day));
+				}
+			}
+			let scale = budgets
+				.iter()
+				.map(|budget| budget.amount.number().scale())
+				.max()
+				.unwrap_or_default();
+			let budget = budget.round_dp(scale);
+			let actual = actuals
+				.get(&(start, account, commodity))
+				.copied()
+				.unwrap_or(Decimal::new(0, scale));
+			if budget.is_zero() && actual.is_zero() {
+				continue;
+			}
+			rows.push(BudgetRow {
+				begin: first,
+				end: last,
+				account: account.clone(),
+				budget: Amount::new(budget, commodity.clone()),
+				actual: Amount::new(actual, commodity.clone()),
+				remaining: Amount::new(budget - actual, commodity.clone()),
+			});
+		}
+		start = next;
+	}
+	rows
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::core::budget::budgets;
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_budget_report() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			2025-01-01 open Assets:Cash
+			2025-01-01 open Expenses:Food:Groceries
+			2025-01-01 open Expenses:Rent
+			2025-01-01 custom "budget" Expenses:Food "monthly" 500.00 USD
+			2025-01-01 custom "budget" Expenses:Rent "yearly" 12000.00 USD
+			2025-02-15 custom "budget" Expenses:Food "weekly" 140.00 USD
+			2025-03-01 custom "budget" Expenses:Food "biweekly" 300.00 USD
+			2025-01-10 * "Groceries"
+				Expenses:Food:Groceries 420.00 USD
+				Assets:Cash
+			2025-02-01 * "Rent"
+				Expenses:Rent 1000.00 USD
+				Assets:Cash
+			2025-02-20 * "Groceries"
+				Expenses:Food:Groceries 610.00 USD
+				Assets:Cash
+			"#,
+		);
+		// The budget with an invalid period is left out
+		let (budgets, _) = budgets(&ledger.directives);
+		assert_eq!(budgets.len(), 3);
+
+		let begin = NaiveDate::from_ymd_opt(2025, 1, 1);
+		let end = NaiveDate::from_ymd_opt(2025, 2, 28);
+		let rows: Vec<String> = budget_report(&ledger, &budgets, BudgetPeriod::Monthly, begin, end)
+			.iter()
+			.map(|row| {
+				format!(
+					"{} {} {} {} {} {}",
+					row.begin, row.end, row.account, row.budget, row.actual, row.remaining
+				)
+			})
+			.collect();
+		assert_eq!(
+			rows,
+			vec![
+				"2025-01-01 2025-01-31 Expenses:Food 500.00 USD 420.00 USD 80.00 USD",
+				"2025-01-01 2025-01-31 Expenses:Rent 1019.18 USD 0.00 USD 1019.18 USD",
+				"2025-02-01 2025-02-28 Expenses:Food 530.00 USD 610.00 USD -80.00 USD",
+				"2025-02-01 2025-02-28 Expenses:Rent 920.55 USD 1000.00 USD -79.45 USD",
+			]
+		);
+	}
+}
@@ -0,0 +1,318 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use chrono::{Months, NaiveDate};
+
+use crate::core::{
+	directive::DirectiveKind,
+	inventory::{Inventory, Position},
+	position::Cost,
+	prices::PriceMap,
+	types::{Account, Amount},
+};
+use crate::loader::Ledger;
+
+/// How long a lot was held for, which decides how its gains are taxed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Term {
+	/// Held for a year or less.
+	Short,
+	/// Held for more than a year.
+	Long,
+}
+
+impl Term {
+	/// Classifies a lot acquired on a date and held until another.
+	pub fn new(acquired: NaiveDate, date: NaiveDate) -> Self {
+		match acquired.checked_add_months(Months::new(12)) {
+			Some(year) if date > year => Term::Long,
+			_ => Term::Short,
+		}
+	}
+}
+
+impl Display for Term {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Term::Short => "short",
+			Term::Long => "long",
+		})
+	}
+}
+
+/// A lot, or a part of one, that was sold or otherwise disposed of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disposal {
+	pub date: NaiveDate,
+	pub account: Account,
+	/// The units disposed of, as a positive amount.
+	pub units: Amount,
+	pub acquired: NaiveDate,
+	pub label: Option<String>,
+	pub cost_basis: Amount,
+	/// What the units were sold for in the commodity of their cost, from the
+	/// price of the posting or the price of the day. Unknown without either.
+	pub proceeds: Option<Amount>,
+	pub gain: Option<Amount>,
+	pub term: Term,
+}
+
+/// A lot still held, with its value at the last known price.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenLot {
+	pub account: Account,
+	pub units: Amount,
+	pub acquired: NaiveDate,
+	pub label: Option<String>,
+	pub cost_basis: Amount,
+	pub market_value: Option<Amount>,
+	pub unrealized_gain: Option<Amount>,
+	/// The term its gains would have if it was sold on the date of the report.
+	pub term: Term,
+}
+
+fn gain(proceeds: Option<&Amount>, cost_basis: &Amount) -> Option<Amount> {
+	proceeds.and_then(|proceeds| proceeds.checked_sub(cost_basis).ok())
+}
+
+fn cost_basis(units: &Amount, cost: &Cost) -> Amount {
+	Amount::new(units.number() * cost.number(), cost.commodity().clone())
+}
+
+/// Lists the lots disposed of over a period, with both ends included, in date
+/// order.
+pub fn realized_gains(
+	ledger: &Ledger,
+	begin: Option<NaiveDate>,
+	end: Option<NaiveDate>,
+) -> Vec<Disposal> {
+	let prices = PriceMap::from_directives(&ledger.directives);
+	let mut disposals = vec![];
+	for directive in &ledger.directives {
+		if begin.is_some_and(|begin| directive.date < begin)
+			|| end.is_some_and(|end| directive.date > end)
+		{
+			continue;
+		}
+		let DirectiveKind::Transaction { postings, .. } = &directive.kind else {
+			continue;
+		};
+		// Units added back at the same cost by the same transaction were moved
+		// between accounts. The date of the cost isn't compared, since the new
+		// lot is dated on the day of the transfer unless one is written
+		let mut moved: Vec<Position> = postings
+			.iter()
+			.filter_map(|posting| posting.position())
+			.filter(|position| position.cost().is_some() && !position.units().is_negative())
+			.collect();
+		for posting in postings {
+			let Some(position) = posting.position() else {
+				continue;
+			};
+			let (Some(cost), true) = (position.cost(), position.units().is_negative()) else {
+				continue;
+			};
+			let mut units = position.units().abs();
+			// Units sold at a price are a disposal even if the lot is bought back
+			if posting.price().is_none() {
+				let mut remaining = units.number();
+				for lot in moved.iter_mut().filter(|lot| {
+					lot.units().commodity() == units.commodity()
+						&& lot.cost().is_some_and(|lot_cost| {
+							lot_cost.number() == cost.number()
+								&& lot_cost.commodity() == cost.commodity()
+						})
+				}) {
+					let transferred = remaining.min(lot.units().number());
+					*lot = lot.with_number(lot.units().number() - transferred);
+					remaining -= transferred;
+				}
+				if remaining.is_zero() {
+					continue;
+				}
+				units = Amount::new(remaining, units.commodity().clone());
+			}
+			let cost_basis = cost_basis(&units, cost);
+			let proceeds = match posting.price() {
+				Some(price) => prices.convert(
+					&(price * units.number()),
+					cost.commodity(),
+					Some(directive.date),
+				),
+				None => prices.convert(&units, cost.commodity(), Some(directive.date)),
+			};
+			disposals.push(Disposal {
+				date: directive.date,
+				account: posting.account().clone(),
+				units,
+				acquired: cost.date(),
+				label: cost.label().map(str::to_string),
+				gain: gain(proceeds.as_ref(), &cost_basis),
+				cost_basis,
+				proceeds,
+				term: Term::new(cost.date(), directive.date),
+			});
+		}
+	}
+	disposals
+}
+
+/// Lists the lots held on a date, or after the last transaction if not given,
+/// by account. They are valued at the prices of that date, or the last known
+/// ones.
+pub fn open_lots(ledger: &Ledger, date: Option<NaiveDate>) -> Vec<OpenLot> {
+	let prices = PriceMap::from_directives(&ledger.directives);
+	let mut inventories: BTreeMap<&Account, Inventory> = BTreeMap::new();
+	let mut last = None;
+	for directive in &ledger.directives {
+		if date.is_some_and(|date| directive.date > date) {
+			break;
+		}
+		if let DirectiveKind::Transaction { postings, .. } = &directive.kind {
+			for posting in postings {
+				if let Some(position) = posting.position().filter(|p| p.cost().is_some()) {
+					let inventory = inventories.entry(posting.account()).or_default();
+					let _ = inventory.add_position(&position);
+				}
+			}
+			last = Some(directive.date);
+		}
+	}
+
+	let Some(held_until) = date.or(last) else {
+		return vec![];
+	};
+	let mut lots = vec![];
+	for (account, inventory) in inventories {
+		for position in &inventory {
+			let Some(cost) = position.cost() else {
+				continue;
+			};
+			let cost_basis = cost_basis(position.units(), cost);
+			let market_value = prices.convert(position.units(), cost.commodity(), date);
+			lots.push(OpenLot {
+				account: account.clone(),
+				units: position.units().clone(),
+				acquired: cost.date(),
+				label: cost.label().map(str::to_string),
+				unrealized_gain: gain(market_value.as_ref(), &cost_basis),
+				cost_basis,
+				market_value,
+				term: Term::new(cost.date(), held_until),
+			});
+		}
+	}
+	lots
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::loader::load_str;
+
+	const LEDGER: &str = r#"
+		2023-01-01 open Assets:Broker HOOL "FIFO"
+		2023-01-01 open Assets:Cash
+		2023-01-01 open Income:Gains
+		2023-03-01 * "Buy"
+			Assets:Broker 10 HOOL {100.00 USD}
+			Assets:Cash
+		2024-02-01 * "Buy"
+			Assets:Broker 10 HOOL {120.00 USD}
+			Assets:Cash
+		2024-06-01 * "Sell"
+			Assets:Broker -15 HOOL {} @ 130.00 USD
+			Assets:Cash 1950.00 USD
+			Income:Gains
+		2024-07-01 price HOOL 110.00 USD
+	"#;
+
+	#[test]
+	fn test_realized_gains() {
+		let ledger = load_str(Rc::from("test"), LEDGER);
+		let disposals: Vec<String> = realized_gains(&ledger, None, None)
+			.iter()
+			.map(|d| {
+				format!(
+					"{} {} {} {} {} {}",
+					d.units,
+					d.acquired,
+					d.cost_basis,
+					d.proceeds.as_ref().unwrap(),
+					d.gain.as_ref().unwrap(),
+					d.term
+				)
+			})
+			.collect();
+		assert_eq!(
+			disposals,
+			vec![
+				"10 HOOL 2023-03-01 1000.00 USD 1300.00 USD 300.00 USD long",
+				"5 HOOL 2024-02-01 600.00 USD 650.00 USD 50.00 USD short",
+			]
+		);
+
+		let before = NaiveDate::from_ymd_opt(2024, 5, 31);
+		assert!(realized_gains(&ledger, None, before).is_empty());
+	}
+
+	#[test]
+	fn test_transfer_is_not_a_disposal() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			2024-01-01 open Assets:Broker
+			2024-01-01 open Assets:Retirement
+			2024-01-01 open Assets:Cash
+			2024-01-02 * "Buy"
+				Assets:Broker 10 HOOL {100.00 USD}
+				Assets:Cash
+			2024-02-01 * "Move to the other broker, selling a few"
+				Assets:Broker -10 HOOL {100.00 USD}
+				Assets:Retirement 6 HOOL {100.00 USD}
+				Assets:Cash 480.00 USD
+				Income:Gains
+			2024-02-01 price HOOL 120.00 USD
+			"#,
+		);
+		let disposals = realized_gains(&ledger, None, None);
+		assert_eq!(disposals.len(), 1);
+		assert_eq!(disposals[0].account.as_str(), "Assets:Broker");
+		assert_eq!(disposals[0].units.to_string(), "4 HOOL");
+		assert_eq!(disposals[0].gain.as_ref().unwrap().to_string(), "80.00 USD");
+		assert_eq!(
+			open_lots(&ledger, None)[0].account.as_str(),
+			"Assets:Retirement"
+		);
+	}
+
+	#[test]
+	fn test_open_lots() {
+		let ledger = load_str(Rc::from("test"), LEDGER);
+		let lots: Vec<String> = open_lots(&ledger, NaiveDate::from_ymd_opt(2024, 12, 31))
+			.iter()
+			.map(|lot| {
+				format!(
+					"{} {} {} {} {} {}",
+					lot.account,
+					lot.units,
+					lot.cost_basis,
+					lot.market_value.as_ref().unwrap(),
+					lot.unrealized_gain.as_ref().unwrap(),
+					lot.term
+				)
+			})
+			.collect();
+		assert_eq!(
+			lots,
+			vec!["Assets:Broker 5 HOOL 600.00 USD 550.00 USD -50.00 USD short"]
+		);
+
+		let lots = open_lots(&ledger, NaiveDate::from_ymd_opt(2024, 3, 2));
+		assert_eq!(lots.len(), 2);
+		assert_eq!(lots[0].term, Term::Long);
+		assert_eq!(lots[0].market_value, None);
+	}
+}
@@ -0,0 +1,185 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use super::gains::{open_lots, OpenLot};
+use super::ReportSettings;
+use crate::core::{
+	directive::DirectiveKind,
+	inventory::Inventory,
+	prices::PriceMap,
+	types::{Amount, Commodity},
+};
+use crate::loader::Ledger;
+
+/// What the holdings are grouped by.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Grouping {
+	/// The commodity held.
+	#[default]
+	Commodity,
+	/// The account holding it.
+	Account,
+	/// A metadata key of the commodity held, like `asset-class`.
+	Metadata(String),
+}
+
+/// Positions held at cost, summed up by group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Holding {
+	pub group: String,
+	pub units: Inventory,
+	/// The cost the units were acquired at.
+	pub book_value: Amount,
+	/// The value of the units at the last known price, or their cost without
+	/// one.
+	pub market_value: Amount,
+	pub unrealized_gain: Amount,
+	/// The share of the market value of all holdings in the same commodity,
+	/// in percent.
+	pub allocation: Decimal,
+}
+
+/// The name under which holdings without the metadata key are grouped.
+const OTHER: &str = "Other";
+
+/// Sums up the positions held at cost on a date, or after the last
+/// transaction if not given, by group. Values are converted to the currency
+/// of the settings when a rate is known.
+pub fn holdings(
+	ledger: &Ledger,
+	date: Option<NaiveDate>,
+	grouping: &Grouping,
+	settings: &ReportSettings,
+) -> Vec<Holding> {
+	let prices = PriceMap::from_directives(&ledger.directives);
+	let commodity_meta: HashMap<&Commodity, _> = ledger
+		.directives
+		.iter()
+		.filter_map(|directive| match &directive.kind {
+			DirectiveKind::Commodity(commodity) => Some((commodity, &directive.meta)),
+			_ => None,
+		})
+		.collect();
+	let group = |lot: &OpenLot| match grouping {
+		Grouping::Commodity => lot.units.commodity().to_string(),
+		Grouping::Account => lot.account.to_string(),
+		Grouping::Metadata(key) => commodity_meta
+			.get(lot.units.commodity())
+			.and_then(|meta| meta.get(key))
+			.map(|value| value.as_str().map_or(value.to_string(), str::to_string))
+			.unwrap_or_else(|| OTHER.to_string()),
+	};
+	let convert = |amount: &Amount| {
+		settings
+			.currency
+			.as_ref()
+			.and_then(|currency| prices.convert(amount, currency, date))
+			.unwrap_or_else(|| amount.clone())
+	};
+
+	// Numbers are summed by commodity of the values, so they can't mismatch
+	let mut groups: BTreeMap<(String, Commodity), (Inventory, Decimal, Decimal)> = BTreeMap::new();
+	for lot in open_lots(ledger, date) {
+		let book_value = convert(&lot.cost_basis);
+		let market_value = convert(lot.market_value.as_ref().unwrap_or(&lot.cost_basis));
+		let (units, book, market) = groups
+			.entry((group(&lot), book_value.commodity().clone()))
+			.or_default();
+		let _ = units.add_amount(&lot.units);
+		*book += book_value.number();
+		*market += market_value.number();
+	}
+
+	let mut totals: HashMap<Commodity, Decimal> = HashMap::new();
+	for ((_, commodity), (_, _, market)) in &groups {
+		*totals.entry(commodity.clone()).or_default() += market;
+	}
+	groups
+		.into_iter()
+		.map(|((group, commodity), (units, book, market))| {
+			let total = totals[&commodity];
+			let allocation = match total.is_zero() {
+				true => Decimal::ZERO,
+				false => (market / total * Decimal::ONE_HUNDRED).round_dp(2),
+			};
+			Holding {
+				group,
+				units,
+				book_value: Amount::new(book, commodity.clone()),
+				market_value: Amount::new(market, commodity.clone()),
+				unrealized_gain: Amount::new(market - book, commodity),
+				allocation,
+			}
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_holdings() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			2025-01-01 commodity HOOL
+				asset-class: "stock"
+			2025-01-01 commodity BOND
+				asset-class: "bond"
+			2025-01-01 open Assets:Broker
+			2025-01-01 open Assets:Retirement
+			2025-01-01 open Assets:Cash
+			2025-01-10 * "Buy"
+				Assets:Broker 10 HOOL {100.00 USD}
+				Assets:Retirement 5 HOOL {110.00 USD}
+				Assets:Retirement 20 BOND {50.00 USD}
+				Assets:Cash
+			2025-02-01 price HOOL 120.00 USD
+			"#,
+		);
+		let render = |grouping| -> Vec<String> {
+			holdings(&ledger, None, &grouping, &ReportSettings::default())
+				.iter()
+				.map(|h| {
+					format!(
+						"{} {} {} {} {} {}",
+						h.group,
+						h.units,
+						h.book_value,
+						h.market_value,
+						h.unrealized_gain,
+						h.allocation
+					)
+				})
+				.collect()
+		};
+
+		assert_eq!(
+			render(Grouping::Commodity),
+			vec![
+				"BOND (20 BOND) 1000.00 USD 1000.00 USD 0.00 USD 35.71",
+				"HOOL (15 HOOL) 1550.00 USD 1800.00 USD 250.00 USD 64.29",
+			]
+		);
+		assert_eq!(
+			render(Grouping::Account),
+			vec![
+				"Assets:Broker (10 HOOL) 1000.00 USD 1200.00 USD 200.00 USD 42.86",
+				"Assets:Retirement (5 HOOL, 20 BOND) 1550.00 USD 1600.00 USD 50.00 USD 57.14",
+			]
+		);
+		assert_eq!(
+			render(Grouping::Metadata("asset-class".to_string())),
+			vec![
+				"bond (20 BOND) 1000.00 USD 1000.00 USD 0.00 USD 35.71",
+				"stock (15 HOOL) 1550.00 USD 1800.00 USD 250.00 USD 64.29",
+			]
+		);
+	}
+}
@@ -0,0 +1,168 @@
+use chrono::NaiveDate;
+use regex::Regex;
+
+use super::{period, ReportSettings, Valuer};
+use crate::core::{
+	directive::DirectiveKind, inventory::Inventory, prices::PriceMap, types::Account,
+};
+use crate::loader::Ledger;
+
+/// Which postings a journal lists. Dates are both included.
+#[derive(Debug, Clone, Default)]
+pub struct JournalFilter {
+	/// Matched against the account of each posting.
+	pub account: Option<Regex>,
+	pub begin: Option<NaiveDate>,
+	pub end: Option<NaiveDate>,
+	pub tag: Option<String>,
+	pub link: Option<String>,
+}
+
+/// A posting of a journal, with the balance of the journal after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalRow {
+	pub date: NaiveDate,
+	pub flag: Option<char>,
+	pub payee: Option<String>,
+	pub narration: Option<String>,
+	pub account: Account,
+	pub change: Inventory,
+	pub balance: Inventory,
+}
+
+/// Lists the postings matching a filter in date order, with a running
+/// balance in each commodity. With a beginning date, the balances before it
+/// come first as opening balances.
+pub fn journal(
+	ledger: &Ledger,
+	filter: &JournalFilter,
+	settings: &ReportSettings,
+) -> Vec<JournalRow> {
+	let prices = PriceMap::from_directives(&ledger.directives);
+	let mut balance = Inventory::new();
+	let mut rows = vec![];
+	for directive in &period(ledger, filter.begin, filter.end) {
+		let DirectiveKind::Transaction {
+			flag,
+			payee,
+			narration,
+			tags,
+			links,
+			postings,
+		} = &directive.kind
+		else {
+			continue;
+		};
+		if filter.tag.as_ref().is_some_and(|tag| !tags.contains(tag))
+			|| filter
+				.link
+				.as_ref()
+				.is_some_and(|link| !links.contains(link))
+		{
+			continue;
+		}
+
+		// Amounts are converted at the prices of the day of the transaction
+		let valuer = Valuer {
+			prices: &prices,
+			settings,
+			date: Some(directive.date),
+		};
+		for posting in postings {
+			let matches = filter
+				.account
+				.as_ref()
+				.is_none_or(|account| account.is_match(posting.account().as_str()));
+			let Some(position) = posting.position().filter(|_| matches) else {
+				continue;
+			};
+			let change = valuer.value(&Inventory::from(position));
+			let _ = balance.add_inventory(&change);
+			rows.push(JournalRow {
+				date: directive.date,
+				flag: *flag,
+				payee: payee.clone(),
+				narration: narration.clone(),
+				account: posting.account().clone(),
+				change,
+				balance: balance.clone(),
+			});
+		}
+	}
+	rows
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_journal() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			2025-01-01 open Assets:Cash
+			2025-01-01 open Expenses:Food
+			2025-01-01 open Expenses:Travel
+			2025-01-05 * "Opening"
+				Assets:Cash 500.00 USD
+				Assets:Cash -200.00 CAD
+				Equity:Opening-Balances
+			2025-01-10 * "Lunch" #trip
+				Expenses:Food 20.00 USD
+				Assets:Cash
+			2025-01-15 * "Train" #trip ^booking
+				Expenses:Travel 80.00 CAD
+				Assets:Cash
+			2025-01-20 * "Dinner"
+				Expenses:Food 30.00 USD
+				Assets:Cash
+			"#,
+		);
+		let render = |rows: Vec<JournalRow>| -> Vec<String> {
+			rows.iter()
+				.map(|row| {
+					format!(
+						"{} {} {} {}",
+						row.date,
+						row.narration.as_deref().unwrap_or_default(),
+						row.change,
+						row.balance
+					)
+				})
+				.collect()
+		};
+
+		let filter = JournalFilter {
+			account: Regex::new("^Assets:Cash$").ok(),
+			begin: NaiveDate::from_ymd_opt(2025, 1, 10),
+			end: NaiveDate::from_ymd_opt(2025, 1, 15),
+			..Default::default()
+		};
+		assert_eq!(
+			render(journal(&ledger, &filter, &ReportSettings::default())),
+			vec![
+				"2025-01-09 Opening balance for 'Assets:Cash' (Summarization) (500.00 USD) (500.00 USD)",
+				"2025-01-09 Opening balance for 'Assets:Cash' (Summarization) (-200.00 CAD) (500.00 USD, -200.00 CAD)",
+				"2025-01-10 Lunch (-20.00 USD) (480.00 USD, -200.00 CAD)",
+				"2025-01-15 Train (-80.00 CAD) (480.00 USD, -280.00 CAD)",
+			]
+		);
+
+		let filter = JournalFilter {
+			account: Regex::new("^Expenses").ok(),
+			tag: Some("trip".to_string()),
+			..Default::default()
+		};
+		assert_eq!(
+			render(journal(&ledger, &filter, &ReportSettings::default())),
+			vec![
+				"2025-01-10 Lunch (20.00 USD) (20.00 USD)",
+				"2025-01-15 Train (80.00 CAD) (20.00 USD, 80.00 CAD)",
+			]
+		);
+	}
+}
@@ -0,0 +1,165 @@
+use chrono::NaiveDate;
+
+use crate::core::{
+	directive::Directive,
+	inventory::{Inventory, Position},
+	options::Options,
+	prices::PriceMap,
+	types::Commodity,
+};
+use crate::loader::Ledger;
+use crate::realization::RealAccount;
+use crate::summarize::clamp;
+
+pub mod balsheet;
+pub mod budget;
+pub mod gains;
+pub mod holdings;
+pub mod journal;
+pub mod returns;
+pub mod trial;
+
+/// Restricts the directives to a period with both ends included, with what
+/// happened before it summarized.
+fn period(ledger: &Ledger, begin: Option<NaiveDate>, end: Option<NaiveDate>) -> Vec<Directive> {
+	let end = end.and_then(|end| end.succ_opt());
+	clamp(ledger.directives.clone(), begin, end, &ledger.options)
+}
+
+/// How the positions of a report are valued.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Valuation {
+	/// The units held, with their cost.
+	#[default]
+	Units,
+	/// The cost the units were acquired at.
+	Cost,
+	/// The value of the units at the last known price.
+	Market,
+}
+
+/// Settings shared by the reports.
+#[derive(Debug, Clone, Default)]
+pub struct ReportSettings {
+	pub valuation: Valuation,
+	/// The commodity amounts are converted to, when a rate is known.
+	pub currency: Option<Commodity>,
+	/// Accounts deeper than this are collapsed into their parent.
+	pub depth: Option<usize>,
+}
+
+/// Values inventories according to the settings of a report, at a date.
+pub struct Valuer<'a> {
+	pub prices: &'a PriceMap,
+	pub settings: &'a ReportSettings,
+	/// The date prices are taken at, or the last ones if not given.
+	pub date: Option<NaiveDate>,
+}
+
+impl Valuer<'_> {
+	pub fn value(&self, inventory: &Inventory) -> Inventory {
+		let mut valued = Inventory::new();
+		for position in inventory {
+			let position = match self.settings.valuation {
+				Valuation::Units => position.clone(),
+				Valuation::Cost => position.at_cost().into(),
+				Valuation::Market => self.prices.value(position, self.date).into(),
+			};
+			// Units held at cost are only valued at market with the market
+			// valuation, which books the unrealized gains to balance them
+			let converted = match position.cost() {
+				Some(_) => None,
+				None => self.settings.currency.as_ref().and_then(|currency| {
+					self.prices
+						.convert(position.units(), currency, self.date)
+						.map(Position::from)
+				}),
+			};
+			// Reports have no room for errors, overflows are reported when loading
+			let _ = valued.add_position(&converted.unwrap_or(position));
+		}
+		valued
+	}
+}
+
+/// Books the difference between the market value of the accounts under the
+/// roots and their cost to the unrealized gains of the equity, so that the
+/// roots still add up to zero once valued.
+fn book_unrealized(root: &mut RealAccount, names: &[&String], valuer: &Valuer, options: &Options) {
+	if valuer.settings.valuation != Valuation::Market {
+		return;
+	}
+	let mut unrealized = Inventory::new();
+	for real in names.iter().filter_map(|name| root.get(name)) {
+		let _ = unrealized.add_inventory(&valuer.value(&real.total()));
+	}
+	let account = format!(
+		"{}:{}",
+		options.name_equity, options.account_unrealized_gains
+	);
+	let _ = root
+		.get_or_create(&account)
+		.balance
+		.add_inventory(&-&unrealized);
+}
+
+/// A line of a report, with the balance of an account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportRow {
+	pub account: String,
+	pub depth: usize,
+	pub balance: Inventory,
+}
+
+/// A part of a report, such as the assets of a balance sheet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+	pub title: String,
+	pub rows: Vec<ReportRow>,
+	pub total: Inventory,
+}
+
+/// A report made of sections, and a line summing them up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+	pub title: String,
+	pub sections: Vec<Section>,
+	pub summary: Option<(String, Inventory)>,
+}
+
+impl Section {
+	/// Lists the accounts under one of the roots of the tree, like `Assets`,
+	/// titled by its name.
+	pub fn from_root(root: &RealAccount, name: &str, valuer: &Valuer) -> Section {
+		match root.get(name) {
+			Some(real) => Section::from_tree(name, real, valuer),
+			None => Section::from_tree(name, &RealAccount::new(name), valuer),
+		}
+	}
+
+	/// Lists the accounts of a tree that have a balance, with the accounts
+	/// at the maximum depth showing the total of their sub-accounts.
+	pub fn from_tree(title: impl Into<String>, real: &RealAccount, valuer: &Valuer) -> Section {
+		let max_depth = valuer.settings.depth.unwrap_or(usize::MAX);
+		let rows = real
+			.iter()
+			.filter(|real| real.depth() <= max_depth && !real.total().is_empty())
+			.map(|real| {
+				let balance = match real.depth() == max_depth {
+					true => real.total(),
+					false => real.balance.clone(),
+				};
+				ReportRow {
+					account: real.account.clone(),
+					depth: real.depth(),
+					balance: valuer.value(&balance),
+				}
+			})
+			.collect();
+		Section {
+			title: title.into(),
+			rows,
+			total: valuer.value(&real.total()),
+		}
+	}
+}
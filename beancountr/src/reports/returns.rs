@@ -0,0 +1,336 @@
+use chrono::NaiveDate;
+use regex::Regex;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+
+use crate::core::{
+	directive::DirectiveKind,
+	inventory::Inventory,
+	prices::PriceMap,
+	types::{Account, Amount, Commodity},
+};
+use crate::loader::Ledger;
+
+/// The accounts and the period the returns are computed for. Dates are both
+/// included.
+#[derive(Debug, Clone)]
+pub struct ReturnsFilter {
+	/// Matches the accounts holding the investments.
+	pub accounts: Regex,
+	/// Matches the accounts whose postings are part of the returns, like the
+	/// dividends and the fees of the investments. Dividends paid out of the
+	/// investment accounts are still counted as a withdrawal.
+	pub internal: Option<Regex>,
+	pub begin: Option<NaiveDate>,
+	pub end: Option<NaiveDate>,
+}
+
+/// Money put into the investments, or taken out of them when negative, on a
+/// date.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CashFlow {
+	pub date: NaiveDate,
+	pub amount: Decimal,
+	/// The value of the investments at the end of the day, after the flow.
+	pub value: Decimal,
+}
+
+/// The performance of investments over a period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Returns {
+	pub begin: NaiveDate,
+	pub end: NaiveDate,
+	pub currency: Commodity,
+	/// The value of the investments at the end of the day before the period.
+	pub begin_value: Decimal,
+	pub end_value: Decimal,
+	pub flows: Vec<CashFlow>,
+	/// The money-weighted return, per year. Unknown when it can't be solved.
+	pub xirr: Option<f64>,
+	/// The time-weighted return over the whole period.
+	pub twr: Option<f64>,
+	/// The time-weighted return, per year.
+	pub annualized_twr: Option<f64>,
+}
+
+impl Returns {
+	/// Returns the sum of the money put in over the period.
+	pub fn contributions(&self) -> Decimal {
+		self.flows.iter().map(|flow| flow.amount).sum()
+	}
+}
+
+/// Computes the money-weighted and time-weighted returns of investment
+/// accounts over a period, valued in a currency. The period starts at the
+/// first transaction of the accounts and ends at the last directive if not
+/// given.
+///
+/// The cash flows are the postings to other accounts in the transactions of
+/// the investment accounts, and in those of the internal accounts. They are
+/// assumed to happen at the end of their day.
+pub fn returns(ledger: &Ledger, filter: &ReturnsFilter, currency: &Commodity) -> Option<Returns> {
+	let prices = PriceMap::from_directives(&ledger.directives);
+	let is_investment = |account: &Account| filter.accounts.is_match(account.as_str());
+	let is_internal = |account: &Account| {
+		filter
+			.internal
+			.as_ref()
+			.is_some_and(|internal| internal.is_match(account.as_str()))
+	};
+	let convert = |amount: &Amount, date| {
+		prices
+			.convert(amount, currency, Some(date))
+			.map(|amount| amount.number())
+	};
+
+	let mut flows: Vec<(NaiveDate, Decimal)> = vec![];
+	for directive in &ledger.directives {
+		let DirectiveKind::Transaction { postings, .. } = &directive.kind else {
+			continue;
+		};
+		let relevant = postings
+			.iter()
+			.any(|posting| is_investment(posting.account()) || is_internal(posting.account()));
+		if !relevant {
+			continue;
+		}
+		// The money coming in is what leaves the external accounts
+		let flow: Decimal = postings
+			.iter()
+			.filter(|posting| !is_investment(posting.account()) && !is_internal(posting.account()))
+			// Weights that overflow are reported when loading
+			.filter_map(|posting| convert(&posting.weight().ok()??, directive.date))
+			.map(|number| -number)
+			.sum();
+		match flows.last_mut() {
+			Some((date, amount)) if *date == directive.date => *amount += flow,
+			_ => flows.push((directive.date, flow)),
+		}
+	}
+
+	let begin = filter.begin.or(flows.first().map(|(date, _)| *date))?;
+	let end = filter
+		.end
+		.or(ledger.directives.last().map(|directive| directive.date))?;
+	flows.retain(|(date, amount)| *date >= begin && *date <= end && !amount.is_zero());
+
+	// Values the investments at the end of increasing dates
+	let mut balance = Inventory::new();
+	let mut directives = ledger.directives.iter().peekable();
+	let mut value_at = |date: NaiveDate| {
+		while let Some(directive) = directives.next_if(|directive| directive.date <= date) {
+			if let DirectiveKind::Transaction { postings, .. } = &directive.kind {
+				postings
+					.iter()
+					.filter(|posting| is_investment(posting.account()))
+					.filter_map(|posting| posting.position())
+					.for_each(|position| {
+						let _ = balance.add_position(&position);
+					});
+			}
+		}
+		// Positions without a price yet are valued at their cost
+		balance
+			.iter()
+			.filter_map(|position| {
+				let value = position
+					.cost()
+					.and_then(|cost| prices.convert(position.units(), cost.commodity(), Some(date)))
+					.unwrap_or_else(|| position.at_cost());
+				convert(&value, date)
+			})
+			.sum::<Decimal>()
+	};
+
+	let begin_value = begin.pred_opt().map_or(Decimal::ZERO, &mut value_at);
+	let flows: Vec<CashFlow> = flows
+		.into_iter()
+		.map(|(date, amount)| CashFlow {
+			date,
+			amount,
+			value: value_at(date),
+		})
+		.collect();
+	let end_value = value_at(end);
+
+	let mut xirr_flows = vec![(begin, -begin_value)];
+	xirr_flows.extend(flows.iter().map(|flow| (flow.date, -flow.amount)));
+	xirr_flows.push((end, end_value));
+	let twr = twr(begin_value, &flows, end_value);
+	let years = (end - begin).num_days() as f64 / 365.0;
+	Some(Returns {
+		begin,
+		end,
+		currency: currency.clone(),
+		begin_value,
+		end_value,
+		xirr: xirr(&xirr_flows),
+		annualized_twr: twr
+			.filter(|_| years > 0.0)
+			.map(|twr| (1.0 + twr).powf(1.0 / years) - 1.0),
+		twr,
+		flows,
+	})
+}
+
+/// Chains the returns of the periods between the cash flows.
+fn twr(begin_value: Decimal, flows: &[CashFlow], end_value: Decimal) -> Option<f64> {
+	let mut growth = Decimal::ONE;
+	let mut previous = begin_value;
+	let mut invested = !begin_value.is_zero();
+	for flow in flows {
+		if !previous.is_zero() {
+			growth *= (flow.value - flow.amount) / previous;
+		}
+		invested |= !flow.amount.is_zero();
+		previous = flow.value;
+	}
+	if !previous.is_zero() {
+		growth *= end_value / previous;
+	}
+	invested.then(|| (growth - Decimal::ONE).to_f64()).flatten()
+}
+
+/// Finds the yearly rate making the present value of dated cash flows zero,
+/// with Newton's method or by bisection if it doesn't converge.
+fn xirr(flows: &[(NaiveDate, Decimal)]) -> Option<f64> {
+	let first = flows.first()?.0;
+	let flows: Vec<(f64, f64)> = flows
+		.iter()
+		.map(|(date, amount)| {
+			let years = (*date - first).num_days() as f64 / 365.0;
+			Some((years, amount.to_f64()?))
+		})
+		.collect::<Option<_>>()?;
+	let npv = |rate: f64| -> f64 {
+		flows
+			.iter()
+			.map(|(years, amount)| amount / (1.0 + rate).powf(*years))
+			.sum()
+	};
+	let derivative = |rate: f64| -> f64 {
+		flows
+			.iter()
+			.map(|(years, amount)| -years * amount / (1.0 + rate).powf(years + 1.0))
+			.sum()
+	};
+	const TOLERANCE: f64 = 1e-9;
+
+	let mut rate = 0.1;
+	for _ in 0..100 {
+		let (value, slope) = (npv(rate), derivative(rate));
+		if value.abs() < TOLERANCE {
+			return Some(rate);
+		}
+		if slope == 0.0 || !slope.is_finite() {
+			break;
+		}
+		rate -= value / slope;
+		if rate <= -1.0 || !rate.is_finite() {
+			break;
+		}
+	}
+
+	let (mut low, mut high) = (-0.999999, 1e6);
+	if npv(low).signum() == npv(high).signum() {
+		return None;
+	}
+	for _ in 0..200 {
+		let middle = (low + high) / 2.0;
+		match npv(middle).signum() == npv(low).signum() {
+			true => low = middle,
+			false => high = middle,
+		}
+	}
+	Some((low + high) / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_returns() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			2023-01-01 open Assets:Bank
+			2023-01-01 open Assets:Broker
+			2023-01-01 open Income:Dividends
+			2023-01-01 * "Buy"
+				Assets:Broker 10 HOOL {100.00 USD}
+				Assets:Bank
+			2023-07-01 price HOOL 120.00 USD
+			2023-07-01 * "Buy"
+				Assets:Broker 5 HOOL {120.00 USD}
+				Assets:Bank
+			2023-10-01 * "Dividend"
+				Income:Dividends -20.00 USD
+				Assets:Bank
+			2024-01-01 price HOOL 108.00 USD
+			"#,
+		);
+		let usd: Commodity = "USD".parse().unwrap();
+		let filter = ReturnsFilter {
+			accounts: Regex::new("^Assets:Broker").unwrap(),
+			internal: Regex::new("^Income:Dividends").ok(),
+			begin: None,
+			end: None,
+		};
+		let returns = returns(&ledger, &filter, &usd).unwrap();
+		let flows: Vec<(String, String, String)> = returns
+			.flows
+			.iter()
+			.map(|flow| {
+				(
+					flow.date.to_string(),
+					flow.amount.to_string(),
+					flow.value.to_string(),
+				)
+			})
+			.collect();
+		assert_eq!(
+			flows,
+			vec![
+				(
+					"2023-01-01".to_string(),
+					"1000.00".to_string(),
+					"1000.00".to_string()
+				),
+				(
+					"2023-07-01".to_string(),
+					"600.00".to_string(),
+					"1800.00".to_string()
+				),
+				(
+					"2023-10-01".to_string(),
+					"-20.00".to_string(),
+					"1800.00".to_string()
+				),
+			]
+		);
+		assert_eq!(returns.begin_value, Decimal::ZERO);
+		assert_eq!(returns.end_value.to_string(), "1620.00");
+		assert_eq!(returns.contributions().to_string(), "1580.00");
+		// 1.2 * (1820 / 1800) * 0.9
+		assert!((returns.twr.unwrap() - 0.092).abs() < 1e-9);
+
+		let xirr = returns.xirr.unwrap();
+		let npv = -1000.0 - 600.0 / (1.0 + xirr).powf(181.0 / 365.0)
+			+ 20.0 / (1.0 + xirr).powf(273.0 / 365.0)
+			+ 1620.0 / (1.0 + xirr);
+		assert!(npv.abs() < 1e-6);
+		assert!((xirr - 0.030883).abs() < 1e-6);
+
+		// Starting from the value held after the first purchase
+		let filter = ReturnsFilter {
+			begin: NaiveDate::from_ymd_opt(2023, 7, 1),
+			..filter
+		};
+		let returns = super::returns(&ledger, &filter, &usd).unwrap();
+		assert_eq!(returns.begin_value.to_string(), "1000.00");
+		assert!((returns.twr.unwrap() - 0.092).abs() < 1e-9);
+	}
+}
@@ -0,0 +1,151 @@
+use chrono::NaiveDate;
+
+use super::{book_unrealized, period, Report, ReportSettings, Section, Valuation, Valuer};
+use crate::core::{inventory::Inventory, prices::PriceMap};
+use crate::loader::Ledger;
+use crate::realization::realize;
+
+/// Builds the trial balance of a period, with both ends included: the
+/// balance of every account under each root. Since every transaction
+/// balances, the total adds up to zero, with the units held at cost counted
+/// at their cost. At market value, the difference with that cost is booked to
+/// the unrealized gains of the equity.
+pub fn trial_balance(
+	ledger: &Ledger,
+	begin: Option<NaiveDate>,
+	end: Option<NaiveDate>,
+	settings: &ReportSettings,
+) -> Report {
+	let options = &ledger.options;
+	let mut root = realize(&period(ledger, begin, end));
+
+	let prices = PriceMap::from_directives(&ledger.directives);
+	let valuer = Valuer {
+		prices: &prices,
+		settings,
+		date: end,
+	};
+	let at_cost = ReportSettings {
+		valuation: Valuation::Cost,
+		..settings.clone()
+	};
+	let cost_valuer = Valuer {
+		settings: &at_cost,
+		..valuer
+	};
+	let names = [
+		&options.name_assets,
+		&options.name_liabilities,
+		&options.name_equity,
+		&options.name_income,
+		&options.name_expenses,
+	];
+	book_unrealized(&mut root, &names, &valuer, options);
+	let mut total = Inventory::new();
+	let sections: Vec<Section> = names
+		.into_iter()
+		.map(|name| {
+			let mut section = Section::from_root(&root, name, &valuer);
+			// Parents only show the balance of their sub-accounts when collapsed
+			section.rows.retain(|row| !row.balance.is_empty());
+			let _ = total.add_inventory(&cost_valuer.value(&section.total));
+			section
+		})
+		.collect();
+
+	Report {
+		title: "Trial Balance".to_string(),
+		sections,
+		summary: Some(("Total".to_string(), total)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::loader::load_str;
+
+	#[test]
+	fn test_trial_balance() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			2025-01-01 open Assets:Bank:Checking
+			2025-01-01 open Assets:Bank:Savings
+			2025-01-01 open Income:Salary
+			2025-01-01 open Expenses:Food
+			2025-01-05 * "Pay"
+				Assets:Bank:Checking 3000.00 USD
+				Income:Salary
+			2025-01-06 * "Save"
+				Assets:Bank:Savings 1000.00 USD
+				Assets:Bank:Checking
+			2025-02-10 * "Food"
+				Expenses:Food 40.00 USD
+				Assets:Bank:Checking
+			"#,
+		);
+
+		let report = trial_balance(
+			&ledger,
+			NaiveDate::from_ymd_opt(2025, 2, 1),
+			None,
+			&ReportSettings::default(),
+		);
+		let rows: Vec<(String, String)> = report
+			.sections
+			.iter()
+			.flat_map(|section| &section.rows)
+			.map(|row| (row.account.clone(), row.balance.to_string()))
+			.collect();
+		assert_eq!(
+			rows,
+			vec![
+				(
+					"Assets:Bank:Checking".to_string(),
+					"(1960.00 USD)".to_string()
+				),
+				(
+					"Assets:Bank:Savings".to_string(),
+					"(1000.00 USD)".to_string()
+				),
+				(
+					"Equity:Earnings:Previous".to_string(),
+					"(-3000.00 USD)".to_string()
+				),
+				("Expenses:Food".to_string(), "(40.00 USD)".to_string()),
+			]
+		);
+		assert_eq!(report.summary.unwrap().1, Inventory::new());
+	}
+
+	#[test]
+	fn test_trial_balance_at_cost() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			option "operating_currency" "USD"
+			2025-01-01 open Assets:Broker
+			2025-01-01 open Equity:Opening
+			2025-01-02 * "Buy"
+				Assets:Broker 10 HOOL {100.00 USD}
+				Equity:Opening
+			2025-01-03 price HOOL 120.00 USD
+			"#,
+		);
+		assert!(ledger.errors.is_empty(), "{:?}", ledger.errors);
+
+		for valuation in [Valuation::Units, Valuation::Cost, Valuation::Market] {
+			let settings = ReportSettings {
+				valuation,
+				currency: ledger.options.operating_currency.first().cloned(),
+				depth: None,
+			};
+			let report = trial_balance(&ledger, None, None, &settings);
+			let total = report.summary.unwrap().1;
+			assert!(total.is_empty(), "{:?}: {}", valuation, total);
+		}
+	}
+}
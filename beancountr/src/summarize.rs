@@ -0,0 +1,440 @@
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use crate::core::{
+	amounts::Amounts,
+	directive::{Directive, DirectiveKind, MetadataMap, Posting},
+	inventory::Inventory,
+	options::Options,
+	position::CostOrSpec,
+	types::{Account, Amount, Commodity},
+};
+
+/// Checks if an account is an income or expenses account.
+pub fn is_income_statement_account(account: &Account, options: &Options) -> bool {
+	let root = account.as_str().split(':').next().unwrap_or_default();
+	root == options.name_income || root == options.name_expenses
+}
+
+/// Returns the index of the first directive on or after a date, the
+/// directives being sorted.
+fn split_index(directives: &[Directive], date: Option<NaiveDate>) -> usize {
+	match date {
+		Some(date) => directives.partition_point(|directive| directive.date < date),
+		None => directives.len(),
+	}
+}
+
+/// Returns the date summarizing directives are made on: the day before the
+/// date, or the date of the last directive if none is given.
+fn summary_date(directives: &[Directive], date: Option<NaiveDate>) -> Option<NaiveDate> {
+	match date {
+		Some(date) => Some(date.pred_opt().unwrap_or(date)),
+		None => directives.last().map(|directive| directive.date),
+	}
+}
+
+/// Sums the positions of every account over the transactions.
+fn balances(directives: &[Directive]) -> BTreeMap<Account, Inventory> {
+	let mut balances: BTreeMap<Account, Inventory> = BTreeMap::new();
+	for directive in directives {
+		if let DirectiveKind::Transaction { postings, .. } = &directive.kind {
+			for posting in postings {
+				// Balances that overflow are reported when booking
+				if let Some(position) = posting.position() {
+					let _ = balances
+						.entry(posting.account().clone())
+						.or_default()
+						.add_position(&position);
+				}
+			}
+		}
+	}
+	balances
+}
+
+fn transaction(
+	date: NaiveDate,
+	flag: char,
+	narration: String,
+	postings: Vec<Posting>,
+) -> Directive {
+	Directive::new(
+		date,
+		DirectiveKind::Transaction {
+			flag: Some(flag),
+			payee: None,
+			narration: Some(narration),
+			tags: HashSet::new(),
+			links: HashSet::new(),
+			postings,
+		},
+		MetadataMap::new(),
+	)
+}
+
+fn posting(
+	account: &Account,
+	units: Amount,
+	cost: Option<CostOrSpec>,
+	price: Option<Amount>,
+) -> Posting {
+	Posting::new(
+		account.clone(),
+		Some(units),
+		cost,
+		price,
+		None,
+		MetadataMap::new(),
+	)
+}
+
+/// Creates a transaction per account moving its balance to or from another
+/// account, at cost. With `summarize` the balance is kept on the account and
+/// taken from the other one, otherwise it's moved to the other one.
+fn entries_from_balances(
+	balances: &BTreeMap<Account, Inventory>,
+	date: NaiveDate,
+	other: &Account,
+	summarize: bool,
+	flag: char,
+	narration: impl Fn(&Account) -> String,
+) -> Vec<Directive> {
+	balances
+		.iter()
+		.filter(|(_, balance)| !balance.is_empty())
+		.map(|(account, balance)| {
+			let mut postings = vec![];
+			for position in balance {
+				let position = match summarize {
+					true => position.clone(),
+					false => -position,
+				};
+				postings.push(posting(
+					account,
+					position.units().clone(),
+					position.cost().cloned().map(CostOrSpec::Cost),
+					None,
+				));
+				postings.push(posting(other, -position.at_cost(), None, None));
+			}
+			transaction(date, flag, narration(account), postings)
+		})
+		.collect()
+}
+
+/// Moves the balances of the accounts matching a predicate before a date, or
+/// over all the directives, to another account. The transfers are made the
+/// day before the date.
+pub fn transfer_balances(
+	mut directives: Vec<Directive>,
+	date: Option<NaiveDate>,
+	predicate: impl Fn(&Account) -> bool,
+	to: &Account,
+) -> Vec<Directive> {
+	let index = split_index(&directives, date);
+	let Some(transfer_date) = summary_date(&directives[..index], date) else {
+		return directives;
+	};
+	let mut balances = balances(&directives[..index]);
+	balances.retain(|account, _| predicate(account));
+	let transfers = entries_from_balances(&balances, transfer_date, to, false, 'T', |account| {
+		format!("Transfer balance for '{}' (Transfer balance)", account)
+	});
+	directives.splice(index..index, transfers);
+	directives
+}
+
+/// Replaces the transactions before a date by a transaction per account
+/// opening its balance against another account, the day before the date.
+/// Accounts that are still open, commodities and prices are kept.
+pub fn summarize(directives: Vec<Directive>, date: NaiveDate, account: &Account) -> Vec<Directive> {
+	let index = split_index(&directives, Some(date));
+	let summary_date = date.pred_opt().unwrap_or(date);
+	let balances = balances(&directives[..index]);
+	let closed: HashSet<&Account> = directives[..index]
+		.iter()
+		.filter_map(|directive| match &directive.kind {
+			DirectiveKind::Close(account) => Some(account),
+			_ => None,
+		})
+		.collect();
+
+	let mut summarized: Vec<Directive> = directives[..index]
+		.iter()
+		.filter(|directive| match &directive.kind {
+			DirectiveKind::Open(account, ..) => !closed.contains(account),
+			DirectiveKind::Commodity(..) | DirectiveKind::Price { .. } => true,
+			_ => false,
+		})
+		.cloned()
+		.collect();
+	summarized.extend(entries_from_balances(
+		&balances,
+		summary_date,
+		account,
+		true,
+		'S',
+		|account| format!("Opening balance for '{}' (Summarization)", account),
+	));
+	summarized.sort_by_key(Directive::sort_key);
+	summarized.extend(directives.into_iter().skip(index));
+	summarized
+}
+
+/// Drops the directives on or after a date.
+pub fn truncate(mut directives: Vec<Directive>, date: NaiveDate) -> Vec<Directive> {
+	directives.truncate(split_index(&directives, Some(date)));
+	directives
+}
+
+/// Adds a transaction cancelling what's left of the balances at cost before a
+/// date, or over all the directives. Converting between commodities at a
+/// price leaves the units of both at cost, so the balance sheet wouldn't
+/// add up to zero without it. Its postings are priced at zero in the
+/// conversion currency, so it balances.
+pub fn conversions(
+	mut directives: Vec<Directive>,
+	account: &Account,
+	conversion_currency: &Commodity,
+	date: Option<NaiveDate>,
+) -> Vec<Directive> {
+	let index = split_index(&directives, date);
+	let Some(conversion_date) = summary_date(&directives[..index], date) else {
+		return directives;
+	};
+	let mut residual = Amounts::new();
+	for inventory in balances(&directives[..index]).values() {
+		// Sums too large to represent are left out of the conversions
+		if let Ok(at_cost) = inventory.at_cost() {
+			let _ = residual.add_amounts(&at_cost);
+		}
+	}
+	if residual.is_empty() {
+		return directives;
+	}
+
+	let price = Amount::new(Decimal::ZERO, conversion_currency.clone());
+	let postings = residual
+		.iter()
+		.map(|amount| posting(account, -amount, None, Some(price.clone())))
+		.collect();
+	let conversion = transaction(
+		conversion_date,
+		'C',
+		format!("Conversion for {}", residual),
+		postings,
+	);
+	directives.insert(index, conversion);
+	directives
+}
+
+fn conversion_currency(options: &Options) -> Commodity {
+	options
+		.conversion_currency
+		.parse()
+		.unwrap_or_else(|_| "NOTHING".parse().unwrap())
+}
+
+/// Opens the books at a date: income and expenses before it are moved to the
+/// previous earnings, then every balance is summarized into an opening
+/// balance. Like Beancount's `summarize.open`.
+pub fn open(directives: Vec<Directive>, date: NaiveDate, options: &Options) -> Vec<Directive> {
+	let directives = transfer_balances(
+		directives,
+		Some(date),
+		|account| is_income_statement_account(account, options),
+		&options.equity_account(&options.account_previous_earnings),
+	);
+	let directives = summarize(
+		directives,
+		date,
+		&options.equity_account(&options.account_previous_balances),
+	);
+	conversions(
+		directives,
+		&options.equity_account(&options.account_previous_conversions),
+		&conversion_currency(options),
+		Some(date),
+	)
+}
+
+/// Closes the books at a date, dropping the directives from it on, or at the
+/// end if no date is given. Like Beancount's `summarize.close`.
+pub fn close(
+	directives: Vec<Directive>,
+	date: Option<NaiveDate>,
+	options: &Options,
+) -> Vec<Directive> {
+	let directives = match date {
+		Some(date) => truncate(directives, date),
+		None => directives,
+	};
+	conversions(
+		directives,
+		&options.equity_account(&options.account_current_conversions),
+		&conversion_currency(options),
+		date,
+	)
+}
+
+/// Moves the income and expenses before a date, or over all the directives,
+/// to the current earnings. Like Beancount's `summarize.clear`.
+pub fn clear(
+	directives: Vec<Directive>,
+	date: Option<NaiveDate>,
+	options: &Options,
+) -> Vec<Directive> {
+	transfer_balances(
+		directives,
+		date,
+		|account| is_income_statement_account(account, options),
+		&options.equity_account(&options.account_current_earnings),
+	)
+}
+
+/// Restricts the directives to a period, from `begin` included to `end`
+/// excluded. What happened before the period is summarized into opening
+/// balances, with its income and expenses in the previous earnings, so the
+/// balances over the period still add up. Like Beancount's
+/// `summarize.clamp_opt`.
+pub fn clamp(
+	directives: Vec<Directive>,
+	begin: Option<NaiveDate>,
+	end: Option<NaiveDate>,
+	options: &Options,
+) -> Vec<Directive> {
+	let mut directives = directives;
+	if let Some(begin) = begin {
+		directives = transfer_balances(
+			directives,
+			Some(begin),
+			|account| is_income_statement_account(account, options),
+			&options.equity_account(&options.account_previous_earnings),
+		);
+		directives = summarize(
+			directives,
+			begin,
+			&options.equity_account(&options.account_previous_balances),
+		);
+	}
+	if let Some(end) = end {
+		directives = truncate(directives, end);
+	}
+	conversions(
+		directives,
+		&options.equity_account(&options.account_current_conversions),
+		&conversion_currency(options),
+		end,
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::loader::load_str;
+	use crate::realization::realize;
+
+	const LEDGER: &str = r#"
+		2024-01-01 open Assets:Bank
+		2024-01-01 open Assets:Old
+		2024-01-01 open Income:Salary
+		2024-01-01 open Expenses:Food
+		2024-06-01 close Assets:Old
+		2024-12-01 * "Pay"
+			Assets:Bank 1000.00 USD
+			Income:Salary
+		2024-12-15 * "Exchange"
+			Assets:Bank 100.00 CAD @ 0.75 USD
+			Assets:Bank -75.00 USD
+		2025-01-10 * "Food"
+			Expenses:Food 40.00 USD
+			Assets:Bank
+		2025-02-10 * "Food"
+			Expenses:Food 60.00 USD
+			Assets:Bank
+		"#;
+
+	fn date(s: &str) -> NaiveDate {
+		s.parse().unwrap()
+	}
+
+	#[test]
+	fn test_clamp() {
+		let ledger = load_str(Rc::from("test"), LEDGER);
+		assert!(ledger.errors.is_empty(), "{:?}", ledger.errors);
+
+		let clamped = clamp(
+			ledger.directives.clone(),
+			Some(date("2025-01-01")),
+			Some(date("2025-02-01")),
+			&ledger.options,
+		);
+		let printed: Vec<String> = clamped.iter().map(|d| d.to_string()).collect();
+		assert_eq!(
+			printed,
+			vec![
+				"2024-01-01 open Assets:Bank",
+				"2024-01-01 open Income:Salary",
+				"2024-01-01 open Expenses:Food",
+				"2024-12-31 S \"Opening balance for 'Assets:Bank' (Summarization)\"\n  Assets:Bank  925.00 USD\n  Equity:Opening-Balances  -925.00 USD\n  Assets:Bank  100.00 CAD\n  Equity:Opening-Balances  -100.00 CAD",
+				"2024-12-31 S \"Opening balance for 'Equity:Earnings:Previous' (Summarization)\"\n  Equity:Earnings:Previous  -1000.00 USD\n  Equity:Opening-Balances  1000.00 USD",
+				"2025-01-10 * \"Food\"\n  Expenses:Food  40.00 USD\n  Assets:Bank  -40.00 USD",
+			]
+		);
+
+		// The balances still add up over the period
+		let root = realize(&clamped);
+		assert_eq!(root.total().to_string(), "()");
+		assert_eq!(
+			root.get("Assets:Bank").unwrap().balance.to_string(),
+			"(885.00 USD, 100.00 CAD)"
+		);
+	}
+
+	#[test]
+	fn test_open_close_clear() {
+		let ledger = load_str(Rc::from("test"), LEDGER);
+
+		let closed = close(
+			ledger.directives.clone(),
+			Some(date("2025-01-01")),
+			&ledger.options,
+		);
+		let conversion = closed.last().unwrap().to_string();
+		assert_eq!(
+			conversion,
+			"2024-12-31 C \"Conversion for (100.00 CAD, -75.00 USD)\"\n  Equity:Conversions:Current  -100.00 CAD @ 0 NOTHING\n  Equity:Conversions:Current  75.00 USD @ 0 NOTHING"
+		);
+
+		let cleared = clear(closed, None, &ledger.options);
+		let root = realize(&cleared);
+		assert!(root.get("Income").unwrap().total().is_empty());
+		assert_eq!(
+			root.get("Equity:Earnings:Current")
+				.unwrap()
+				.balance
+				.to_string(),
+			"(-1000.00 USD)"
+		);
+
+		let opened = open(
+			ledger.directives.clone(),
+			date("2025-02-01"),
+			&ledger.options,
+		);
+		let root = realize(&opened);
+		assert_eq!(
+			root.get("Equity:Earnings:Previous")
+				.unwrap()
+				.total()
+				.to_string(),
+			"(-960.00 USD)"
+		);
+		assert_eq!(root.total().to_string(), "()");
+	}
+}
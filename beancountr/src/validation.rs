@@ -0,0 +1,325 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use crate::core::{
+	amounts::Amounts,
+	directive::{Directive, DirectiveKind},
+	error::Diagnostic,
+	options::Options,
+	tolerance::{balance_tolerance, infer_tolerances},
+	types::{Account, Amount},
+};
+
+/// Checks that accounts are opened and closed at most once, and that they are
+/// only used from the day they are opened to the day they are closed.
+pub fn validate_open_close(directives: &[Directive]) -> Vec<Diagnostic> {
+	let mut sorted: Vec<&Directive> = directives.iter().collect();
+	sorted.sort_by_key(|directive| directive.sort_key());
+
+	let mut errors = vec![];
+	let mut opened: HashMap<&Account, NaiveDate> = HashMap::new();
+	let mut closed: HashMap<&Account, NaiveDate> = HashMap::new();
+	for directive in &sorted {
+		let (dates, account, kind) = match &directive.kind {
+			DirectiveKind::Open(account, ..) => (&mut opened, account, "open"),
+			DirectiveKind::Close(account) => (&mut closed, account, "close"),
+			_ => continue,
+		};
+		match dates.entry(account) {
+			Entry::Occupied(_) => errors.push(Diagnostic::at(
+				directive,
+				format!("Duplicate {} directive for {}", kind, account),
+			)),
+			Entry::Vacant(entry) => {
+				entry.insert(directive.date);
+			}
+		}
+	}
+
+	for directive in sorted {
+		if let DirectiveKind::Open(..) = directive.kind {
+			continue;
+		}
+		let mut accounts = directive.accounts();
+		accounts.sort();
+		accounts.dedup();
+		for account in accounts {
+			let message = match (opened.get(account), closed.get(account)) {
+				(None, _) => format!("Invalid reference to unknown account '{}'", account),
+				(Some(open), _) if directive.date < *open => {
+					format!(
+						"Invalid reference to account '{}' before it's opened",
+						account
+					)
+				}
+				(_, Some(close)) if directive.date > *close => {
+					format!(
+						"Invalid reference to account '{}' after it's closed",
+						account
+					)
+				}
+				_ => continue,
+			};
+			errors.push(Diagnostic::at(directive, message));
+		}
+	}
+	errors
+}
+
+/// Checks that every transaction balances within the tolerances inferred from
+/// its postings. Transactions with postings that are missing their amount are
+/// skipped, since those are filled in to balance the transaction.
+pub fn validate_transaction_balances(
+	directives: &[Directive],
+	options: &Options,
+) -> Vec<Diagnostic> {
+	let mut errors = vec![];
+	for directive in directives {
+		let DirectiveKind::Transaction { postings, .. } = &directive.kind else {
+			continue;
+		};
+		let weights = match postings
+			.iter()
+			.map(|posting| posting.weight())
+			.collect::<Result<Option<Vec<_>>, _>>()
+		{
+			Ok(Some(weights)) => weights,
+			Ok(None) => continue,
+			Err(e) => {
+				errors.push(Diagnostic::at(directive, e.to_string()));
+				continue;
+			}
+		};
+		let residual = match Amounts::sum(weights) {
+			Ok(residual) => residual,
+			Err(e) => {
+				errors.push(Diagnostic::at(directive, e.to_string()));
+				continue;
+			}
+		};
+		let tolerances = infer_tolerances(postings, &directive.meta, options);
+		let unbalanced: Vec<String> = residual
+			.iter()
+			.filter(|amount| !tolerances.is_negligible(amount))
+			.map(|amount| amount.to_string())
+			.collect();
+		if !unbalanced.is_empty() {
+			errors.push(Diagnostic::at(
+				directive,
+				format!("Transaction does not balance: ({})", unbalanced.join(", ")),
+			));
+		}
+	}
+	errors
+}
+
+/// Checks every balance assertion against the balance of its account and all
+/// its sub-accounts at the start of the day, within the tolerance of the
+/// assertion.
+pub fn validate_balance_assertions(directives: &[Directive], options: &Options) -> Vec<Diagnostic> {
+	let mut sorted: Vec<&Directive> = directives.iter().collect();
+	sorted.sort_by_key(|directive| directive.sort_key());
+
+	let mut errors = vec![];
+	let mut balances: HashMap<Account, Amounts> = HashMap::new();
+	for directive in sorted {
+		match &directive.kind {
+			DirectiveKind::Transaction { postings, .. } => {
+				for posting in postings {
+					if let Some(units) = posting.units() {
+						let balance = balances.entry(posting.account().clone()).or_default();
+						if let Err(e) = balance.add_amount(units) {
+							errors.push(Diagnostic::at(directive, e.to_string()));
+						}
+					}
+				}
+			}
+			DirectiveKind::Balance {
+				account,
+				amount,
+				tolerance,
+				..
+			} => {
+				let balance: Decimal = balances
+					.iter()
+					.filter(|(other, _)| account.includes(other))
+					.map(|(_, amounts)| amounts.get(amount.commodity()))
+					.sum();
+				let diff = balance - amount.number();
+				let tolerance = balance_tolerance(amount, *tolerance, &directive.meta, options);
+				if diff.abs() > tolerance {
+					errors.push(Diagnostic::at(
+						directive,
+						format!(
+							"Balance failed for '{}': expected {} != accumulated {} ({} too {})",
+							account,
+							amount,
+							Amount::new(balance, amount.commodity().clone()),
+							diff.abs(),
+							if diff.is_sign_positive() {
+								"much"
+							} else {
+								"little"
+							}
+						),
+					));
+				}
+			}
+			_ => {}
+		}
+	}
+	errors
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use crate::parser::{parse_str, Statement};
+
+	use super::*;
+
+	fn load(src: &str) -> (Vec<Directive>, Options) {
+		let (statements, errors) = parse_str(Rc::from("test"), src);
+		assert!(errors.is_empty(), "{:?}", errors);
+
+		let mut options = Options::default();
+		let mut directives = vec![];
+		for statement in statements {
+			match statement {
+				Statement::Option(key, value) => options.set(&key, &value).unwrap(),
+				Statement::Directive(directive) => directives.push(directive),
+				_ => {}
+			}
+		}
+		(directives, options)
+	}
+
+	#[test]
+	fn test_transaction_balances() {
+		let (directives, options) = load(
+			r#"
+			option "inferred_tolerance_default" "JPY:1"
+			2025-01-01 * "Within the inferred tolerance of 0.005 USD"
+				Assets:Cash 10.004 USD
+				Expenses:Food -10.00 USD
+			2025-01-02 * "Outside of it"
+				Assets:Cash 10.006 USD
+				Expenses:Food -10.00 USD
+			2025-01-03 * "Integer amounts use the default"
+				Assets:Cash 1000 JPY
+				Expenses:Food -1001 JPY
+			2025-01-04 * "Tolerance from metadata"
+				tolerance: 0.1
+				Assets:Cash 10.05 USD
+				Expenses:Food -10.00 USD
+			2025-01-05 * "Converted at a price"
+				Assets:Cash 100.00 CAD @ 0.75 USD
+				Expenses:Food -75.00 USD
+			2025-01-06 * "Interpolated"
+				Assets:Cash 100.00 CAD
+				Expenses:Food
+			"#,
+		);
+
+		let errors = validate_transaction_balances(&directives, &options);
+		assert_eq!(errors.len(), 1, "{:?}", errors);
+		assert_eq!(errors[0].location.as_ref().map(|l| l.lineno), Some(6));
+		assert_eq!(
+			errors[0].message,
+			"Transaction does not balance: (0.006 USD)"
+		);
+	}
+
+	#[test]
+	fn test_overflowing_weights() {
+		let (directives, options) = load(
+			r#"
+			2025-01-01 * "Weight too large"
+				Assets:Cash 50000000000000000000000000000 CAD @ 2 USD
+				Expenses:Food -1 USD
+			2025-01-02 * "Sum too large"
+				Assets:Cash 50000000000000000000000000000 USD
+				Assets:Bank 50000000000000000000000000000 USD
+				Expenses:Food -1 USD
+			"#,
+		);
+
+		let errors: Vec<String> = validate_transaction_balances(&directives, &options)
+			.iter()
+			.map(|e| e.to_string())
+			.collect();
+		assert_eq!(
+			errors,
+			vec!["test:2: Arithmetic overflow", "test:5: Arithmetic overflow"]
+		);
+	}
+
+	#[test]
+	fn test_balance_assertions() {
+		let (directives, options) = load(
+			r#"
+			option "inferred_tolerance_default" "*:0.5"
+			2025-01-01 * "Deposit"
+				Assets:Bank:Checking 100.00 USD
+				Assets:Bank:Savings 50.00 USD
+				Equity:Opening-Balances -150.00 USD
+			2025-01-01 balance Assets:Bank 0 USD
+			2025-01-02 balance Assets:Bank 150.01 USD
+			2025-01-02 balance Assets:Bank:Checking 100 USD
+			2025-01-02 balance Assets:Bank:Checking 101 USD
+			2025-01-02 balance Assets:Bank:Savings 50.2 ~ 0.1 USD
+			"#,
+		);
+
+		let errors = validate_balance_assertions(&directives, &options);
+		assert_eq!(errors.len(), 2, "{:?}", errors);
+		assert_eq!(
+			errors[0].message,
+			"Balance failed for 'Assets:Bank:Checking': expected 101 USD != accumulated 100.00 USD (1.00 too little)"
+		);
+		assert_eq!(errors[1].location.as_ref().map(|l| l.lineno), Some(11));
+	}
+
+	#[test]
+	fn test_open_close() {
+		let (directives, _) = load(
+			r#"
+			2025-01-01 open Assets:Cash
+			2025-01-02 open Expenses:Food
+			2025-01-01 * "Before the open"
+				Expenses:Food 10.00 USD
+				Assets:Cash
+			2025-01-03 * "Unknown"
+				Expenses:Rent 10.00 USD
+				Assets:Cash
+				Assets:Cash
+			2025-01-05 close Expenses:Food
+			2025-01-05 * "On the close"
+				Expenses:Food 10.00 USD
+				Assets:Cash
+			2025-01-06 note Expenses:Food "After the close"
+			2025-01-07 open Assets:Cash
+			2025-01-08 close Assets:Bank
+			"#,
+		);
+
+		let errors: Vec<String> = validate_open_close(&directives)
+			.iter()
+			.map(|e| e.to_string())
+			.collect();
+		assert_eq!(
+			errors,
+			vec![
+				"test:16: Duplicate open directive for Assets:Cash",
+				"test:4: Invalid reference to account 'Expenses:Food' before it's opened",
+				"test:7: Invalid reference to unknown account 'Expenses:Rent'",
+				"test:15: Invalid reference to account 'Expenses:Food' after it's closed",
+				"test:17: Invalid reference to unknown account 'Assets:Bank'",
+			]
+		);
+	}
+}
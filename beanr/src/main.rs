@@ -1,7 +1,17 @@
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitCode;
 use std::rc::Rc;
 
+use beancountr::loader::{load_file, Ledger};
 use beancountr::parser::{parse_str, print_errors};
 use clap::{Parser, Subcommand};
+use output::Format;
+use report::ReportCommand;
+
+mod output;
+mod query;
+mod report;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -14,9 +24,37 @@ struct Cli {
 enum Commands {
 	/// does testing things
 	Test,
+	/// Runs a BQL query against a ledger, or starts an interactive shell
+	/// without one
+	Query {
+		/// The ledger file
+		file: PathBuf,
+		/// The query to run
+		query: Option<String>,
+		/// Runs a query stored in the ledger with a query directive
+		#[arg(long, conflicts_with = "query")]
+		name: Option<String>,
+		/// How results are written
+		#[arg(long, short, value_enum, default_value_t = Format::Text)]
+		format: Format,
+	},
+	/// Writes a report on a ledger
+	Report {
+		#[command(subcommand)]
+		report: ReportCommand,
+	},
+}
+
+/// Loads a ledger, reporting its errors without stopping.
+fn load(file: &PathBuf) -> Ledger {
+	let ledger = load_file(file);
+	for error in &ledger.errors {
+		eprintln!("{}", error);
+	}
+	ledger
 }
 
-fn main() {
+fn main() -> ExitCode {
 	let cli = Cli::parse();
 
 	match &cli.command {
@@ -31,9 +69,53 @@ fn main() {
 
 			print_errors(filename, src, errors);
 
-			if let Some(statements) = statements {
-				println!("{:#?}", statements);
+			println!("{:#?}", statements);
+		}
+		Commands::Query {
+			file,
+			query,
+			name,
+			format,
+		} => {
+			let ledger = load(file);
+			let src = match (query, name) {
+				(Some(query), _) => query.as_str(),
+				(None, Some(name)) => match query::stored_query(&ledger, name) {
+					Some(query) => query,
+					None => {
+						eprintln!("No query named \"{}\" in {}", name, file.display());
+						return ExitCode::FAILURE;
+					}
+				},
+				(None, None) => {
+					return match query::repl(&ledger, *format) {
+						Ok(()) => ExitCode::SUCCESS,
+						Err(e) => {
+							eprintln!("{}", e);
+							ExitCode::FAILURE
+						}
+					}
+				}
+			};
+			if !query::run_query(&ledger, src, *format, &mut io::stdout().lock()) {
+				return ExitCode::FAILURE;
+			}
+		}
+		Commands::Report { report } => {
+			let ledger = load(&report.args().file);
+			let (title, result) = match report.run(&ledger) {
+				Ok(table) => table,
+				Err(e) => {
+					eprintln!("{}", e);
+					return ExitCode::FAILURE;
+				}
+			};
+			let format = report.args().format;
+			if let Err(e) = report::write_report(&title, &result, format, &ledger.display_context) {
+				eprintln!("Could not write the report: {}", e);
+				return ExitCode::FAILURE;
 			}
 		}
 	}
+	ExitCode::SUCCESS
 }
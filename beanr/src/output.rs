@@ -0,0 +1,251 @@
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use beancountr::core::{display_context::DisplayContext, inventory::Position, types::Amount};
+use beancountr::query::{value::Value, QueryResult};
+use clap::ValueEnum;
+use serde_json::json;
+
+/// How the results of a query are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+	/// An aligned table
+	Text,
+	Csv,
+	Json,
+}
+
+/// Writes the result of a query in a format, with the numbers of amounts
+/// rendered in the style inferred for their commodity.
+pub fn write_result(
+	out: &mut impl Write,
+	result: &QueryResult,
+	format: Format,
+	context: &DisplayContext,
+) -> io::Result<()> {
+	match format {
+		Format::Text => write_text(out, result, context),
+		Format::Csv => write_csv(out, result, context),
+		Format::Json => write_json(out, result, context),
+	}
+}
+
+/// Whether a value is aligned to the right of its column.
+fn is_numeric(value: &Value) -> bool {
+	matches!(
+		value,
+		Value::Number(_) | Value::Amount(_) | Value::Position(_) | Value::Inventory(_)
+	)
+}
+
+/// Writes the rows as a table, with the columns as wide as their widest cell
+/// and numbers aligned to the right.
+pub fn write_text(
+	out: &mut impl Write,
+	result: &QueryResult,
+	context: &DisplayContext,
+) -> io::Result<()> {
+	let cells: Vec<Vec<String>> = result
+		.rows
+		.iter()
+		.map(|row| row.iter().map(|value| value.format(context)).collect())
+		.collect();
+	let widths: Vec<usize> = result
+		.columns
+		.iter()
+		.enumerate()
+		.map(|(i, column)| {
+			cells
+				.iter()
+				.map(|row| row[i].chars().count())
+				.chain([column.chars().count()])
+				.max()
+				.unwrap_or_default()
+		})
+		.collect();
+
+	let header: Vec<String> = result
+		.columns
+		.iter()
+		.zip(&widths)
+		.map(|(column, width)| format!("{:<width$}", column))
+		.collect();
+	writeln!(out, "{}", header.join("  ").trim_end())?;
+	let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+	writeln!(out, "{}", rule.join("  "))?;
+
+	for (row, values) in cells.iter().zip(&result.rows) {
+		let line: Vec<String> = row
+			.iter()
+			.zip(values)
+			.zip(&widths)
+			.map(|((cell, value), width)| match is_numeric(value) {
+				true => format!("{:>width$}", cell),
+				false => format!("{:<width$}", cell),
+			})
+			.collect();
+		writeln!(out, "{}", line.join("  ").trim_end())?;
+	}
+	Ok(())
+}
+
+/// Writes the rows as CSV, with the column names as the header.
+pub fn write_csv(
+	out: &mut impl Write,
+	result: &QueryResult,
+	context: &DisplayContext,
+) -> io::Result<()> {
+	let mut writer = csv::Writer::from_writer(out);
+	writer.write_record(&result.columns)?;
+	for row in &result.rows {
+		writer.write_record(row.iter().map(|value| value.format(context)))?;
+	}
+	writer.flush()
+}
+
+fn amount_json(amount: &Amount, context: &DisplayContext) -> serde_json::Value {
+	json!({
+		"number": decimal_json(&context.round(amount.number(), amount.commodity())),
+		"currency": amount.commodity().to_string(),
+	})
+}
+
+/// Keeps the exact number, rather than going through a float.
+fn decimal_json(number: &impl ToString) -> serde_json::Value {
+	serde_json::Number::from_str(&number.to_string())
+		.map(serde_json::Value::Number)
+		.unwrap_or(serde_json::Value::Null)
+}
+
+fn position_json(position: &Position, context: &DisplayContext) -> serde_json::Value {
+	let cost = position.cost().map(|cost| {
+		json!({
+			"number": decimal_json(&context.round(cost.number(), cost.commodity())),
+			"currency": cost.commodity().to_string(),
+			"date": cost.date().to_string(),
+			"label": cost.label(),
+		})
+	});
+	json!({ "units": amount_json(position.units(), context), "cost": cost })
+}
+
+fn value_json(value: &Value, context: &DisplayContext) -> serde_json::Value {
+	match value {
+		Value::Null => serde_json::Value::Null,
+		Value::Bool(b) => json!(b),
+		Value::Number(n) => decimal_json(n),
+		Value::String(s) => json!(s),
+		Value::Date(date) => json!(date.to_string()),
+		Value::Amount(amount) => amount_json(amount, context),
+		Value::Position(position) => position_json(position, context),
+		Value::Inventory(inventory) => inventory
+			.iter()
+			.map(|position| position_json(position, context))
+			.collect(),
+		Value::Set(set) => json!(set),
+	}
+}
+
+/// Writes the result as a JSON object with its `columns` and `rows`. Amounts
+/// and positions are objects, and inventories a list of positions. Their
+/// numbers are rounded to the precision inferred for their commodity.
+pub fn write_json(
+	out: &mut impl Write,
+	result: &QueryResult,
+	context: &DisplayContext,
+) -> io::Result<()> {
+	let rows: Vec<Vec<serde_json::Value>> = result
+		.rows
+		.iter()
+		.map(|row| row.iter().map(|value| value_json(value, context)).collect())
+		.collect();
+	serde_json::to_writer_pretty(
+		&mut *out,
+		&json!({ "columns": result.columns, "rows": rows }),
+	)?;
+	writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use beancountr::loader::load_str;
+	use beancountr::query::{execute, parse_query};
+
+	use super::*;
+
+	fn render(format: Format) -> String {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			2025-01-01 * "Coffee, with milk"
+				Expenses:Food 4.50 USD
+				Assets:Cash
+			"#,
+		);
+		let query = parse_query("SELECT narration, account, position, number").unwrap();
+		let result = execute(&query, &ledger).unwrap();
+		let mut out = vec![];
+		write_result(&mut out, &result, format, &ledger.display_context).unwrap();
+		String::from_utf8(out).unwrap()
+	}
+
+	#[test]
+	fn test_write_text() {
+		assert_eq!(
+			render(Format::Text),
+			"narration          account        position   number\n\
+			 -----------------  -------------  ---------  ------\n\
+			 Coffee, with milk  Expenses:Food   4.50 USD    4.50\n\
+			 Coffee, with milk  Assets:Cash    -4.50 USD   -4.50\n"
+		);
+	}
+
+	#[test]
+	fn test_write_csv_and_json() {
+		assert_eq!(
+			render(Format::Csv),
+			"narration,account,position,number\n\
+			 \"Coffee, with milk\",Expenses:Food,4.50 USD,4.50\n\
+			 \"Coffee, with milk\",Assets:Cash,-4.50 USD,-4.50\n"
+		);
+
+		let json: serde_json::Value = serde_json::from_str(&render(Format::Json)).unwrap();
+		assert_eq!(json["columns"][3], "number");
+		assert_eq!(json["rows"][0][3].to_string(), "4.50");
+		assert_eq!(
+			json["rows"][1][2].to_string(),
+			r#"{"cost":null,"units":{"currency":"USD","number":-4.50}}"#
+		);
+	}
+
+	#[test]
+	fn test_write_json_values() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			2025-01-02 * "Buy" #invest
+				Assets:Brokerage 3 HOOL {30.00 USD, "first"}
+				Assets:Cash -90.00 USD
+			"#,
+		);
+		let query = parse_query(
+			"SELECT tags, payee, position, sum(position) WHERE account ~ 'Brokerage' GROUP BY tags, payee, position",
+		)
+		.unwrap();
+		let result = execute(&query, &ledger).unwrap();
+		let mut out = vec![];
+		write_json(&mut out, &result, &ledger.display_context).unwrap();
+		let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+		let row = &json["rows"][0];
+		assert_eq!(row[0].to_string(), r#"["invest"]"#);
+		assert_eq!(row[1], serde_json::Value::Null);
+		assert_eq!(
+			row[2].to_string(),
+			r#"{"cost":{"currency":"USD","date":"2025-01-02","label":"first","number":30.00},"units":{"currency":"HOOL","number":3}}"#
+		);
+		assert_eq!(row[3], serde_json::Value::Array(vec![row[2].clone()]));
+	}
+}
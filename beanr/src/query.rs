@@ -0,0 +1,199 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use beancountr::core::directive::DirectiveKind;
+use beancountr::loader::Ledger;
+use beancountr::parser::print_errors;
+use beancountr::query::{exec::table_columns, execute, parse_query, TABLES};
+use clap::ValueEnum;
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use crate::output::{write_result, Format};
+
+/// Runs a query and writes its result, or reports why it failed. Returns
+/// whether it succeeded.
+pub fn run_query(ledger: &Ledger, src: &str, format: Format, out: &mut impl Write) -> bool {
+	let query = match parse_query(src) {
+		Ok(query) => query,
+		Err(errors) => {
+			print_errors(Rc::from("query"), src, errors);
+			return false;
+		}
+	};
+	match execute(&query, ledger) {
+		Ok(result) => {
+			if let Err(e) = write_result(out, &result, format, &ledger.display_context) {
+				eprintln!("Could not write the result: {}", e);
+				return false;
+			}
+			true
+		}
+		Err(e) => {
+			eprintln!("{}", e);
+			false
+		}
+	}
+}
+
+/// Finds the query stored in the ledger by a `query` directive.
+pub fn stored_query<'a>(ledger: &'a Ledger, name: &str) -> Option<&'a str> {
+	ledger
+		.directives
+		.iter()
+		.find_map(|directive| match &directive.kind {
+			DirectiveKind::Query { name: n, query } if n == name => Some(query.as_str()),
+			_ => None,
+		})
+}
+
+const HELP: &str = "\
+Enter a query to run it, or one of:
+  .tables            List the tables and their columns
+  .explain <query>   Show how a query is parsed
+  .format <format>   Write results as text, csv or json
+  .help              Show this help
+  .exit              Leave the shell";
+
+fn history_path() -> Option<PathBuf> {
+	std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".beanr_history"))
+}
+
+/// Runs a line of the shell, a command or a query, writing its output. Errors
+/// are reported without stopping. Returns whether the shell goes on.
+fn run_line(
+	ledger: &Ledger,
+	line: &str,
+	format: &mut Format,
+	out: &mut impl Write,
+) -> io::Result<bool> {
+	let (command, rest) = line
+		.split_once(char::is_whitespace)
+		.map(|(command, rest)| (command, rest.trim()))
+		.unwrap_or((line, ""));
+	match command {
+		".exit" | ".quit" => return Ok(false),
+		".help" => writeln!(out, "{}", HELP)?,
+		".tables" => {
+			for table in TABLES {
+				let columns = table_columns(table).unwrap_or_default();
+				writeln!(out, "{}: {}", table, columns.join(", "))?;
+			}
+		}
+		".explain" => match parse_query(rest) {
+			Ok(query) => writeln!(out, "{:#?}", query)?,
+			Err(errors) => print_errors(Rc::from("query"), rest, errors),
+		},
+		".format" => match Format::from_str(rest, true) {
+			Ok(f) => *format = f,
+			Err(_) => eprintln!("Unknown format \"{}\", expected text, csv or json", rest),
+		},
+		command if command.starts_with('.') => {
+			eprintln!("Unknown command {}, type .help for help", command)
+		}
+		_ => {
+			run_query(ledger, line, *format, out);
+		}
+	}
+	Ok(true)
+}
+
+/// Runs an interactive shell reading queries, with the history kept in
+/// `~/.beanr_history`.
+pub fn repl(ledger: &Ledger, mut format: Format) -> rustyline::Result<()> {
+	let mut editor = DefaultEditor::new()?;
+	let history = history_path();
+	if let Some(path) = &history {
+		// There is no history the first time
+		let _ = editor.load_history(path);
+	}
+	println!(
+		"{} directives loaded, type .help for help",
+		ledger.directives.len()
+	);
+
+	loop {
+		let line = match editor.readline("beanr> ") {
+			Ok(line) => line,
+			Err(ReadlineError::Interrupted) => continue,
+			Err(ReadlineError::Eof) => break,
+			Err(e) => return Err(e),
+		};
+		let line = line.trim();
+		if line.is_empty() {
+			continue;
+		}
+		editor.add_history_entry(line)?;
+		if !run_line(ledger, line, &mut format, &mut io::stdout().lock())? {
+			break;
+		}
+	}
+
+	if let Some(path) = &history {
+		editor.save_history(path)?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use beancountr::loader::load_str;
+
+	use super::*;
+
+	fn ledger() -> Ledger {
+		load_str(
+			Rc::from("test"),
+			r#"
+			2025-01-01 query "food" "SELECT account, position WHERE account ~ 'Food'"
+			2025-01-02 * "Lunch"
+				Expenses:Food 12.50 USD
+				Assets:Cash
+			"#,
+		)
+	}
+
+	/// Runs lines in a shell, returning what they wrote and whether the shell
+	/// went on after the last one.
+	fn run_lines(ledger: &Ledger, lines: &[&str]) -> (String, bool) {
+		let mut format = Format::Text;
+		let mut out = vec![];
+		let mut goes_on = true;
+		for line in lines {
+			goes_on = run_line(ledger, line, &mut format, &mut out).unwrap();
+		}
+		(String::from_utf8(out).unwrap(), goes_on)
+	}
+
+	#[test]
+	fn test_run_line() {
+		let ledger = ledger();
+		let query = stored_query(&ledger, "food").unwrap();
+		assert_eq!(stored_query(&ledger, "rent"), None);
+
+		let (out, goes_on) = run_lines(&ledger, &[query]);
+		assert!(goes_on);
+		assert_eq!(
+			out,
+			"account        position\n\
+			 -------------  ---------\n\
+			 Expenses:Food  12.50 USD\n"
+		);
+
+		let (out, _) = run_lines(&ledger, &[".format csv", query, ".format yaml", query]);
+		assert_eq!(
+			out,
+			"account,position\nExpenses:Food,12.50 USD\n\
+			 account,position\nExpenses:Food,12.50 USD\n"
+		);
+
+		let (out, goes_on) = run_lines(&ledger, &[".tables", ".unknown", "SELECT nothing"]);
+		assert!(goes_on);
+		assert_eq!(out.lines().count(), TABLES.len());
+		assert!(out.starts_with(&format!("{}: ", TABLES[0])));
+
+		let (out, goes_on) = run_lines(&ledger, &[".exit"]);
+		assert!(!goes_on);
+		assert_eq!(out, "");
+	}
+}
@@ -0,0 +1,741 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use beancountr::core::{
+	budget::{budgets, BudgetPeriod},
+	display_context::DisplayContext,
+	error::Result,
+	types::Amount,
+};
+use beancountr::loader::Ledger;
+use beancountr::query::{value::Value, QueryResult};
+
+use beancountr::reports::{
+	balsheet::{balance_sheet, income_statement},
+	budget::{budget_report, BudgetRow},
+	gains::{open_lots, realized_gains, Disposal, OpenLot},
+	holdings::{holdings, Grouping, Holding},
+	journal::{journal, JournalFilter, JournalRow},
+	returns::{returns, Returns, ReturnsFilter},
+	trial::trial_balance,
+	Report, ReportSettings, Valuation,
+};
+use chrono::NaiveDate;
+use clap::{Args, Subcommand};
+use regex::Regex;
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+
+use crate::output::{write_result, Format};
+
+/// Arguments shared by the reports.
+#[derive(Args)]
+pub struct ReportArgs {
+	/// The ledger file
+	pub file: PathBuf,
+	/// Collapses accounts deeper than this into their parent
+	#[arg(long)]
+	depth: Option<usize>,
+	/// Values positions at the cost they were acquired at
+	#[arg(long, conflicts_with = "at_market")]
+	at_cost: bool,
+	/// Values positions at their last known price
+	#[arg(long)]
+	at_market: bool,
+	/// Converts amounts to this commodity, the first operating currency by
+	/// default
+	#[arg(long)]
+	currency: Option<String>,
+	/// How the report is written
+	#[arg(long, short, value_enum, default_value_t = Format::Text)]
+	pub format: Format,
+}
+
+impl ReportArgs {
+	pub fn settings(&self, ledger: &Ledger) -> Result<ReportSettings> {
+		let valuation = match (self.at_cost, self.at_market) {
+			(true, _) => Valuation::Cost,
+			(_, true) => Valuation::Market,
+			_ => Valuation::Units,
+		};
+		let currency = match &self.currency {
+			Some(currency) => Some(currency.parse()?),
+			None => ledger.options.operating_currency.first().cloned(),
+		};
+		Ok(ReportSettings {
+			valuation,
+			currency,
+			depth: self.depth,
+		})
+	}
+}
+
+#[derive(Subcommand)]
+pub enum ReportCommand {
+	/// Balances of the assets, liabilities and equity on a date
+	Balsheet {
+		#[command(flatten)]
+		args: ReportArgs,
+		/// The date of the balances, the last transaction by default
+		#[arg(long)]
+		to: Option<NaiveDate>,
+	},
+	/// Income and expenses over a period
+	Income {
+		#[command(flatten)]
+		args: ReportArgs,
+		/// The first day of the period
+		#[arg(long)]
+		from: Option<NaiveDate>,
+		/// The last day of the period
+		#[arg(long)]
+		to: Option<NaiveDate>,
+	},
+	/// Balances of every account over a period, adding up to zero
+	Trial {
+		#[command(flatten)]
+		args: ReportArgs,
+		/// The first day of the period
+		#[arg(long)]
+		from: Option<NaiveDate>,
+		/// The last day of the period
+		#[arg(long)]
+		to: Option<NaiveDate>,
+	},
+	/// Lots sold over a period, with their gains
+	Gains {
+		#[command(flatten)]
+		args: ReportArgs,
+		/// The first day of the period
+		#[arg(long)]
+		from: Option<NaiveDate>,
+		/// The last day of the period
+		#[arg(long)]
+		to: Option<NaiveDate>,
+	},
+	/// Lots held on a date, with their unrealized gains
+	Lots {
+		#[command(flatten)]
+		args: ReportArgs,
+		/// The date of the lots, the last transaction by default
+		#[arg(long)]
+		to: Option<NaiveDate>,
+	},
+	/// Positions held at cost, with their market value and allocation
+	Holdings {
+		#[command(flatten)]
+		args: ReportArgs,
+		/// The date of the holdings, the last transaction by default
+		#[arg(long)]
+		to: Option<NaiveDate>,
+		/// Groups the holdings by account instead of commodity
+		#[arg(long, conflicts_with = "by_meta")]
+		by_account: bool,
+		/// Groups the holdings by a metadata key of their commodity
+		#[arg(long, value_name = "KEY")]
+		by_meta: Option<String>,
+	},
+	/// Money-weighted and time-weighted returns of investment accounts
+	Returns {
+		#[command(flatten)]
+		args: ReportArgs,
+		/// A regular expression matching the investment accounts
+		accounts: String,
+		/// A regular expression matching the accounts that are part of the
+		/// returns, like dividends and fees
+		#[arg(long)]
+		internal: Option<String>,
+		/// The first day of the period
+		#[arg(long)]
+		from: Option<NaiveDate>,
+		/// The last day of the period
+		#[arg(long)]
+		to: Option<NaiveDate>,
+	},
+	/// Budgets against the actual changes of their accounts, period by period
+	Budget {
+		#[command(flatten)]
+		args: ReportArgs,
+		/// The periods compared: daily, weekly, monthly, quarterly or yearly
+		#[arg(long, default_value = "monthly")]
+		period: String,
+		/// The first day compared, the date of the first budget by default
+		#[arg(long)]
+		from: Option<NaiveDate>,
+		/// The last day compared, the last transaction by default
+		#[arg(long)]
+		to: Option<NaiveDate>,
+	},
+	/// Postings in date order, with a running balance
+	Journal {
+		#[command(flatten)]
+		args: ReportArgs,
+		/// A regular expression the accounts must match
+		account: Option<String>,
+		/// The first day of the period
+		#[arg(long)]
+		from: Option<NaiveDate>,
+		/// The last day of the period
+		#[arg(long)]
+		to: Option<NaiveDate>,
+		/// Only lists transactions with this tag
+		#[arg(long)]
+		tag: Option<String>,
+		/// Only lists transactions with this link
+		#[arg(long)]
+		link: Option<String>,
+	},
+}
+
+impl ReportCommand {
+	pub fn args(&self) -> &ReportArgs {
+		match self {
+			ReportCommand::Balsheet { args, .. }
+			| ReportCommand::Income { args, .. }
+			| ReportCommand::Trial { args, .. }
+			| ReportCommand::Gains { args, .. }
+			| ReportCommand::Lots { args, .. }
+			| ReportCommand::Holdings { args, .. }
+			| ReportCommand::Returns { args, .. }
+			| ReportCommand::Budget { args, .. }
+			| ReportCommand::Journal { args, .. } => args,
+		}
+	}
+
+	/// Builds the report, returning its title and its table.
+	pub fn run(&self, ledger: &Ledger) -> std::result::Result<(String, QueryResult), String> {
+		let settings = self.args().settings(ledger).map_err(|e| e.to_string())?;
+		let report = match self {
+			ReportCommand::Balsheet { to, .. } => balance_sheet(ledger, *to, &settings),
+			ReportCommand::Income { from, to, .. } => {
+				income_statement(ledger, *from, *to, &settings)
+			}
+			ReportCommand::Trial { from, to, .. } => trial_balance(ledger, *from, *to, &settings),
+			ReportCommand::Gains { from, to, .. } => {
+				let disposals = realized_gains(ledger, *from, *to);
+				return Ok(("Capital Gains".to_string(), gains_result(&disposals)));
+			}
+			ReportCommand::Lots { to, .. } => {
+				let lots = open_lots(ledger, *to);
+				return Ok(("Open Lots".to_string(), lots_result(&lots)));
+			}
+			ReportCommand::Holdings {
+				to,
+				by_account,
+				by_meta,
+				..
+			} => {
+				let grouping = match (by_account, by_meta) {
+					(true, _) => Grouping::Account,
+					(_, Some(key)) => Grouping::Metadata(key.clone()),
+					_ => Grouping::Commodity,
+				};
+				let holdings = holdings(ledger, *to, &grouping, &settings);
+				return Ok(("Holdings".to_string(), holdings_result(&holdings)));
+			}
+			ReportCommand::Returns {
+				accounts,
+				internal,
+				from,
+				to,
+				..
+			} => {
+				let currency = settings
+					.currency
+					.as_ref()
+					.ok_or("No currency to value the investments in, use --currency")?;
+				let filter = ReturnsFilter {
+					accounts: Regex::new(accounts).map_err(|e| e.to_string())?,
+					internal: internal
+						.as_deref()
+						.map(Regex::new)
+						.transpose()
+						.map_err(|e| e.to_string())?,
+					begin: *from,
+					end: *to,
+				};
+				let result = match returns(ledger, &filter, currency) {
+					Some(returns) => returns_result(&returns),
+					None => table(&RETURNS_COLUMNS, vec![]),
+				};
+				return Ok(("Returns".to_string(), result));
+			}
+			ReportCommand::Budget {
+				period, from, to, ..
+			} => {
+				// The budgets with an invalid period are reported by the loader
+				let (budgets, _) = budgets(&ledger.directives);
+				let period = period.parse::<BudgetPeriod>().map_err(|e| e.to_string())?;
+				let rows = budget_report(ledger, &budgets, period, *from, *to);
+				return Ok(("Budget".to_string(), budget_result(&rows)));
+			}
+			ReportCommand::Journal {
+				account,
+				from,
+				to,
+				tag,
+				link,
+				..
+			} => {
+				let account = match account {
+					Some(account) => Some(Regex::new(account).map_err(|e| e.to_string())?),
+					None => None,
+				};
+				let filter = JournalFilter {
+					account,
+					begin: *from,
+					end: *to,
+					tag: tag.clone(),
+					link: link.clone(),
+				};
+				let rows = journal(ledger, &filter, &settings);
+				return Ok(("Journal".to_string(), journal_result(&rows)));
+			}
+		};
+		Ok((report.title.clone(), report_result(&report)))
+	}
+}
+
+fn optional(value: Option<&String>) -> Value {
+	value.cloned().map(Value::String).unwrap_or(Value::Null)
+}
+
+fn table(columns: &[&str], rows: Vec<Vec<Value>>) -> QueryResult {
+	QueryResult {
+		columns: columns.iter().map(|column| column.to_string()).collect(),
+		rows,
+	}
+}
+
+fn amount(amount: Option<&Amount>) -> Value {
+	amount.cloned().map(Value::Amount).unwrap_or(Value::Null)
+}
+
+/// Turns the lots sold over a period into a table.
+pub fn gains_result(disposals: &[Disposal]) -> QueryResult {
+	let columns = [
+		"date", "account", "units", "acquired", "label", "cost", "proceeds", "gain", "term",
+	];
+	let rows = disposals
+		.iter()
+		.map(|disposal| {
+			vec![
+				Value::Date(disposal.date),
+				Value::String(disposal.account.to_string()),
+				Value::Amount(disposal.units.clone()),
+				Value::Date(disposal.acquired),
+				optional(disposal.label.as_ref()),
+				Value::Amount(disposal.cost_basis.clone()),
+				amount(disposal.proceeds.as_ref()),
+				amount(disposal.gain.as_ref()),
+				Value::String(disposal.term.to_string()),
+			]
+		})
+		.collect();
+	table(&columns, rows)
+}
+
+/// Turns the lots held on a date into a table.
+pub fn lots_result(lots: &[OpenLot]) -> QueryResult {
+	let columns = [
+		"account", "units", "acquired", "label", "cost", "value", "gain", "term",
+	];
+	let rows = lots
+		.iter()
+		.map(|lot| {
+			vec![
+				Value::String(lot.account.to_string()),
+				Value::Amount(lot.units.clone()),
+				Value::Date(lot.acquired),
+				optional(lot.label.as_ref()),
+				Value::Amount(lot.cost_basis.clone()),
+				amount(lot.market_value.as_ref()),
+				amount(lot.unrealized_gain.as_ref()),
+				Value::String(lot.term.to_string()),
+			]
+		})
+		.collect();
+	table(&columns, rows)
+}
+
+/// Turns holdings into a table.
+pub fn holdings_result(holdings: &[Holding]) -> QueryResult {
+	let columns = [
+		"group",
+		"units",
+		"book_value",
+		"market_value",
+		"gain",
+		"allocation",
+	];
+	let rows = holdings
+		.iter()
+		.map(|holding| {
+			vec![
+				Value::String(holding.group.clone()),
+				Value::Inventory(holding.units.clone()),
+				Value::Amount(holding.book_value.clone()),
+				Value::Amount(holding.market_value.clone()),
+				Value::Amount(holding.unrealized_gain.clone()),
+				Value::Number(holding.allocation),
+			]
+		})
+		.collect();
+	table(&columns, rows)
+}
+
+const RETURNS_COLUMNS: [&str; 8] = [
+	"from",
+	"to",
+	"begin_value",
+	"contributions",
+	"end_value",
+	"xirr",
+	"twr",
+	"annualized_twr",
+];
+
+fn percent(rate: Option<f64>) -> Value {
+	rate.and_then(|rate| Decimal::from_f64(rate * 100.0))
+		.map(|percent| Value::Number(percent.round_dp(2)))
+		.unwrap_or(Value::Null)
+}
+
+/// Turns returns into a table, with the rates in percent.
+pub fn returns_result(returns: &Returns) -> QueryResult {
+	let amount = |number| Value::Amount(Amount::new(number, returns.currency.clone()));
+	let row = vec![
+		Value::Date(returns.begin),
+		Value::Date(returns.end),
+		amount(returns.begin_value),
+		amount(returns.contributions()),
+		amount(returns.end_value),
+		percent(returns.xirr),
+		percent(returns.twr),
+		percent(returns.annualized_twr),
+	];
+	table(&RETURNS_COLUMNS, vec![row])
+}
+
+/// Turns budgets compared with the actual changes into a table.
+pub fn budget_result(rows: &[BudgetRow]) -> QueryResult {
+	let columns = ["from", "to", "account", "budget", "actual", "remaining"];
+	let rows = rows
+		.iter()
+		.map(|row| {
+			vec![
+				Value::Date(row.begin),
+				Value::Date(row.end),
+				Value::String(row.account.to_string()),
+				Value::Amount(row.budget.clone()),
+				Value::Amount(row.actual.clone()),
+				Value::Amount(row.remaining.clone()),
+			]
+		})
+		.collect();
+	table(&columns, rows)
+}
+
+/// Turns the rows of a journal into a table.
+pub fn journal_result(rows: &[JournalRow]) -> QueryResult {
+	let columns = [
+		"date",
+		"flag",
+		"payee",
+		"narration",
+		"account",
+		"change",
+		"balance",
+	];
+	let rows = rows
+		.iter()
+		.map(|row| {
+			vec![
+				Value::Date(row.date),
+				optional(row.flag.map(|flag| flag.to_string()).as_ref()),
+				optional(row.payee.as_ref()),
+				optional(row.narration.as_ref()),
+				Value::String(row.account.to_string()),
+				Value::Inventory(row.change.clone()),
+				Value::Inventory(row.balance.clone()),
+			]
+		})
+		.collect();
+	table(&columns, rows)
+}
+
+/// Turns a report into a table of accounts and balances, with a line for
+/// the total of each section.
+pub fn report_result(report: &Report) -> QueryResult {
+	let mut rows = vec![];
+	for section in &report.sections {
+		for row in &section.rows {
+			rows.push(vec![
+				Value::String(row.account.clone()),
+				Value::Inventory(row.balance.clone()),
+			]);
+		}
+		rows.push(vec![
+			Value::String(format!("Total {}", section.title)),
+			Value::Inventory(section.total.clone()),
+		]);
+	}
+	if let Some((title, total)) = &report.summary {
+		rows.push(vec![
+			Value::String(title.clone()),
+			Value::Inventory(total.clone()),
+		]);
+	}
+	table(&["account", "balance"], rows)
+}
+
+/// Writes the table of a report, with its title above it when written as
+/// text.
+pub fn write_report(
+	title: &str,
+	result: &QueryResult,
+	format: Format,
+	context: &DisplayContext,
+) -> io::Result<()> {
+	let mut out = io::stdout().lock();
+	if format == Format::Text {
+		writeln!(out, "{}\n", title)?;
+	}
+	write_result(&mut out, result, format, context)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use beancountr::loader::load_str;
+
+	use super::*;
+
+	fn render(result: &QueryResult, ledger: &Ledger) -> String {
+		let mut out = vec![];
+		write_result(&mut out, result, Format::Text, &ledger.display_context).unwrap();
+		String::from_utf8(out).unwrap()
+	}
+
+	fn args() -> ReportArgs {
+		ReportArgs {
+			file: PathBuf::from("test"),
+			depth: None,
+			at_cost: false,
+			at_market: false,
+			currency: None,
+			format: Format::Text,
+		}
+	}
+
+	const INVESTMENTS: &str = r#"
+		option "operating_currency" "USD"
+		2025-01-01 open Assets:Brokerage
+		2025-01-01 open Assets:Cash
+		2025-01-01 open Equity:Opening
+		2025-01-01 open Income:Gains
+		2025-01-01 * "Deposit"
+			Assets:Cash 1000.00 USD
+			Equity:Opening
+		2025-01-02 * "Buy"
+			Assets:Brokerage 10 HOOL {30.00 USD}
+			Assets:Cash -300.00 USD
+		2025-03-01 * "Sell"
+			Assets:Brokerage -4 HOOL {30.00 USD} @ 40.00 USD
+			Assets:Cash 160.00 USD
+			Income:Gains -40.00 USD
+		2025-03-02 price HOOL 45.00 USD
+	"#;
+
+	/// Runs a report on the investments, rendered as text.
+	fn run(command: ReportCommand) -> String {
+		let ledger = load_str(Rc::from("test"), INVESTMENTS);
+		assert!(ledger.errors.is_empty(), "{:?}", ledger.errors);
+		let (title, result) = command.run(&ledger).unwrap();
+		format!("{}\n{}", title, render(&result, &ledger))
+	}
+
+	#[test]
+	fn test_balance_sheet_table() {
+		let at_market = ReportArgs {
+			at_market: true,
+			..args()
+		};
+		let table = run(ReportCommand::Balsheet {
+			args: at_market,
+			to: None,
+		});
+		assert_eq!(
+			table,
+			"Balance Sheet\n\
+			 account                     balance\n\
+			 --------------------------  ------------\n\
+			 Assets\n\
+			 Assets:Brokerage              270.00 USD\n\
+			 Assets:Cash                   860.00 USD\n\
+			 Total Assets                 1130.00 USD\n\
+			 Total Liabilities\n\
+			 Equity\n\
+			 Equity:Earnings\n\
+			 Equity:Earnings:Current       -40.00 USD\n\
+			 Equity:Earnings:Unrealized    -90.00 USD\n\
+			 Equity:Opening              -1000.00 USD\n\
+			 Total Equity                -1130.00 USD\n\
+			 Net Worth                    1130.00 USD\n"
+		);
+
+		// Without a valuation, the shares stay at their cost
+		let table = run(ReportCommand::Balsheet {
+			args: args(),
+			to: None,
+		});
+		let brokerage = table
+			.lines()
+			.find(|line| line.starts_with("Assets:Brokerage"));
+		assert_eq!(
+			brokerage.map(|line| line.split_whitespace().collect::<Vec<_>>()),
+			Some(vec![
+				"Assets:Brokerage",
+				"6",
+				"HOOL",
+				"{30.00",
+				"USD,",
+				"2025-01-02}"
+			])
+		);
+	}
+
+	#[test]
+	fn test_gains_and_lots_tables() {
+		let gains = run(ReportCommand::Gains {
+			args: args(),
+			from: None,
+			to: None,
+		});
+		assert_eq!(
+			gains,
+			"Capital Gains\n\
+			 date        account           units   acquired    label  cost        proceeds    gain       term\n\
+			 ----------  ----------------  ------  ----------  -----  ----------  ----------  ---------  -----\n\
+			 2025-03-01  Assets:Brokerage  4 HOOL  2025-01-02         120.00 USD  160.00 USD  40.00 USD  short\n"
+		);
+		let lots = run(ReportCommand::Lots {
+			args: args(),
+			to: None,
+		});
+		assert_eq!(
+			lots,
+			"Open Lots\n\
+			 account           units   acquired    label  cost        value       gain       term\n\
+			 ----------------  ------  ----------  -----  ----------  ----------  ---------  -----\n\
+			 Assets:Brokerage  6 HOOL  2025-01-02         180.00 USD  270.00 USD  90.00 USD  short\n"
+		);
+	}
+
+	#[test]
+	fn test_holdings_table() {
+		let holdings = run(ReportCommand::Holdings {
+			args: args(),
+			to: None,
+			by_account: true,
+			by_meta: None,
+		});
+		assert_eq!(
+			holdings,
+			"Holdings\n\
+			 group             units   book_value  market_value  gain       allocation\n\
+			 ----------------  ------  ----------  ------------  ---------  ----------\n\
+			 Assets:Brokerage  6 HOOL  180.00 USD    270.00 USD  90.00 USD         100\n"
+		);
+	}
+
+	#[test]
+	fn test_returns_table() {
+		let returns = run(ReportCommand::Returns {
+			args: args(),
+			accounts: "Brokerage".to_string(),
+			internal: Some("Gains".to_string()),
+			from: None,
+			to: None,
+		});
+		// The sale books a gain of 40.00 USD on 300.00 USD, then the 6 HOOL
+		// left gain half their cost
+		assert_eq!(
+			returns,
+			"Returns\n\
+			 from        to          begin_value  contributions  end_value   xirr    twr  annualized_twr\n\
+			 ----------  ----------  -----------  -------------  ----------  ------  ---  --------------\n\
+			 2025-01-02  2025-03-02     0.00 USD     140.00 USD  270.00 USD  840.55   70         2564.76\n"
+		);
+	}
+
+	#[test]
+	fn test_journal_inferred_precision() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			2025-01-01 open Assets:Brokerage
+			2025-01-01 open Assets:Cash
+			2025-01-02 * "Buy"
+				Assets:Brokerage 3 HOOL {{100.00 USD}}
+				Assets:Cash -100.00 USD
+			2025-01-03 price HOOL 40.00 USD
+			"#,
+		);
+		assert!(ledger.errors.is_empty(), "{:?}", ledger.errors);
+
+		// The cost per unit is a third of the total, but rendered like the
+		// other USD amounts
+		let filter = JournalFilter {
+			account: Regex::new("Brokerage").ok(),
+			begin: None,
+			end: None,
+			tag: None,
+			link: None,
+		};
+		let rows = journal(&ledger, &filter, &ReportSettings::default());
+		assert_eq!(
+			render(&journal_result(&rows), &ledger),
+			"date        flag  payee  narration  account           change                          balance\n\
+			 ----------  ----  -----  ---------  ----------------  ------------------------------  ------------------------------\n\
+			 2025-01-02  *            Buy        Assets:Brokerage  3 HOOL {33.33 USD, 2025-01-02}  3 HOOL {33.33 USD, 2025-01-02}\n"
+		);
+	}
+
+	#[test]
+	fn test_budget_invalid_period() {
+		let ledger = load_str(
+			Rc::from("test"),
+			r#"
+			2025-01-01 open Assets:Cash
+			2025-01-01 open Expenses:Food
+			2025-01-01 open Expenses:Rent
+			2025-01-01 custom "budget" Expenses:Food "monthly" 500.00 USD
+			2025-01-01 custom "budget" Expenses:Rent "biweekly" 600.00 USD
+			2025-01-10 * "Groceries"
+				Expenses:Food 420.00 USD
+				Assets:Cash
+			"#,
+		);
+		let errors: Vec<String> = ledger.errors.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			errors,
+			vec!["test:6: Invalid custom \"budget\" directive: Invalid period: biweekly"]
+		);
+
+		let command = ReportCommand::Budget {
+			args: args(),
+			period: "monthly".to_string(),
+			from: None,
+			to: NaiveDate::from_ymd_opt(2025, 1, 31),
+		};
+		let (title, result) = command.run(&ledger).unwrap();
+		assert_eq!(title, "Budget");
+		assert_eq!(
+			render(&result, &ledger),
+			"from        to          account        budget      actual      remaining\n\
+			 ----------  ----------  -------------  ----------  ----------  ---------\n\
+			 2025-01-01  2025-01-31  Expenses:Food  500.00 USD  420.00 USD  80.00 USD\n"
+		);
+	}
+}